@@ -0,0 +1,73 @@
+//! Cost of a sampling decision, on one thread and with threads contending for the
+//! same sampler.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lipservice::{AdaptiveSampler, Config};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Distinct messages cycled through, so decisions hit a realistic number of patterns
+const MESSAGES: &[&str] = &[
+    "User 42 logged in",
+    "GET /api/orders 200",
+    "Cache miss for key session:8812",
+    "Processed 1523 records in 42ms",
+    "Connection pool exhausted, waiting",
+    "Payment 9912 authorized",
+];
+
+fn sampler() -> AdaptiveSampler {
+    let config = Config {
+        default_sampling_rate: 0.1,
+        ..Default::default()
+    };
+    AdaptiveSampler::new_standalone(config).unwrap()
+}
+
+fn should_sample_single_thread(c: &mut Criterion) {
+    let sampler = sampler();
+    let mut group = c.benchmark_group("should_sample");
+    group.bench_function("info", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            sampler.should_sample(black_box(MESSAGES[i % MESSAGES.len()]), "INFO")
+        })
+    });
+    group.bench_function("error", |b| {
+        b.iter(|| sampler.should_sample(black_box("Database connection failed"), "ERROR"))
+    });
+    group.bench_function("with_fields", |b| {
+        let fields = [("user_id", "42"), ("route", "/api/orders"), ("status", "200")];
+        b.iter(|| sampler.should_sample_with_fields(black_box("GET /api/orders 200"), "INFO", &fields))
+    });
+    group.finish();
+}
+
+fn should_sample_contended(c: &mut Criterion) {
+    let sampler = Arc::new(sampler());
+    let mut group = c.benchmark_group("should_sample_threads");
+    for threads in [2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| {
+            // Each iteration is one decision per thread
+            b.iter_custom(|iters| {
+                let started = Instant::now();
+                std::thread::scope(|scope| {
+                    for thread in 0..threads {
+                        let sampler = Arc::clone(&sampler);
+                        scope.spawn(move || {
+                            for i in 0..iters as usize {
+                                black_box(sampler.should_sample(MESSAGES[(i + thread) % MESSAGES.len()], "INFO"));
+                            }
+                        });
+                    }
+                });
+                started.elapsed()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, should_sample_single_thread, should_sample_contended);
+criterion_main!(benches);
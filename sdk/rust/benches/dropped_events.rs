@@ -0,0 +1,46 @@
+//! Cost of rejecting a tracing event on the callsite-sampling fast path.
+//!
+//! Dropped events are decided before any field is visited, their span's fields
+//! included, so the per-event cost should stay flat as the number of fields grows.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lipservice::posthog::{LipServiceLayer, LipServiceLogger};
+use lipservice::{AdaptiveSampler, Config};
+use std::sync::Arc;
+use tracing_subscriber::layer::SubscriberExt;
+
+fn dropped_events(c: &mut Criterion) {
+    let config = Config {
+        callsite_sampling: true,
+        inherit_span_fields: true,
+        default_sampling_rate: 0.0,
+        ..Default::default()
+    };
+    let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+    let logger = Arc::new(LipServiceLogger::new(sampler, None));
+    let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+    let mut group = c.benchmark_group("dropped_event");
+    tracing::subscriber::with_default(subscriber, || {
+        let _request = tracing::info_span!("request", request_id = "r-1", route = "/users").entered();
+        group.bench_function(BenchmarkId::from_parameter(0), |b| {
+            b.iter(|| tracing::info!("request served"))
+        });
+        group.bench_function(BenchmarkId::from_parameter(4), |b| {
+            b.iter(|| tracing::info!(user_id = 1, route = "/users", status = 200, latency_ms = 12.5, "request served"))
+        });
+        group.bench_function(BenchmarkId::from_parameter(16), |b| {
+            b.iter(|| {
+                tracing::info!(
+                    f1 = 1, f2 = 2, f3 = 3, f4 = 4, f5 = 5, f6 = 6, f7 = 7, f8 = 8,
+                    f9 = "a", f10 = "b", f11 = "c", f12 = "d", f13 = "e", f14 = "f", f15 = "g", f16 = "h",
+                    "request served"
+                )
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dropped_events);
+criterion_main!(benches);
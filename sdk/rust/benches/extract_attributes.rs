@@ -0,0 +1,47 @@
+//! Cost of turning a kept tracing event into export attributes, for varying field
+//! counts.
+//!
+//! Every event is kept and handed to a no-op sampled log callback, which runs the
+//! same attribute extraction as an export without any network I/O.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lipservice::posthog::{LipServiceLayer, LipServiceLogger};
+use lipservice::{AdaptiveSampler, Config};
+use std::sync::Arc;
+use tracing_subscriber::layer::SubscriberExt;
+
+fn extract_attributes(c: &mut Criterion) {
+    let config = Config {
+        default_sampling_rate: 1.0,
+        ..Default::default()
+    }
+    .with_on_sampled_log(|log| {
+        criterion::black_box(log);
+    });
+    let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+    let logger = Arc::new(LipServiceLogger::new(sampler, None));
+    let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+    let mut group = c.benchmark_group("extract_attributes");
+    tracing::subscriber::with_default(subscriber, || {
+        group.bench_function(BenchmarkId::from_parameter(0), |b| {
+            b.iter(|| tracing::info!("request served"))
+        });
+        group.bench_function(BenchmarkId::from_parameter(4), |b| {
+            b.iter(|| tracing::info!(user_id = 1, route = "/users", status = 200, latency_ms = 12.5, "request served"))
+        });
+        group.bench_function(BenchmarkId::from_parameter(16), |b| {
+            b.iter(|| {
+                tracing::info!(
+                    f1 = 1, f2 = 2, f3 = 3, f4 = 4, f5 = 5, f6 = 6, f7 = 7, f8 = 8,
+                    f9 = "a", f10 = "b", f11 = "c", f12 = "d", f13 = "e", f14 = "f", f15 = "g", f16 = "h",
+                    "request served"
+                )
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, extract_attributes);
+criterion_main!(benches);
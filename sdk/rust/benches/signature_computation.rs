@@ -0,0 +1,42 @@
+//! Cost of computing pattern signatures for representative log messages.
+//!
+//! Every sampled log is signed, so this is the floor of the per-log sampling cost.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lipservice::SignatureComputer;
+
+const MESSAGES: &[(&str, &str)] = &[
+    ("short", "cache warmed"),
+    ("numbers", "Processed 1523 records in 42ms for batch 9981"),
+    ("identifiers", "User 8f14e45f-ceea-467f-a0f2-0e3c2b9b7d11 logged in from 192.168.1.42"),
+    (
+        "long",
+        "GET /api/v1/users/4821/orders?page=3&limit=50 returned 200 in 12.5ms (cache miss, upstream=orders-7, \
+         request_id=req-5c1b7e, trace_id=4bf92f3577b34da6a3ce929d0e0e4736)",
+    ),
+];
+
+fn compute_signature(c: &mut Criterion) {
+    let computer = SignatureComputer::new();
+    let mut group = c.benchmark_group("compute_signature");
+    for (name, message) in MESSAGES {
+        group.bench_with_input(BenchmarkId::from_parameter(name), message, |b, message| {
+            b.iter(|| computer.compute_signature(black_box(message)))
+        });
+    }
+    group.finish();
+}
+
+fn compute_signature_normalized_paths(c: &mut Criterion) {
+    let computer = SignatureComputer::new().with_path_normalization().with_aggressive_numbers();
+    let mut group = c.benchmark_group("compute_signature_normalized");
+    for (name, message) in MESSAGES {
+        group.bench_with_input(BenchmarkId::from_parameter(name), message, |b, message| {
+            b.iter(|| computer.compute_signature(black_box(message)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compute_signature, compute_signature_normalized_paths);
+criterion_main!(benches);
@@ -0,0 +1,155 @@
+//! Request-scoped sampling for an axum service
+//!
+//! Every request runs inside a `request` span. With `sample_spans` LipService decides
+//! once per span whether to keep the request, so a request's logs are kept or dropped
+//! together; a request that logs an error keeps its later logs, and `span_close_logs`
+//! emits one `request closed` log with the duration and status once the response is
+//! ready.
+//!
+//! Run with `cargo run --example axum_middleware`, then `curl localhost:3000/orders/7`.
+
+use axum::extract::{Path, Request};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use lipservice::posthog::{LipServiceLayer, LipServiceLogger};
+use lipservice::{AdaptiveSampler, Config, PatternRule, PostHogExporter};
+use std::sync::Arc;
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// LipService settings keeping `request_rate` of the requests
+fn config(service_name: &str, request_rate: f64) -> Config {
+    Config {
+        sample_spans: true,
+        keep_errored_spans: true,
+        span_close_logs: true,
+        inherit_span_fields: true,
+        // Span sampling matches pattern rules against the span name
+        pattern_rules: vec![PatternRule::new("^request$", request_rate)],
+        ..Config::new(service_name.to_string(), "http://localhost:8000".to_string())
+    }
+}
+
+/// Run each request inside a span carrying its id, route and response status
+async fn request_span(request: Request, next: Next) -> Response {
+    let span = tracing::info_span!(
+        "request",
+        request_id = %uuid::Uuid::new_v4(),
+        method = %request.method(),
+        path = %request.uri().path(),
+        status = tracing::field::Empty,
+    );
+
+    async move {
+        let response = next.run(request).await;
+        tracing::Span::current().record("status", response.status().as_u16());
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+async fn get_order(Path(id): Path<u64>) -> String {
+    tracing::info!(order_id = id, "order loaded");
+    format!("order {}", id)
+}
+
+async fn checkout() -> StatusCode {
+    tracing::error!("payment provider unavailable");
+    tracing::info!("checkout aborted");
+    StatusCode::SERVICE_UNAVAILABLE
+}
+
+fn app() -> Router {
+    Router::new()
+        .route("/orders/:id", get(get_order))
+        .route("/checkout", get(checkout))
+        .layer(middleware::from_fn(request_span))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config {
+        posthog_api_key: std::env::var("POSTHOG_API_KEY").ok(),
+        posthog_team_id: std::env::var("POSTHOG_TEAM_ID").ok(),
+        ..config("axum-example", 0.25)
+    };
+    let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await?);
+    // Kept logs are only exported when PostHog credentials are set
+    let exporter = if config.posthog_api_key.is_some() {
+        Some(Arc::new(PostHogExporter::new(config).await?))
+    } else {
+        None
+    };
+    let (logger, _local_writer) = LipServiceLogger::new(sampler, exporter).with_non_blocking_writer(std::io::stdout());
+    let logger = Arc::new(logger);
+    tracing_subscriber::registry().with(LipServiceLayer::new(Arc::clone(&logger))).init();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    axum::serve(listener, app())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    // Flushes the sampled logs still queued for export
+    logger.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use lipservice::SampledLog;
+    use tower::ServiceExt;
+
+    /// Serve one request, returning the logs LipService kept for it
+    async fn kept_logs(request_rate: f64, uri: &str) -> Vec<SampledLog> {
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let config = Config {
+            default_sampling_rate: 1.0,
+            ..config("axum-example", request_rate)
+        }
+        .with_on_sampled_log(move |log: &SampledLog| sink.lock().push(log.clone()));
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app().oneshot(request).await.unwrap();
+        let logs = received.lock().clone();
+        logs
+    }
+
+    fn attribute(log: &SampledLog, key: &str) -> Option<String> {
+        log.attributes.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone())
+    }
+
+    #[tokio::test]
+    async fn test_kept_request_logs_share_the_request_span() {
+        let logs = kept_logs(1.0, "/orders/7").await;
+        let bodies: Vec<&str> = logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["order loaded", "request closed"]);
+
+        let request_id = attribute(&logs[0], "request_id").expect("order log carries the request id");
+        assert_eq!(attribute(&logs[1], "request_id"), Some(request_id));
+        assert_eq!(attribute(&logs[1], "status").as_deref(), Some("200"));
+        assert!(attribute(&logs[1], "span.duration_ms").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_requests_keep_only_errors_and_what_follows() {
+        assert!(kept_logs(0.0, "/orders/7").await.is_empty(), "a dropped request logs nothing");
+
+        let logs = kept_logs(0.0, "/checkout").await;
+        let bodies: Vec<&str> = logs.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["payment provider unavailable", "checkout aborted"]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Sampling audit module
+//!
+//! This module records every drop decision to an append-only file, so auditors can
+//! confirm which logs were dropped and that no error-level log ever was.
+
+use crate::sampler::DropReason;
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use crate::internal::internal_event;
+use tracing::Level;
+
+/// Append-only JSON lines file of drop decisions
+pub struct AuditLog {
+    file: Mutex<File>,
+    internal_level: Level,
+}
+
+/// A single audited drop decision
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    signature: &'a str,
+    severity: &'a str,
+    reason: DropReason,
+}
+
+impl AuditLog {
+    /// Open (or create) the audit file for appending
+    ///
+    /// Failures to write a record are logged at `internal_level`.
+    pub fn open(path: &Path, internal_level: Level) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            internal_level,
+        })
+    }
+
+    /// Append a drop decision
+    pub fn record(&self, signature: &str, severity: &str, reason: DropReason) {
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            signature,
+            severity,
+            reason,
+        };
+
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                internal_event!(self.internal_level, "Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // One write per record keeps lines intact across threads
+        if let Err(e) = self.file.lock().write_all(&line) {
+            internal_event!(self.internal_level, "Failed to write audit record: {}", e);
+        }
+    }
+}
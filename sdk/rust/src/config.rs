@@ -1,5 +1,13 @@
+use crate::error::LipServiceError;
+use crate::policy::SamplingPolicy;
+use crate::posthog::LogProcessorFactory;
+use crate::severity::level_from_severity;
+use crate::sink::{SampledLog, SampledLogCallback};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
+use tracing::Level;
 
 /// Configuration for LipService
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,32 +21,683 @@ pub struct Config {
     /// API key for LipService (optional)
     pub api_key: Option<String>,
     
+    /// Deployment identifier attached to exported logs as `deployment.id`
+    pub deployment_id: Option<String>,
+
+    /// Git commit attached to exported logs as `git.sha`
+    pub git_sha: Option<String>,
+
     /// PostHog API key for direct integration
     pub posthog_api_key: Option<String>,
     
-    /// PostHog team ID
+    /// PostHog team ID, also the team the LipService backend scopes this service under
     pub posthog_team_id: Option<String>,
     
-    /// PostHog endpoint (defaults to https://app.posthog.com)
+    /// PostHog endpoint (defaults to https://app.posthog.com; `unix:///path` exports OTLP/gRPC to a collector socket)
     pub posthog_endpoint: String,
     
+    /// How logs are sent to PostHog
+    pub posthog_export_mode: ExportMode,
+
+    /// OTLP instrumentation scope of logs without a tracing target; logs from tracing
+    /// events use their target
+    pub instrumentation_scope: String,
+
+    /// Additional services exported to their own PostHog teams, one exporter each
+    pub team_targets: Vec<TeamTarget>,
+
+    /// Log field naming the service a log belongs to, matched against team targets
+    pub team_routing_field: String,
+
     /// Batch size for exports
     pub batch_size: usize,
     
     /// Flush interval between batch exports
     pub flush_interval: Duration,
+
+    /// Fail initialization if the PostHog exporter can't be created, instead of retrying in the background
+    pub fail_fast_on_export_init: bool,
+
+    /// Logs buffered between the logging path and the export task (0 exports inline)
+    pub export_queue_size: usize,
+
+    /// OTLP batch pipelines exporting concurrently; records are spread across them
+    /// round-robin. The capture API already sends each log in its own request.
+    pub export_concurrency: usize,
+
+    /// Recently exported records kept for [`PostHogExporter::last_records`](crate::PostHogExporter::last_records)
+    #[cfg(feature = "debug-records")]
+    pub recent_records_size: usize,
     
     /// Maximum number of retry attempts
     pub max_retries: u32,
     
-    /// Request timeout
+    /// Timeout of control-plane requests: policy fetches and pattern reports
     pub timeout: Duration,
+
+    /// Timeout of log exports, both OTLP batches and capture requests; defaults to `timeout`
+    pub export_timeout: Option<Duration>,
     
     /// Policy refresh interval
     pub policy_refresh_interval: Duration,
     
+    /// Subscribe to server-sent policy updates, polling only while disconnected
+    ///
+    /// Streaming stops for the rest of the run if the backend answers 404 or 405.
+    pub policy_streaming: bool,
+
     /// Pattern report interval
     pub pattern_report_interval: Duration,
+
+    /// Send pattern reports on wall-clock multiples of the interval (e.g. the top of
+    /// each minute) instead of relative to startup, so reports line up across instances
+    pub align_pattern_reports: bool,
+
+    /// File that every drop decision is appended to for auditing
+    pub audit_log_path: Option<PathBuf>,
+
+    /// File pattern statistics are saved to on shutdown and restored from on startup
+    pub pattern_state_path: Option<PathBuf>,
+
+    /// JSON file mapping pattern signatures to sampling rates (`{"<signature>": 0.0}`),
+    /// taking precedence over every rule but the blocklist and the allowlist
+    pub fingerprint_rates_path: Option<PathBuf>,
+
+    /// How often the fingerprint rates file is checked for changes
+    pub fingerprint_reload_interval: Duration,
+
+    /// Log a final sampling summary on shutdown
+    pub shutdown_summary: bool,
+
+    /// Level of LipService's own warnings and errors (e.g. failed exports), which are
+    /// logged under the `lipservice::internal` target; each keeps its level when unset
+    pub internal_log_level: Option<String>,
+
+    /// Emit a TRACE event under the `lipservice::decisions` target for every sampling
+    /// decision; free while that target's TRACE level is disabled
+    pub trace_decisions: bool,
+
+    /// Wire format for pattern reports sent to the backend
+    pub pattern_report_format: ReportFormat,
+
+    /// Largest pattern report body; bigger reports are split into several requests
+    ///
+    /// Counts are cleared for the patterns of each request the backend accepted, and
+    /// carried over to the next report otherwise.
+    pub pattern_report_max_bytes: usize,
+
+    /// Fixed sampling policy used instead of fetching from the backend
+    pub static_policy: Option<SamplingPolicy>,
+
+    /// Sampling rate for patterns without a learned or policy rate
+    pub default_sampling_rate: f64,
+
+    /// Local sink that sampled logs are mirrored to
+    pub local_sink: LocalSink,
+
+    /// Callback invoked with every log that passes sampling (reservoir samples excluded)
+    #[serde(skip)]
+    pub on_sampled_log: Option<SampledLogCallback>,
+
+    /// Extra OTLP log processors attached to each export pipeline, next to the
+    /// PostHog batch exporter
+    #[serde(skip)]
+    pub log_processors: Vec<LogProcessorFactory>,
+
+    /// Keep every log for this long after startup before sampling kicks in
+    pub warmup_duration: Option<Duration>,
+
+    /// Lowest sampling rate any pattern is driven to, so no pattern loses visibility
+    pub min_pattern_rate: f64,
+
+    /// Highest sampling rate any pattern is driven to, so no pattern blows up cost
+    pub max_pattern_rate: f64,
+
+    /// Most patterns tracked at once; logs of newer patterns use their severity's rate
+    pub max_tracked_patterns: Option<usize>,
+
+    /// Local message pattern rules, consulted before backend policy rates
+    pub pattern_rules: Vec<PatternRule>,
+
+    /// Pattern signatures that are never sampled out
+    pub always_keep_signatures: Vec<String>,
+
+    /// Example messages whose patterns are never sampled out (signed at startup)
+    ///
+    /// Not supported with [`SignatureMode::Fields`], where signatures come from field names.
+    pub always_keep_messages: Vec<String>,
+
+    /// Pattern signatures of non-error logs that are always dropped
+    pub always_drop_signatures: Vec<String>,
+
+    /// Regexes matching non-error log messages that are always dropped
+    pub always_drop_patterns: Vec<String>,
+
+    /// Local debounce rules limiting matching patterns to one export per interval
+    pub debounce_rules: Vec<DebounceRule>,
+
+    /// Rules changing the severity of matching logs before any sampling decision
+    pub severity_remaps: Vec<SeverityRemap>,
+
+    /// Logs-per-minute targets for patterns or severities, consulted after pattern rules
+    ///
+    /// ERROR and above are always kept, so budgets cannot be scoped to them.
+    pub volume_budgets: Vec<VolumeBudget>,
+
+    /// Rate multipliers by message length, e.g. to favor long, detailed messages
+    pub length_adjustments: Vec<LengthAdjustment>,
+
+    /// Export a uniform sample of this many non-error logs per pattern each report
+    /// interval, instead of rate-based admission (requires PostHog export)
+    pub reservoir_size: Option<usize>,
+
+    /// Keep the first logs of each pattern's burst before rate sampling applies
+    pub burst_allowance: Option<BurstAllowance>,
+
+    /// Always keep the first occurrence of each pattern per report interval
+    pub keep_one_per_interval: bool,
+
+    /// Severities shed as export congestion rises, in order
+    pub load_shedding_ladder: Vec<LoadShedStep>,
+
+    /// Extra headers sent with every OTLP export request
+    pub extra_export_headers: HashMap<String, String>,
+
+    /// Allow extra headers to replace the PostHog auth headers
+    pub override_auth_headers: bool,
+
+    /// Namespace prepended to exported log attribute keys (e.g. "app" -> "app.user_id")
+    pub attribute_prefix: Option<String>,
+
+    /// Attribute keys, after prefixing, whose values are constant for the process
+    /// lifetime (e.g. "region"); OTLP exports move them onto the Resource. At most
+    /// 16 keys.
+    pub promote_attributes: Vec<String>,
+
+    /// Numeric fields exported as bucket labels (e.g. "1024-2048") instead of raw
+    /// values, which move to a `<key>.raw` attribute
+    pub attribute_buckets: Vec<AttributeBucket>,
+
+    /// Export the fields of enclosing spans with each log, event fields taking precedence
+    pub inherit_span_fields: bool,
+
+    /// Log each closed span with its name, fields and `span.duration_ms`
+    pub span_close_logs: bool,
+
+    /// How exported attributes sharing a key are resolved
+    pub duplicate_attribute_keys: DuplicateKeys,
+
+    /// Attach the emitting event's `code.filepath` and `code.lineno` to exported logs
+    pub capture_source_location: bool,
+
+    /// Attach the probability each log was kept with as `lipservice.sample_rate`, so
+    /// sampled counts can be scaled back up to estimate true volume
+    ///
+    /// Logs kept by rules that aren't a probability (keep-one, bursts, debouncing and
+    /// the sliding window and leaky bucket strategies) carry no rate.
+    pub attach_sampling_metadata: bool,
+
+    /// Export the severity label the log was given (e.g. CRITICAL) as its severity text
+    /// instead of the canonical name; the severity number is unaffected
+    pub preserve_severity_text: bool,
+
+    /// Export FATAL with OTLP severity number FATAL4 (24), keeping CRITICAL at FATAL (21)
+    pub split_fatal_severity: bool,
+
+    /// Log field used as the PostHog distinct_id to associate logs with a person
+    pub distinct_id_field: Option<String>,
+
+    /// Field whose value drives deterministic keep/drop decisions (e.g. "session_id")
+    pub consistent_sampling_field: Option<String>,
+
+    /// Algorithm turning sampling rates into keep/drop decisions
+    pub sampling_strategy: SamplingStrategy,
+
+    /// Two-stage sampling that keeps or drops whole sessions before rates apply
+    pub session_sampling: Option<SessionSampling>,
+
+    /// Numeric field scaling a log's sampling rate to `rate * (1 + priority)`, clamped to 1.0
+    pub priority_field: Option<String>,
+
+    /// Seed mixed into deterministic sampling hashes; share it across services
+    pub sampling_seed: u64,
+
+    /// Salt mixed into per-pattern hash decisions so services sample independently;
+    /// defaults to `service_name`. Decisions on `consistent_sampling_field` are unsalted.
+    pub sampling_salt: Option<String>,
+
+    /// Keep URL/path structure in signatures instead of collapsing whole URLs
+    pub normalize_url_paths: bool,
+
+    /// Collapse absolute Unix and Windows file paths in signatures, keeping extensions
+    pub normalize_file_paths: bool,
+
+    /// Which digit runs are collapsed to `N` in signatures
+    pub number_normalization: NumberNormalization,
+
+    /// Group messages embedding JSON by the JSON's keys rather than its values
+    pub normalize_json: bool,
+
+    /// Trimmed messages shorter than this many characters are signed without normalization
+    pub min_normalization_length: usize,
+
+    /// What happens to logs whose message is empty or whitespace only
+    pub empty_messages: EmptyMessages,
+
+    /// Sample tracing events on their static callsite before formatting them
+    pub callsite_sampling: bool,
+
+    /// Decide on span creation whether to keep the span's events
+    pub sample_spans: bool,
+
+    /// Keep every later event of a span once it records an error
+    pub keep_errored_spans: bool,
+
+    /// Keep every log inside a sampled OpenTelemetry trace, sampling only the rest
+    #[cfg(feature = "trace-correlation")]
+    pub follow_trace_sampling: bool,
+
+    /// What log pattern signatures are computed from
+    pub signature_mode: SignatureMode,
+
+    /// Fields whose normalized values are part of the signature (e.g. "status")
+    pub signature_fields: Vec<String>,
+
+    /// Event fields treated as the log body, checked in order (e.g. "msg", "log.message")
+    pub message_field_names: Vec<String>,
+}
+
+/// Deployment environment presets for [`Config::profile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Profile {
+    /// Local development: keep everything and print it
+    Dev,
+    /// Pre-production: moderate sampling with every pattern represented
+    Staging,
+    /// Production: aggressive sampling, export only to PostHog
+    Prod,
+}
+
+/// PostHog ingestion API used for export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportMode {
+    /// OTLP logs endpoint
+    #[default]
+    Otlp,
+    /// Capture (events) API, one event per log sent in batches of up to `batch_size`
+    Capture,
+}
+
+/// Algorithm deciding whether a log is kept at its pattern's sampling rate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SamplingStrategy {
+    /// Keep each log independently with probability equal to the rate
+    #[default]
+    Probabilistic,
+    /// Keep based on a seeded hash, so replaying the same logs gives the same decisions
+    DeterministicHash,
+    /// Keep exactly the rate's share of each pattern over a trailing window
+    SlidingWindow { window: Duration },
+    /// Sample at the rate, then keep bursts of up to `capacity` logs per pattern, draining at `drain_per_sec`
+    LeakyBucket { capacity: u32, drain_per_sec: f64 },
+}
+
+/// Local destination sampled logs are mirrored to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LocalSink {
+    /// No local output
+    #[default]
+    None,
+    /// One line per sampled log on stdout, written from a background thread when
+    /// used through [`crate::LipService`]
+    Stdout,
+}
+
+/// Wire format of pattern reports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReportFormat {
+    /// JSON, accepted by every backend version
+    #[default]
+    Json,
+    /// Compact MessagePack encoding for high-cardinality services
+    MessagePack,
+}
+
+impl ReportFormat {
+    /// HTTP Content-Type for this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+}
+
+/// Source of a log's pattern signature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureMode {
+    /// Normalized message text
+    #[default]
+    Message,
+    /// Sorted set of structured field names, falling back to the message when there are none
+    Fields,
+}
+
+/// How numbers are collapsed when computing signatures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberNormalization {
+    /// Only standalone numbers (`retry 3` -> `retry N`)
+    #[default]
+    Conservative,
+    /// Also digits inside identifiers (`worker5` -> `workerN`), for tighter grouping
+    Aggressive,
+}
+
+/// What happens to logs whose message is empty or whitespace only
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmptyMessages {
+    /// Sample them like any other log; they all share the empty message's signature
+    #[default]
+    Keep,
+    /// Drop them unless they are errors
+    Drop,
+    /// Sample them under a placeholder signature told apart by their field names
+    ///
+    /// The placeholder signature ignores `signature_mode` and `signature_fields`.
+    Placeholder,
+}
+
+/// How attributes sharing a key are resolved
+///
+/// Inherited span fields come before the event's own fields, so "last" is the
+/// event's value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateKeys {
+    /// Keep the last value
+    #[default]
+    LastWins,
+    /// Keep the first value
+    FirstWins,
+    /// Keep every value, renaming later ones `key_2`, `key_3`, ...
+    Suffix,
+}
+
+/// How a numeric attribute value is mapped to a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Bucketing {
+    /// Buckets between consecutive powers of two, e.g. 1500 -> "1024-2048"
+    PowersOfTwo,
+    /// Buckets of a fixed width, e.g. 1500 with width 500 -> "1500-2000"
+    Linear { width: f64 },
+}
+
+impl Bucketing {
+    /// Label of the bucket `value` falls in
+    pub fn label(&self, value: f64) -> String {
+        match *self {
+            Self::PowersOfTwo if value < 0.0 => "<0".to_string(),
+            Self::PowersOfTwo if value < 1.0 => "0-1".to_string(),
+            Self::PowersOfTwo => {
+                let lower = 2f64.powi(value.log2().floor() as i32);
+                format!("{}-{}", lower, lower * 2.0)
+            }
+            Self::Linear { width } => {
+                let lower = (value / width).floor() * width;
+                format!("{}-{}", lower, lower + width)
+            }
+        }
+    }
+}
+
+/// Exports a numeric field as a bucket label, see [`Config::attribute_buckets`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttributeBucket {
+    /// Field name, before any attribute prefix
+    pub field: String,
+
+    /// How the field's values are bucketed
+    pub bucketing: Bucketing,
+}
+
+impl AttributeBucket {
+    /// Create a new attribute bucket rule
+    pub fn new(field: String, bucketing: Bucketing) -> Self {
+        Self { field, bucketing }
+    }
+}
+
+/// Pins log messages matching a regex to a fixed sampling rate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternRule {
+    /// Regex matched against the raw log message
+    pub pattern: String,
+
+    /// Sampling rate applied to matching messages (0.0 - 1.0)
+    pub rate: f64,
+}
+
+/// Stops exporting a severity once export congestion reaches a threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadShedStep {
+    /// Congestion level (0.0 - 1.0) at which this step engages
+    pub congestion: f64,
+
+    /// Severity dropped while this step is engaged
+    pub severity: String,
+}
+
+impl LoadShedStep {
+    /// Create a new load shedding step
+    pub fn new(congestion: f64, severity: impl Into<String>) -> Self {
+        Self {
+            congestion,
+            severity: severity.into(),
+        }
+    }
+}
+
+/// Exports a matching pattern at most once per `min_interval`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebounceRule {
+    /// Regex matched against the raw log message
+    pub pattern: String,
+
+    /// Minimum time between exports of the same pattern
+    pub min_interval: Duration,
+}
+
+impl DebounceRule {
+    /// Create a new debounce rule
+    pub fn new(pattern: impl Into<String>, min_interval: Duration) -> Self {
+        Self {
+            pattern: pattern.into(),
+            min_interval,
+        }
+    }
+}
+
+/// Treats logs matching a regex as another severity (e.g. a benign ERROR as WARN)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeverityRemap {
+    /// Regex matched against the raw log message
+    pub pattern: String,
+
+    /// Severity the matching logs are sampled at
+    pub severity: String,
+}
+
+impl SeverityRemap {
+    /// Create a new severity remap
+    pub fn new(pattern: impl Into<String>, severity: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            severity: severity.into(),
+        }
+    }
+}
+
+/// Keeps the first `size` logs of each pattern at full fidelity, refilled once the
+/// pattern has been quiet for `quiet_period`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BurstAllowance {
+    /// Logs kept at the start of a burst
+    pub size: u32,
+
+    /// Silence after which a pattern's allowance is refilled
+    pub quiet_period: Duration,
+}
+
+impl BurstAllowance {
+    /// Create a new burst allowance
+    pub fn new(size: u32, quiet_period: Duration) -> Self {
+        Self { size, quiet_period }
+    }
+}
+
+/// Logs a volume budget applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetScope {
+    /// Logs whose raw message matches a regex
+    Pattern(String),
+    /// Logs of a severity
+    Severity(String),
+}
+
+/// Keeps roughly `logs_per_minute` of the matching logs
+///
+/// The budget is turned into a sampling rate from the volume observed over the
+/// previous minute; during the first minute the first `logs_per_minute` are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeBudget {
+    /// Logs the budget applies to
+    pub scope: BudgetScope,
+
+    /// Matching logs to keep per minute
+    pub logs_per_minute: u32,
+}
+
+impl VolumeBudget {
+    /// Budget logs whose message matches a regex
+    pub fn for_pattern(pattern: impl Into<String>, logs_per_minute: u32) -> Self {
+        Self {
+            scope: BudgetScope::Pattern(pattern.into()),
+            logs_per_minute,
+        }
+    }
+
+    /// Budget logs of a severity
+    pub fn for_severity(severity: impl Into<String>, logs_per_minute: u32) -> Self {
+        Self {
+            scope: BudgetScope::Severity(severity.into()),
+            logs_per_minute,
+        }
+    }
+}
+
+/// Multiplies the sampling rate of messages at least `min_bytes` long
+///
+/// A message uses the adjustment with the largest `min_bytes` at or below its length
+/// in bytes; its rate becomes `rate * multiplier`, clamped to 1.0. Messages shorter
+/// than every adjustment keep their rate. The multiplier composes with the priority field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LengthAdjustment {
+    /// Shortest message length, in bytes, the adjustment applies to
+    pub min_bytes: usize,
+
+    /// Factor applied to the rate; below 1.0 samples matching messages out
+    pub multiplier: f64,
+}
+
+impl LengthAdjustment {
+    /// Create a new length adjustment
+    pub fn new(min_bytes: usize, multiplier: f64) -> Self {
+        Self { min_bytes, multiplier }
+    }
+}
+
+/// Two-stage sampling: pick sessions first, then keep everything they log
+///
+/// A session is chosen by hashing its `field` value with the sampling seed, so every
+/// service sharing the seed picks the same sessions. Logs of chosen sessions are all
+/// kept; logs of other sessions are dropped unless they are errors. Logs without the
+/// field fall through to the usual per-pattern sampling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSampling {
+    /// Field carrying the session id (e.g. "session_id")
+    pub field: String,
+
+    /// Share of sessions kept, between 0.0 and 1.0
+    pub keep_rate: f64,
+}
+
+impl SessionSampling {
+    /// Create a new session sampling stage
+    pub fn new(field: String, keep_rate: f64) -> Self {
+        Self { field, keep_rate }
+    }
+}
+
+/// A service whose logs are exported to its own PostHog team
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeamTarget {
+    /// Service name matched against the routing field and reported as `service.name`
+    pub service_name: String,
+
+    /// PostHog team ID
+    pub team_id: String,
+
+    /// PostHog API key for the team
+    pub api_key: String,
+
+    /// Tracing target prefix (e.g. "billing::") also routed to this team
+    pub target_prefix: Option<String>,
+
+    /// PostHog endpoint for the team, defaulting to [`Config::posthog_endpoint`]
+    pub endpoint: Option<String>,
+}
+
+impl TeamTarget {
+    /// Create a new team target
+    pub fn new(service_name: impl Into<String>, team_id: impl Into<String>, api_key: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            team_id: team_id.into(),
+            api_key: api_key.into(),
+            target_prefix: None,
+            endpoint: None,
+        }
+    }
+
+    /// Also route logs whose tracing target starts with `prefix`
+    pub fn with_target_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.target_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Export to a PostHog endpoint other than the default one
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Whether a log with this routing field value and tracing target belongs to the team
+    pub fn matches(&self, service: Option<&str>, target: Option<&str>) -> bool {
+        service == Some(self.service_name.as_str())
+            || matches!(
+                (&self.target_prefix, target),
+                (Some(prefix), Some(target)) if target.starts_with(prefix.as_str())
+            )
+    }
+}
+
+impl PatternRule {
+    /// Create a new pattern rule
+    pub fn new(pattern: impl Into<String>, rate: f64) -> Self {
+        Self {
+            pattern: pattern.into(),
+            rate,
+        }
+    }
 }
 
 impl Default for Config {
@@ -47,15 +706,97 @@ impl Default for Config {
             service_name: "lipservice-service".to_string(),
             lipservice_url: "http://localhost:8000".to_string(),
             api_key: None,
+            deployment_id: None,
+            git_sha: None,
             posthog_api_key: None,
             posthog_team_id: None,
             posthog_endpoint: "https://app.posthog.com".to_string(),
+            posthog_export_mode: ExportMode::Otlp,
+            instrumentation_scope: "lipservice-rust".to_string(),
+            team_targets: Vec::new(),
+            team_routing_field: "service".to_string(),
             batch_size: 100,
             flush_interval: Duration::from_secs(5),
+            fail_fast_on_export_init: false,
+            export_queue_size: 2048,
+            export_concurrency: 1,
+            #[cfg(feature = "debug-records")]
+            recent_records_size: 100,
             max_retries: 3,
             timeout: Duration::from_secs(10),
+            export_timeout: None,
             policy_refresh_interval: Duration::from_secs(300), // 5 minutes
+            policy_streaming: false,
             pattern_report_interval: Duration::from_secs(600), // 10 minutes
+            align_pattern_reports: false,
+            audit_log_path: None,
+            pattern_state_path: None,
+            fingerprint_rates_path: None,
+            fingerprint_reload_interval: Duration::from_secs(10),
+            shutdown_summary: true,
+            internal_log_level: None,
+            trace_decisions: false,
+            pattern_report_format: ReportFormat::Json,
+            pattern_report_max_bytes: 1024 * 1024,
+            static_policy: None,
+            default_sampling_rate: 0.1,
+            local_sink: LocalSink::None,
+            on_sampled_log: None,
+            log_processors: Vec::new(),
+            warmup_duration: None,
+            min_pattern_rate: 0.0,
+            max_pattern_rate: 1.0,
+            max_tracked_patterns: None,
+            pattern_rules: Vec::new(),
+            always_keep_signatures: Vec::new(),
+            always_keep_messages: Vec::new(),
+            always_drop_signatures: Vec::new(),
+            always_drop_patterns: Vec::new(),
+            debounce_rules: Vec::new(),
+            severity_remaps: Vec::new(),
+            reservoir_size: None,
+            burst_allowance: None,
+            volume_budgets: Vec::new(),
+            length_adjustments: Vec::new(),
+            keep_one_per_interval: false,
+            load_shedding_ladder: vec![
+                LoadShedStep::new(0.5, "DEBUG"),
+                LoadShedStep::new(0.7, "TRACE"),
+                LoadShedStep::new(0.9, "INFO"),
+            ],
+            extra_export_headers: HashMap::new(),
+            override_auth_headers: false,
+            attribute_prefix: None,
+            promote_attributes: Vec::new(),
+            attribute_buckets: Vec::new(),
+            inherit_span_fields: false,
+            span_close_logs: false,
+            duplicate_attribute_keys: DuplicateKeys::LastWins,
+            capture_source_location: false,
+            attach_sampling_metadata: false,
+            preserve_severity_text: false,
+            split_fatal_severity: false,
+            distinct_id_field: None,
+            consistent_sampling_field: None,
+            sampling_strategy: SamplingStrategy::Probabilistic,
+            session_sampling: None,
+            priority_field: None,
+            sampling_seed: 0,
+            sampling_salt: None,
+            normalize_url_paths: false,
+            normalize_file_paths: false,
+            normalize_json: false,
+            min_normalization_length: 0,
+            number_normalization: NumberNormalization::Conservative,
+            empty_messages: EmptyMessages::Keep,
+            callsite_sampling: false,
+            sample_spans: false,
+            keep_errored_spans: false,
+            #[cfg(feature = "trace-correlation")]
+            follow_trace_sampling: false,
+            signature_mode: SignatureMode::Message,
+            signature_fields: Vec::new(),
+            message_field_names: vec!["message".to_string()],
         }
     }
 }
@@ -70,6 +811,65 @@ impl Config {
         }
     }
 
+    /// Start building a validated config from its required fields
+    pub fn builder(service_name: impl Into<String>, lipservice_url: impl Into<String>) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Self::new(service_name.into(), lipservice_url.into()),
+        }
+    }
+
+    /// Create a config preset for a deployment environment
+    ///
+    /// Every setting not listed keeps its `Default` value:
+    ///
+    /// | Setting                 | Dev      | Staging  | Prod     |
+    /// |-------------------------|----------|----------|----------|
+    /// | `default_sampling_rate` | 1.0      | 0.5      | 0.05     |
+    /// | `local_sink`            | `Stdout` | `Stdout` | `None`   |
+    /// | `keep_one_per_interval` | false    | true     | true     |
+    /// | `policy_refresh_interval` | 60s    | 300s     | 300s     |
+    ///
+    /// PostHog export is enabled in any profile once credentials are set with
+    /// [`Config::with_posthog`]; the Prod profile is intended to be used with it.
+    pub fn profile(profile: Profile) -> Self {
+        let defaults = Self::default();
+        match profile {
+            Profile::Dev => Self {
+                default_sampling_rate: 1.0,
+                local_sink: LocalSink::Stdout,
+                policy_refresh_interval: Duration::from_secs(60),
+                ..defaults
+            },
+            Profile::Staging => Self {
+                default_sampling_rate: 0.5,
+                local_sink: LocalSink::Stdout,
+                keep_one_per_interval: true,
+                ..defaults
+            },
+            Profile::Prod => Self {
+                default_sampling_rate: 0.05,
+                local_sink: LocalSink::None,
+                keep_one_per_interval: true,
+                ..defaults
+            },
+        }
+    }
+
+    /// Tag exported logs with a deployment identifier and git commit
+    pub fn with_build_info(mut self, deployment_id: Option<String>, git_sha: Option<String>) -> Self {
+        self.deployment_id = deployment_id;
+        self.git_sha = git_sha;
+        self
+    }
+
+    /// Fill unset build info from `DEPLOYMENT_ID` and `GIT_SHA` environment variables
+    pub fn with_build_info_from_env(mut self) -> Self {
+        let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        self.deployment_id = self.deployment_id.or_else(|| from_env("DEPLOYMENT_ID"));
+        self.git_sha = self.git_sha.or_else(|| from_env("GIT_SHA"));
+        self
+    }
+
     /// Set PostHog credentials
     pub fn with_posthog(mut self, api_key: String, team_id: String) -> Self {
         self.posthog_api_key = Some(api_key);
@@ -83,6 +883,138 @@ impl Config {
         self
     }
 
+    /// Export a service's logs to its own PostHog team
+    pub fn with_team_target(mut self, target: TeamTarget) -> Self {
+        self.team_targets.push(target);
+        self
+    }
+
+    /// Set the log field used to route logs to team targets
+    pub fn with_team_routing_field(mut self, field: String) -> Self {
+        self.team_routing_field = field;
+        self
+    }
+
+    /// Set the load shedding ladder
+    pub fn with_load_shedding_ladder(mut self, ladder: Vec<LoadShedStep>) -> Self {
+        self.load_shedding_ladder = ladder;
+        self
+    }
+
+    /// Add an extra header to OTLP export requests
+    pub fn with_export_header(mut self, name: String, value: String) -> Self {
+        self.extra_export_headers.insert(name, value);
+        self
+    }
+
+    /// Set attribute key prefix
+    pub fn with_attribute_prefix(mut self, prefix: String) -> Self {
+        self.attribute_prefix = Some(prefix);
+        self
+    }
+
+    /// Export a numeric field as the label of the bucket its value falls in
+    pub fn with_attribute_bucket(mut self, field: impl Into<String>, bucketing: Bucketing) -> Self {
+        self.attribute_buckets.push(AttributeBucket::new(field.into(), bucketing));
+        self
+    }
+
+    /// Export an attribute once on the Resource instead of on every record
+    pub fn with_promoted_attribute(mut self, key: String) -> Self {
+        self.promote_attributes.push(key);
+        self
+    }
+
+    /// Associate logs with PostHog persons through a field
+    pub fn with_distinct_id_field(mut self, field: String) -> Self {
+        self.distinct_id_field = Some(field);
+        self
+    }
+
+    /// Sample consistently on the value of a correlation field
+    pub fn with_consistent_sampling_field(mut self, field: String, seed: u64) -> Self {
+        self.consistent_sampling_field = Some(field);
+        self.sampling_seed = seed;
+        self
+    }
+
+    /// Override the per-service sampling salt
+    pub fn with_sampling_salt(mut self, salt: String) -> Self {
+        self.sampling_salt = Some(salt);
+        self
+    }
+
+    /// Salt of per-pattern hash decisions
+    pub fn sampling_salt(&self) -> &str {
+        self.sampling_salt.as_deref().unwrap_or(&self.service_name)
+    }
+
+    /// Scale sampling rates up by a numeric priority field
+    pub fn with_priority_field(mut self, field: String) -> Self {
+        self.priority_field = Some(field);
+        self
+    }
+
+    /// Set sampling strategy
+    pub fn with_sampling_strategy(mut self, strategy: SamplingStrategy) -> Self {
+        self.sampling_strategy = strategy;
+        self
+    }
+
+    /// Keep `keep_rate` of the sessions identified by `field`, and all logs of each kept session
+    pub fn with_session_sampling(mut self, field: String, keep_rate: f64) -> Self {
+        self.session_sampling = Some(SessionSampling::new(field, keep_rate));
+        self
+    }
+
+    /// Set signature mode
+    pub fn with_signature_mode(mut self, mode: SignatureMode) -> Self {
+        self.signature_mode = mode;
+        self
+    }
+
+    /// Set number normalization
+    pub fn with_number_normalization(mut self, mode: NumberNormalization) -> Self {
+        self.number_normalization = mode;
+        self
+    }
+
+    /// Set what happens to logs with an empty or whitespace-only message
+    pub fn with_empty_messages(mut self, handling: EmptyMessages) -> Self {
+        self.empty_messages = handling;
+        self
+    }
+
+    /// Set how attributes sharing a key are resolved
+    pub fn with_duplicate_attribute_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.duplicate_attribute_keys = policy;
+        self
+    }
+
+    /// Set the event fields treated as the log body
+    pub fn with_message_field_names(mut self, names: Vec<String>) -> Self {
+        self.message_field_names = names;
+        self
+    }
+
+    /// Add a field whose value contributes to signatures
+    pub fn with_signature_field(mut self, field: String) -> Self {
+        self.signature_fields.push(field);
+        self
+    }
+
+    /// Set PostHog export mode
+    pub fn with_posthog_export_mode(mut self, mode: ExportMode) -> Self {
+        self.posthog_export_mode = mode;
+        self
+    }
+
+    /// Set the instrumentation scope of logs without a tracing target
+    pub fn with_instrumentation_scope(mut self, scope: impl Into<String>) -> Self {
+        self.instrumentation_scope = scope.into();
+        self
+    }
+
     /// Set batch size
     pub fn with_batch_size(mut self, batch_size: usize) -> Self {
         self.batch_size = batch_size;
@@ -95,19 +1027,173 @@ impl Config {
         self
     }
 
+    /// Set export queue size
+    pub fn with_export_queue_size(mut self, size: usize) -> Self {
+        self.export_queue_size = size;
+        self
+    }
+
+    /// Set the number of concurrent OTLP export pipelines
+    pub fn with_export_concurrency(mut self, concurrency: usize) -> Self {
+        self.export_concurrency = concurrency;
+        self
+    }
+
+    /// Load per-signature sampling rates from a file, reloaded when it changes
+    pub fn with_fingerprint_rates(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fingerprint_rates_path = Some(path.into());
+        self
+    }
+
     /// Set timeout
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
     }
 
+    /// Set a timeout for log exports separate from control-plane requests
+    pub fn with_export_timeout(mut self, timeout: Duration) -> Self {
+        self.export_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of log exports
+    pub fn export_timeout(&self) -> Duration {
+        self.export_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Team the LipService backend scopes this service under, i.e. `posthog_team_id`
+    pub(crate) fn backend_team_id(&self) -> Result<u64, LipServiceError> {
+        self.posthog_team_id.as_deref().and_then(|id| id.trim().parse().ok()).ok_or_else(|| {
+            LipServiceError::InvalidConfig("posthog_team_id must be a numeric team id to reach the backend".to_string())
+        })
+    }
+
     /// Set max retries
     pub fn with_max_retries(mut self, max_retries: u32) -> Self {
         self.max_retries = max_retries;
         self
     }
 
+    /// Keep every log for a warmup period after startup
+    pub fn with_warmup(mut self, duration: Duration) -> Self {
+        self.warmup_duration = Some(duration);
+        self
+    }
+
+    /// Set pattern report wire format
+    pub fn with_pattern_report_format(mut self, format: ReportFormat) -> Self {
+        self.pattern_report_format = format;
+        self
+    }
+
+    /// Set the default sampling rate
+    pub fn with_default_sampling_rate(mut self, rate: f64) -> Self {
+        self.default_sampling_rate = rate;
+        self
+    }
+
+    /// Log LipService's own warnings and errors at this severity (e.g. "DEBUG")
+    pub fn with_internal_log_level(mut self, severity: impl Into<String>) -> Self {
+        self.internal_log_level = Some(severity.into());
+        self
+    }
+
+    /// Trace every sampling decision at TRACE level
+    pub fn with_decision_tracing(mut self) -> Self {
+        self.trace_decisions = true;
+        self
+    }
+
+    /// Level an internal diagnostic is logged at, `default` unless overridden
+    pub fn internal_level(&self, default: Level) -> Level {
+        self.internal_log_level.as_deref().map_or(default, level_from_severity)
+    }
+
+    /// Set the local sink
+    pub fn with_local_sink(mut self, sink: LocalSink) -> Self {
+        self.local_sink = sink;
+        self
+    }
+
+    /// Hand every sampled log to a callback
+    pub fn with_on_sampled_log(mut self, callback: impl Fn(&SampledLog) + Send + Sync + 'static) -> Self {
+        self.on_sampled_log = Some(SampledLogCallback::new(callback));
+        self
+    }
+
+    /// Attach a processor, created once per export pipeline, to the OTLP providers
+    pub fn with_log_processor<P>(mut self, make: impl Fn() -> P + Send + Sync + 'static) -> Self
+    where
+        P: opentelemetry_sdk::logs::LogProcessor + 'static,
+    {
+        self.log_processors.push(LogProcessorFactory::new(make));
+        self
+    }
+
+    /// Cap the number of tracked patterns
+    pub fn with_max_tracked_patterns(mut self, max: usize) -> Self {
+        self.max_tracked_patterns = Some(max);
+        self
+    }
+
+    /// Add a local pattern rule
+    pub fn with_pattern_rule(mut self, rule: PatternRule) -> Self {
+        self.pattern_rules.push(rule);
+        self
+    }
+
+    /// Never sample out logs with the same pattern as an example message
+    pub fn with_always_keep_message(mut self, message: String) -> Self {
+        self.always_keep_messages.push(message);
+        self
+    }
+
+    /// Always drop non-error logs matching a regex
+    pub fn with_always_drop_pattern(mut self, pattern: String) -> Self {
+        self.always_drop_patterns.push(pattern);
+        self
+    }
+
+    /// Add a local debounce rule
+    pub fn with_debounce_rule(mut self, rule: DebounceRule) -> Self {
+        self.debounce_rules.push(rule);
+        self
+    }
+
+    /// Sample logs matching a remap at its severity instead of their own
+    pub fn with_severity_remap(mut self, remap: SeverityRemap) -> Self {
+        self.severity_remaps.push(remap);
+        self
+    }
+
+    /// Keep roughly a number of matching logs per minute, whatever their volume
+    pub fn with_volume_budget(mut self, budget: VolumeBudget) -> Self {
+        self.volume_budgets.push(budget);
+        self
+    }
+
+    /// Scale the sampling rate of messages by their length
+    pub fn with_length_adjustment(mut self, adjustment: LengthAdjustment) -> Self {
+        self.length_adjustments.push(adjustment);
+        self
+    }
+
+    /// Keep the first logs of each pattern's burst
+    pub fn with_burst_allowance(mut self, allowance: BurstAllowance) -> Self {
+        self.burst_allowance = Some(allowance);
+        self
+    }
+
+    /// Keep at least one example of each pattern per report interval
+    pub fn with_keep_one_per_interval(mut self, enabled: bool) -> Self {
+        self.keep_one_per_interval = enabled;
+        self
+    }
+
     /// Validate the configuration
+    ///
+    /// [`ConfigBuilder::build`] returns these errors as [`LipServiceError::InvalidConfig`].
     pub fn validate(&self) -> Result<(), String> {
         if self.service_name.is_empty() {
             return Err("service_name cannot be empty".to_string());
@@ -120,15 +1206,251 @@ impl Config {
         if self.batch_size == 0 {
             return Err("batch_size must be greater than 0".to_string());
         }
+
+        if self.pattern_report_max_bytes == 0 {
+            return Err("pattern_report_max_bytes must be greater than 0".to_string());
+        }
+
+        if self.export_concurrency == 0 {
+            return Err("export_concurrency must be greater than 0".to_string());
+        }
         
         if self.max_retries > 10 {
             return Err("max_retries cannot exceed 10".to_string());
         }
+
+        if !(0.0..=1.0).contains(&self.default_sampling_rate) {
+            return Err("default_sampling_rate must be between 0.0 and 1.0".to_string());
+        }
+
+        if !(0.0..=1.0).contains(&self.min_pattern_rate)
+            || !(0.0..=1.0).contains(&self.max_pattern_rate)
+            || self.min_pattern_rate > self.max_pattern_rate
+        {
+            return Err("min_pattern_rate and max_pattern_rate must satisfy 0.0 <= min <= max <= 1.0".to_string());
+        }
+
+        if self.reservoir_size == Some(0) {
+            return Err("reservoir_size must be greater than 0".to_string());
+        }
+
+        if self.signature_mode == SignatureMode::Fields && !self.always_keep_messages.is_empty() {
+            return Err(
+                "always_keep_messages can't be matched in SignatureMode::Fields; use always_keep_signatures".to_string()
+            );
+        }
+
+        if let SamplingStrategy::LeakyBucket { capacity, drain_per_sec } = self.sampling_strategy {
+            if capacity == 0 || drain_per_sec <= 0.0 {
+                return Err("leaky bucket capacity and drain_per_sec must be greater than 0".to_string());
+            }
+        }
+
+        if self.fingerprint_rates_path.is_some() && self.fingerprint_reload_interval.is_zero() {
+            return Err("fingerprint_reload_interval must be greater than 0".to_string());
+        }
+
+        for bucket in &self.attribute_buckets {
+            if let Bucketing::Linear { width } = bucket.bucketing {
+                if width.is_nan() || width <= 0.0 {
+                    return Err(format!("attribute bucket '{}' width must be greater than 0", bucket.field));
+                }
+            }
+        }
+
+        if let Some(session) = &self.session_sampling {
+            if session.field.is_empty() || !(0.0..=1.0).contains(&session.keep_rate) {
+                return Err("session sampling needs a field and a keep_rate between 0.0 and 1.0".to_string());
+            }
+        }
+
+        for target in &self.team_targets {
+            if target.service_name.is_empty() || target.team_id.is_empty() || target.api_key.is_empty() {
+                return Err("team targets need a service_name, team_id and api_key".to_string());
+            }
+        }
+
+        for rule in &self.pattern_rules {
+            if !(0.0..=1.0).contains(&rule.rate) {
+                return Err(format!("pattern rule '{}' rate must be between 0.0 and 1.0", rule.pattern));
+            }
+        }
         
+        for remap in &self.severity_remaps {
+            let known = ["TRACE", "DEBUG", "INFO", "WARN", "WARNING", "ERROR", "CRITICAL", "FATAL"];
+            if !known.iter().any(|level| remap.severity.eq_ignore_ascii_case(level)) {
+                return Err(format!("severity remap '{}' has unknown severity {}", remap.pattern, remap.severity));
+            }
+        }
+
+        if let Some(severity) = &self.internal_log_level {
+            if severity.parse::<crate::severity::Severity>().is_err() {
+                return Err(format!("unknown internal log level {}", severity));
+            }
+        }
+
+        for budget in &self.volume_budgets {
+            if budget.logs_per_minute == 0 {
+                return Err("volume budgets must allow at least 1 log per minute".to_string());
+            }
+            if let BudgetScope::Severity(severity) = &budget.scope {
+                match severity.parse::<crate::severity::Severity>() {
+                    Err(_) => return Err(format!("volume budget has unknown severity {}", severity)),
+                    // Errors are kept before budgets are consulted, so such a budget never applies
+                    Ok(level) if level >= crate::severity::Severity::Error => {
+                        return Err(format!("volume budgets cannot limit {} logs", severity));
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        let valid_multiplier = |adjustment: &LengthAdjustment| {
+            adjustment.multiplier.is_finite() && adjustment.multiplier >= 0.0
+        };
+        if !self.length_adjustments.iter().all(valid_multiplier) {
+            return Err("length adjustment multipliers must be finite and non-negative".to_string());
+        }
+
+        for step in &self.load_shedding_ladder {
+            if matches!(step.severity.to_uppercase().as_str(), "WARN" | "WARNING" | "ERROR" | "CRITICAL" | "FATAL") {
+                return Err(format!("load shedding cannot drop {} logs", step.severity));
+            }
+        }
+
+        if self.promote_attributes.len() > 16 {
+            return Err("promote_attributes cannot exceed 16 keys".to_string());
+        }
+
         Ok(())
     }
 }
 
+/// Builder for a [`Config`] that is validated once built
+///
+/// Settings without a setter here can be applied with [`ConfigBuilder::configure`]
+/// and any `Config::with_*` method.
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Set the LipService API key
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.config.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Set PostHog credentials
+    pub fn posthog(mut self, api_key: impl Into<String>, team_id: impl Into<String>) -> Self {
+        self.config = self.config.with_posthog(api_key.into(), team_id.into());
+        self
+    }
+
+    /// Set custom PostHog endpoint
+    pub fn posthog_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.posthog_endpoint = endpoint.into();
+        self
+    }
+
+    /// Set how logs are sent to PostHog
+    pub fn export_mode(mut self, mode: ExportMode) -> Self {
+        self.config.posthog_export_mode = mode;
+        self
+    }
+
+    /// Set batch size
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.config.batch_size = batch_size;
+        self
+    }
+
+    /// Set flush interval
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.config.flush_interval = interval;
+        self
+    }
+
+    /// Set timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Set the timeout of log exports
+    pub fn export_timeout(mut self, timeout: Duration) -> Self {
+        self.config.export_timeout = Some(timeout);
+        self
+    }
+
+    /// Set max retries
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.config.max_retries = max_retries;
+        self
+    }
+
+    /// Set export queue size
+    pub fn export_queue_size(mut self, size: usize) -> Self {
+        self.config.export_queue_size = size;
+        self
+    }
+
+    /// Set the number of concurrent OTLP export pipelines
+    pub fn export_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.export_concurrency = concurrency;
+        self
+    }
+
+    /// Set the rate used until a policy is fetched
+    pub fn default_sampling_rate(mut self, rate: f64) -> Self {
+        self.config.default_sampling_rate = rate;
+        self
+    }
+
+    /// Set sampling strategy
+    pub fn sampling_strategy(mut self, strategy: SamplingStrategy) -> Self {
+        self.config.sampling_strategy = strategy;
+        self
+    }
+
+    /// Keep or drop whole sessions before per-pattern rates apply
+    pub fn session_sampling(mut self, field: impl Into<String>, keep_rate: f64) -> Self {
+        self.config.session_sampling = Some(SessionSampling::new(field.into(), keep_rate));
+        self
+    }
+
+    /// Set the local sink sampled logs are mirrored to
+    pub fn local_sink(mut self, sink: LocalSink) -> Self {
+        self.config.local_sink = sink;
+        self
+    }
+
+    /// Add a local pattern rule
+    pub fn pattern_rule(mut self, rule: PatternRule) -> Self {
+        self.config.pattern_rules.push(rule);
+        self
+    }
+
+    /// Add a volume budget
+    pub fn volume_budget(mut self, budget: VolumeBudget) -> Self {
+        self.config.volume_budgets.push(budget);
+        self
+    }
+
+    /// Apply any other setting, e.g. `|config| config.with_team_target(target)`
+    pub fn configure(mut self, configure: impl FnOnce(Config) -> Config) -> Self {
+        self.config = configure(self.config);
+        self
+    }
+
+    /// Validate and return the config
+    pub fn build(self) -> Result<Config, LipServiceError> {
+        self.config.validate().map_err(LipServiceError::InvalidConfig)?;
+        Ok(self.config)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,6 +1479,39 @@ mod tests {
         assert_eq!(config.timeout, Duration::from_secs(5));
     }
 
+    #[test]
+    fn test_profiles() {
+        let dev = Config::profile(Profile::Dev);
+        assert_eq!(dev.default_sampling_rate, 1.0);
+        assert_eq!(dev.local_sink, LocalSink::Stdout);
+
+        let prod = Config::profile(Profile::Prod).with_posthog("phc_test".to_string(), "12345".to_string());
+        assert_eq!(prod.default_sampling_rate, 0.05);
+        assert_eq!(prod.local_sink, LocalSink::None);
+        assert!(prod.keep_one_per_interval);
+        assert!(prod.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_builder_validates() {
+        let config = Config::builder("checkout", "http://localhost:8000")
+            .posthog("phc_test", "12345")
+            .batch_size(50)
+            .pattern_rule(PatternRule::new("^heartbeat", 0.0))
+            .configure(|config| config.with_export_header("X-Scope-OrgID".to_string(), "tenant-a".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(config.service_name, "checkout");
+        assert_eq!(config.posthog_team_id.as_deref(), Some("12345"));
+        assert_eq!(config.batch_size, 50);
+        assert_eq!(config.pattern_rules.len(), 1);
+        assert_eq!(config.extra_export_headers.len(), 1);
+
+        let err = Config::builder("checkout", "http://localhost:8000").batch_size(0).build().unwrap_err();
+        assert!(matches!(err, LipServiceError::InvalidConfig(ref reason) if reason.contains("batch_size")));
+        assert!(Config::builder("checkout", "http://localhost:8000").default_sampling_rate(1.5).build().is_err());
+    }
+
     #[test]
     fn test_config_validation() {
         let mut config = Config::default();
@@ -170,7 +1525,36 @@ mod tests {
         assert!(config.validate().is_err());
 
         config.batch_size = 100;
+        config.export_concurrency = 0;
+        assert!(config.validate().is_err());
+
+        config.export_concurrency = 1;
         config.max_retries = 11;
         assert!(config.validate().is_err());
+
+        config.max_retries = 3;
+        config.min_pattern_rate = 0.5;
+        config.max_pattern_rate = 0.2;
+        assert!(config.validate().is_err());
+
+        config.min_pattern_rate = 0.0;
+        config.max_pattern_rate = 1.0;
+        config.pattern_rules = vec![PatternRule::new("/health", 1.5)];
+        assert!(config.validate().is_err());
+
+        config.pattern_rules = Vec::new();
+        config.always_keep_messages = vec!["user signed in".to_string()];
+        assert!(config.validate().is_ok());
+        config.signature_mode = SignatureMode::Fields;
+        assert!(config.validate().is_err());
+
+        config.signature_mode = SignatureMode::default();
+        config.always_keep_messages = Vec::new();
+        config.volume_budgets = vec![VolumeBudget::for_severity("WARN", 600)];
+        assert!(config.validate().is_ok());
+        for severity in ["ERROR", "critical", "FATAL"] {
+            config.volume_budgets = vec![VolumeBudget::for_severity(severity, 600)];
+            assert!(config.validate().is_err(), "{} budget should be rejected", severity);
+        }
     }
 }
@@ -0,0 +1,49 @@
+//! Error types module
+//!
+//! This module provides the error types surfaced by LipService.
+
+use thiserror::Error;
+
+/// Errors returned by LipService components
+#[derive(Debug, Error)]
+pub enum LipServiceError {
+    /// No tokio runtime is available to drive background work
+    #[error("{component} requires a running tokio runtime; create it inside #[tokio::main] or Runtime::block_on")]
+    RuntimeRequired { component: &'static str },
+
+    /// The LipService backend rejected our credentials
+    #[error("LipService backend rejected the request with HTTP {status}; check Config::api_key")]
+    Unauthorized { status: u16 },
+
+    /// The LipService backend rejected the request for a non-auth reason
+    #[error("LipService backend rejected the request with HTTP {status}")]
+    Rejected { status: u16 },
+
+    /// The LipService backend is temporarily unavailable
+    #[error("LipService backend unavailable (HTTP {status})")]
+    Unavailable { status: u16 },
+
+    /// A `unix://` export endpoint points at a socket that doesn't exist
+    #[error("OTLP export socket {} does not exist; is the collector running?", path.display())]
+    SocketNotFound { path: std::path::PathBuf },
+
+    /// A configuration failed validation
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+impl LipServiceError {
+    /// Map a non-success backend HTTP status to an error
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => Self::Unauthorized { status },
+            400..=499 => Self::Rejected { status },
+            _ => Self::Unavailable { status },
+        }
+    }
+
+    /// Whether retrying the operation may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Unavailable { .. })
+    }
+}
@@ -0,0 +1,56 @@
+//! Health module
+//!
+//! This module summarizes the status of LipService's subsystems for readiness probes.
+
+use crate::sampler::{PolicyError, PolicySource};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// Consecutive export failures after which the exporter is reported as degraded
+pub const DEGRADED_AFTER_FAILURES: u64 = 3;
+
+/// Point-in-time status of every LipService subsystem
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Where the current sampling policy came from
+    pub policy_source: PolicySource,
+    /// Time since the policy was last successfully loaded
+    pub policy_age: Duration,
+    /// Why the latest policy refresh failed, if it did
+    pub last_policy_error: Option<PolicyError>,
+    /// PostHog exporter status, `None` when export isn't configured or not yet initialized
+    pub exporter: Option<ExporterHealth>,
+    /// Whether the policy refresh and pattern report tasks are still running
+    pub background_tasks_alive: bool,
+    /// Fraction of logs dropped over the sampler's lifetime
+    pub drop_rate: f64,
+}
+
+/// Status of the PostHog exporter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExporterHealth {
+    /// When an export last succeeded
+    pub last_success: Option<SystemTime>,
+    /// Failed exports since the last success
+    pub consecutive_failures: u64,
+    /// Whether exports have been failing repeatedly
+    pub degraded: bool,
+}
+
+impl ExporterHealth {
+    /// Build the exporter status from its export outcomes
+    pub fn new(last_success: Option<SystemTime>, consecutive_failures: u64) -> Self {
+        Self {
+            last_success,
+            consecutive_failures,
+            degraded: consecutive_failures >= DEGRADED_AFTER_FAILURES,
+        }
+    }
+}
+
+impl HealthStatus {
+    /// Whether every subsystem is working
+    pub fn is_healthy(&self) -> bool {
+        self.background_tasks_alive && !self.exporter.as_ref().is_some_and(|exporter| exporter.degraded)
+    }
+}
@@ -0,0 +1,25 @@
+//! Internal diagnostics module
+//!
+//! LipService's own logs are emitted under a dedicated tracing target, so they can be
+//! filtered independently of application logs and are never sampled by the layer.
+
+/// Tracing target of LipService's own logs
+pub const INTERNAL_TARGET: &str = "lipservice::internal";
+
+/// Tracing target of per-log sampling decisions, see `Config::trace_decisions`
+pub const DECISION_TARGET: &str = "lipservice::decisions";
+
+/// Emit an internal diagnostic at a level only known at runtime
+macro_rules! internal_event {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            tracing::Level::TRACE => tracing::trace!(target: $crate::internal::INTERNAL_TARGET, $($arg)+),
+            tracing::Level::DEBUG => tracing::debug!(target: $crate::internal::INTERNAL_TARGET, $($arg)+),
+            tracing::Level::INFO => tracing::info!(target: $crate::internal::INTERNAL_TARGET, $($arg)+),
+            tracing::Level::WARN => tracing::warn!(target: $crate::internal::INTERNAL_TARGET, $($arg)+),
+            tracing::Level::ERROR => tracing::error!(target: $crate::internal::INTERNAL_TARGET, $($arg)+),
+        }
+    };
+}
+
+pub(crate) use internal_event;
@@ -41,68 +41,176 @@
 //! }
 //! ```
 
+pub mod audit;
 pub mod config;
+pub mod error;
+pub mod health;
+pub mod internal;
+pub mod metrics;
+pub mod offline;
+pub mod policy;
 pub mod sampler;
+pub mod sanitize;
 pub mod posthog;
+pub mod queue;
+pub mod reservoir;
 pub mod signature;
+pub mod strategy;
+pub mod severity;
+pub mod sink;
 pub mod logger;
 
-pub use config::Config;
-pub use sampler::AdaptiveSampler;
-pub use posthog::PostHogExporter;
-pub use signature::SignatureComputer;
+pub use config::{
+    AttributeBucket, Bucketing, BudgetScope, BurstAllowance, Config, ConfigBuilder, DebounceRule, DuplicateKeys,
+    EmptyMessages, ExportMode, LengthAdjustment, LoadShedStep, LocalSink, NumberNormalization, PatternRule, Profile,
+    ReportFormat, SamplingStrategy, SessionSampling, SeverityRemap, SignatureMode, TeamTarget, VolumeBudget,
+};
+pub use error::LipServiceError;
+pub use health::{ExporterHealth, HealthStatus};
+pub use internal::{DECISION_TARGET, INTERNAL_TARGET};
+pub use metrics::{ExportStats, LatencySnapshot};
+pub use sampler::{
+    AdaptiveSampler, DropReason, KeptLog, PatternProjection, PatternReport, PatternStat, PolicyError, PolicySource,
+    SamplingSummary, SimulationReport,
+};
+pub use posthog::{LogProcessorFactory, PostHogExporter};
+#[cfg(feature = "debug-records")]
+pub use posthog::ExportedRecord;
+pub use signature::{
+    Md5Hasher, NormalizationStep, SignatureCacheStats, SignatureComputer, SignatureExplanation, SignatureHasher,
+};
+pub use severity::{level_from_severity, severity_from_level, LabeledSeverity, ParseSeverityError, Severity};
+pub use sink::{SampledLog, SampledLogCallback};
 pub use logger::LipServiceLogger;
 
 use anyhow::Result;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use tokio::sync::RwLock;
+use internal::internal_event;
+use tracing::{debug, info, Level};
 
 /// Main LipService client
 pub struct LipService {
     config: Config,
     sampler: Arc<AdaptiveSampler>,
-    posthog_exporter: Option<Arc<PostHogExporter>>,
     logger: Arc<LipServiceLogger>,
+    /// Flushes the local sink when dropped, i.e. on shutdown
+    _local_sink_guard: Option<tracing_appender::non_blocking::WorkerGuard>,
 }
 
+/// Longest wait between background attempts to create the PostHog exporter
+const EXPORTER_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
 impl LipService {
     /// Create a new LipService instance
+    ///
+    /// If the PostHog exporter can't be created, sampling still starts and the exporter
+    /// is retried in the background, unless `Config::fail_fast_on_export_init` is set.
     pub async fn new(config: Config) -> Result<Self> {
         // Initialize adaptive sampler
         let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await?);
 
         // Initialize PostHog exporter if configured
-        let posthog_exporter = if config.posthog_api_key.is_some() && config.posthog_team_id.is_some() {
-            Some(Arc::new(PostHogExporter::new(config.clone()).await?))
+        let posthog_configured = config.posthog_api_key.is_some() && config.posthog_team_id.is_some();
+        let mut deferred_error = None;
+        let posthog_exporter = if posthog_configured {
+            match PostHogExporter::new(config.clone()).await {
+                Ok(exporter) => Some(Arc::new(exporter)),
+                Err(e) if config.fail_fast_on_export_init => return Err(e),
+                Err(e) => {
+                    deferred_error = Some(e);
+                    None
+                }
+            }
         } else {
             None
         };
 
         // Initialize logger
-        let logger = Arc::new(LipServiceLogger::new(
+        let logger = LipServiceLogger::new(
             sampler.clone(),
-            posthog_exporter.clone(),
-        ));
+            posthog_exporter,
+        );
+        let (logger, local_sink_guard) = match config.local_sink {
+            LocalSink::Stdout => {
+                let (logger, guard) = logger.with_non_blocking_writer(std::io::stdout());
+                (logger, Some(guard))
+            }
+            LocalSink::None => (logger, None),
+        };
+        let logger = Arc::new(logger);
+
+        if let Some(e) = deferred_error {
+            internal_event!(
+                config.internal_level(Level::WARN),
+                "PostHog exporter unavailable, retrying in the background: {}",
+                e
+            );
+            Self::retry_exporter_init(config.clone(), Arc::downgrade(&logger));
+        }
 
         Ok(Self {
             config,
             sampler,
-            posthog_exporter,
             logger,
+            _local_sink_guard: local_sink_guard,
         })
     }
 
+    /// Keep trying to create the exporter, installing it on the logger once it works
+    fn retry_exporter_init(config: Config, logger: Weak<LipServiceLogger>) {
+        tokio::spawn(async move {
+            let mut delay = Duration::from_secs(1);
+            loop {
+                tokio::time::sleep(delay).await;
+                // Stop once the LipService instance is gone
+                let Some(logger) = logger.upgrade() else {
+                    return;
+                };
+
+                match PostHogExporter::new(config.clone()).await {
+                    Ok(exporter) => {
+                        info!(target: INTERNAL_TARGET, "PostHog exporter initialized after retrying");
+                        logger.install_exporter(Arc::new(exporter));
+                        return;
+                    }
+                    Err(e) => debug!(target: INTERNAL_TARGET, "PostHog exporter still unavailable: {}", e),
+                }
+                delay = (delay * 2).min(EXPORTER_RETRY_MAX_DELAY);
+            }
+        });
+    }
+
     /// Get the LipService logger
     pub fn logger(&self) -> Arc<LipServiceLogger> {
         self.logger.clone()
     }
 
+    /// Summarize the status of every subsystem, e.g. for readiness probes
+    pub fn health(&self) -> HealthStatus {
+        HealthStatus {
+            policy_source: self.sampler.policy_source(),
+            policy_age: self.sampler.policy_age(),
+            last_policy_error: self.sampler.last_policy_error(),
+            exporter: self.logger.exporter().map(|exporter| exporter.health()),
+            background_tasks_alive: self.sampler.background_tasks_alive(),
+            drop_rate: self.sampler.reduction_ratio(),
+        }
+    }
+
     /// Shutdown the LipService instance
     pub async fn shutdown(self) -> Result<()> {
-        if let Some(exporter) = self.posthog_exporter {
-            exporter.shutdown().await?;
+        if self.config.shutdown_summary {
+            self.sampler.log_final_summary();
+        }
+
+        if let Err(e) = self.sampler.save_pattern_state() {
+            internal_event!(self.config.internal_level(Level::WARN), "Failed to save pattern state: {}", e);
         }
-        Ok(())
+        self.sampler.stop_background_tasks();
+
+        self.logger.shutdown().await
     }
 }
 
@@ -146,4 +254,96 @@ mod tests {
         let ls = LipService::new(config).await;
         assert!(ls.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_new_survives_exporter_init_failure() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://unreachable posthog".to_string(),
+            ..Default::default()
+        };
+
+        let ls = LipService::new(config.clone()).await.unwrap();
+        assert!(ls.logger().exporter().is_none());
+
+        // Sampling keeps working without an exporter
+        ls.logger().error("Database connection failed");
+        assert_eq!(ls.sampler.counters().logs_sampled(), 1);
+
+        let config = Config {
+            fail_fast_on_export_init: true,
+            ..config
+        };
+        assert!(LipService::new(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_degraded_exporter() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            ..Default::default()
+        };
+
+        let ls = LipService::new(config).await.unwrap();
+        let health = ls.health();
+        assert!(health.background_tasks_alive);
+        assert!(!health.exporter.as_ref().unwrap().degraded);
+
+        for _ in 0..health::DEGRADED_AFTER_FAILURES {
+            ls.logger().error("Database connection failed");
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !ls.health().exporter.unwrap().degraded {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("exporter should be reported as degraded");
+
+        let health = ls.health();
+        assert!(!health.is_healthy());
+        assert_eq!(health.exporter.unwrap().last_success, None);
+        assert!(serde_json::to_value(ls.health()).is_ok());
+    }
+
+    /// Collects the `message` of every event
+    struct MessageCollector(Arc<parking_lot::Mutex<Vec<String>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for MessageCollector {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+            struct Visitor(String);
+            impl tracing::field::Visit for Visitor {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0 = format!("{:?}", value);
+                    }
+                }
+            }
+
+            let mut visitor = Visitor(String::new());
+            event.record(&mut visitor);
+            self.0.lock().push(visitor.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_emits_summary() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let messages = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(MessageCollector(messages.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let ls = LipService::new(Config::default()).await.unwrap();
+        ls.sampler.should_sample("request served", "INFO");
+        ls.shutdown().await.unwrap();
+
+        let summaries = messages.lock().iter().filter(|m| *m == "Final sampling summary").count();
+        assert_eq!(summaries, 1);
+    }
 }
@@ -2,15 +2,28 @@
 //! 
 //! This module provides the main logging interface for LipService.
 
-use crate::sampler::AdaptiveSampler;
-use crate::posthog::PostHogExporter;
-use std::sync::Arc;
-use tracing::{debug, error, info, warn};
+use crate::sampler::{AdaptiveSampler, Screened};
+use crate::posthog::{prefixed_key, sampled_log, ExportReservoir, PostHogExporter, SAMPLE_RATE_ATTRIBUTE};
+use crate::internal::internal_event;
+use crate::queue::QueuedLog;
+use crate::severity::level_from_severity;
+use crate::config::LocalSink;
+use anyhow::Result;
+use opentelemetry::KeyValue;
+use parking_lot::RwLock;
+use std::io::Write;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+use tracing::{debug, error, info, trace, warn, Level};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
 
 /// LipService logger that integrates with tracing
 pub struct LipServiceLogger {
     sampler: Arc<AdaptiveSampler>,
-    posthog_exporter: Option<Arc<PostHogExporter>>,
+    posthog_exporter: RwLock<Option<Arc<PostHogExporter>>>,
+    /// Started with the first exporter, as it flushes from a task
+    reservoir: OnceLock<Arc<ExportReservoir>>,
+    local_writer: Option<NonBlocking>,
 }
 
 impl LipServiceLogger {
@@ -19,10 +32,51 @@ impl LipServiceLogger {
         sampler: Arc<AdaptiveSampler>,
         posthog_exporter: Option<Arc<PostHogExporter>>,
     ) -> Self {
-        Self {
+        let logger = Self {
             sampler,
-            posthog_exporter,
+            posthog_exporter: RwLock::new(None),
+            reservoir: OnceLock::new(),
+            local_writer: None,
+        };
+        if let Some(exporter) = posthog_exporter {
+            logger.install_exporter(exporter);
         }
+        logger
+    }
+
+    /// Write sampled lines through a buffered writer on a dedicated thread, for
+    /// [`Config::local_sink`](crate::Config::local_sink), so logging never blocks on local IO
+    ///
+    /// The returned guard must be held for as long as logs are written: dropping it
+    /// flushes the lines still buffered and stops the writer thread.
+    pub fn with_non_blocking_writer(mut self, writer: impl Write + Send + 'static) -> (Self, WorkerGuard) {
+        let (writer, guard) = tracing_appender::non_blocking(writer);
+        self.local_writer = Some(writer);
+        (self, guard)
+    }
+
+    /// Start exporting through an exporter created after the logger
+    pub fn install_exporter(&self, exporter: Arc<PostHogExporter>) {
+        if let Some(size) = self.sampler.config().reservoir_size {
+            self.reservoir.get_or_init(|| ExportReservoir::start(Arc::clone(&self.sampler), size));
+        }
+        *self.posthog_exporter.write() = Some(exporter);
+    }
+
+    /// The PostHog exporter, if one is installed
+    pub fn exporter(&self) -> Option<Arc<PostHogExporter>> {
+        self.posthog_exporter.read().clone()
+    }
+
+    /// Export the logs held back in reservoir mode, then flush the exporter
+    pub async fn shutdown(&self) -> Result<()> {
+        if let Some(reservoir) = self.reservoir.get() {
+            reservoir.flush();
+        }
+        if let Some(exporter) = self.exporter() {
+            exporter.shutdown().await?;
+        }
+        Ok(())
     }
 
     /// Log an info message
@@ -50,30 +104,88 @@ impl LipServiceLogger {
         self.log("FATAL", message, &[]);
     }
 
-    /// Core logging method
-    fn log(&self, severity: &str, message: &str, _attributes: &[(&str, &str)]) {
-        // Check if we should sample this log
-        if !self.sampler.should_sample(message, severity) {
-            return;
+    /// Log a message with structured fields
+    ///
+    /// Fields take part in sampling (e.g. session sampling) and are exported as attributes.
+    pub fn log(&self, severity: &str, message: &str, fields: &[(&str, &str)]) {
+        let config = self.sampler.config();
+        // Shed load as the export queue backs up
+        let exporter = self.exporter();
+        if let Some(exporter) = &exporter {
+            self.sampler.set_congestion(exporter.congestion());
         }
 
+        // In reservoir mode logs passing the early stages wait for the window's representative sample
+        let kept = if let (Some(reservoir), Some(exporter)) = (self.reservoir.get(), &exporter) {
+            match self.sampler.screen_with_fields(message, severity, fields) {
+                Screened::Decided(kept) => kept,
+                Screened::Deferred(signature) => {
+                    let log = QueuedLog {
+                        scope: None,
+                        message: message.to_string(),
+                        severity: severity.into(),
+                        timestamp: SystemTime::now(),
+                        attributes: self.attributes(fields),
+                    };
+                    reservoir.offer(exporter, signature, log);
+                    return;
+                }
+            }
+        } else {
+            self.sampler.sample_with_fields(message, severity, fields)
+        };
+        let Some(kept) = kept else {
+            return;
+        };
+
         // Log to tracing
-        match severity {
-            "INFO" => info!("{}", message),
-            "WARN" => warn!("{}", message),
-            "ERROR" => error!("{}", message),
-            "DEBUG" => debug!("{}", message),
-            "FATAL" => error!("{}", message),
-            _ => info!("{}", message),
+        match level_from_severity(severity) {
+            Level::TRACE => trace!("{}", message),
+            Level::DEBUG => debug!("{}", message),
+            Level::INFO => info!("{}", message),
+            Level::WARN => warn!("{}", message),
+            Level::ERROR => error!("{}", message),
+        }
+
+        if let Some(writer) = &self.local_writer {
+            let line = format!("{} {} {}\n", chrono::Utc::now().to_rfc3339(), severity, message);
+            // Only fails once the worker is gone, i.e. after the guard was dropped
+            let _ = writer.clone().write_all(line.as_bytes());
+        } else if config.local_sink == LocalSink::Stdout {
+            println!("{} {} {}", chrono::Utc::now().to_rfc3339(), severity, message);
+        }
+
+        let timestamp = SystemTime::now();
+        let mut attributes = self.attributes(fields);
+        if let Some(rate) = kept.rate.filter(|_| config.attach_sampling_metadata) {
+            attributes.push(KeyValue::new(SAMPLE_RATE_ATTRIBUTE, rate));
+        }
+
+        if let Some(callback) = &config.on_sampled_log {
+            let signature = kept.signature.unwrap_or_else(|| self.sampler.signature_for(message, fields));
+            callback.call(&sampled_log(message, severity.into(), timestamp, &attributes, signature));
         }
 
         // Export to PostHog if configured
-        if let Some(exporter) = &self.posthog_exporter {
-            if let Err(e) = exporter.export_log(message, severity, std::time::SystemTime::now(), vec![]) {
-                error!("Failed to export log to PostHog: {}", e);
+        if let Some(exporter) = exporter {
+            if let Err(e) = exporter.export_log(message, severity, timestamp, attributes) {
+                internal_event!(
+                    self.sampler.config().internal_level(Level::ERROR),
+                    "Failed to export log to PostHog: {}",
+                    e
+                );
             }
         }
     }
+
+    /// Exported attributes of a log's fields
+    fn attributes(&self, fields: &[(&str, &str)]) -> Vec<KeyValue> {
+        let prefix = self.sampler.config().attribute_prefix.as_deref();
+        fields
+            .iter()
+            .map(|(key, value)| KeyValue::new(prefixed_key(key, prefix), value.to_string()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -107,4 +219,115 @@ mod tests {
         // Test should not panic
         assert!(true);
     }
+
+    #[tokio::test]
+    async fn test_reservoir_mode() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            reservoir_size: Some(2),
+            always_drop_patterns: vec!["^health check".to_string()],
+            always_keep_messages: vec!["payment failed".to_string()],
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config.clone()).unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = LipServiceLogger::new(Arc::clone(&sampler), Some(exporter));
+
+        for i in 0..5 {
+            logger.info(&format!("job {} done", i));
+            logger.info("health check ok");
+        }
+        assert_eq!(sampler.counters().logs_seen(), 10);
+        assert_eq!(sampler.counters().logs_sampled(), 0);
+
+        // Allowlisted logs skip the reservoir rather than being capped by it
+        for _ in 0..3 {
+            logger.warn("payment failed");
+        }
+        assert_eq!(sampler.counters().logs_sampled(), 3);
+
+        // Nothing listens on the endpoint, so only the reservoir flush matters here;
+        // blocklisted logs never entered the reservoir
+        let _ = logger.shutdown().await;
+        assert_eq!(sampler.counters().logs_sampled(), 5);
+    }
+
+    #[test]
+    fn test_reservoir_waits_for_an_exporter() {
+        let config = Config {
+            reservoir_size: Some(2),
+            ..Default::default()
+        };
+        // Outside a runtime, where starting the reservoir's flush task would panic
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+        let logger = LipServiceLogger::new(sampler, None);
+        logger.info("job done");
+        assert!(logger.reservoir.get().is_none());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<parking_lot::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_blocking_local_writer() {
+        let config = Config {
+            default_sampling_rate: 1.0,
+            always_drop_patterns: vec!["^heartbeat".to_string()],
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+        let buffer = SharedBuffer::default();
+        let (logger, guard) = LipServiceLogger::new(sampler, None).with_non_blocking_writer(buffer.clone());
+
+        logger.info("order placed");
+        logger.info("heartbeat ok");
+        logger.error("payment failed");
+        // Dropping the guard flushes the buffered lines
+        drop(guard);
+
+        let output = String::from_utf8_lossy(&buffer.0.lock()).into_owned();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" INFO order placed"));
+        assert!(lines[1].ends_with(" ERROR payment failed"));
+    }
+
+    #[tokio::test]
+    async fn test_sampled_logs_reach_the_callback_with_fields() {
+        let sampled = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&sampled);
+        let config = Config::default()
+            .with_session_sampling("session_id".to_string(), 0.0)
+            .with_on_sampled_log(move |log: &crate::SampledLog| sink.lock().push(log.clone()));
+        let config = Config {
+            attach_sampling_metadata: true,
+            ..config
+        };
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config).unwrap());
+        let logger = LipServiceLogger::new(Arc::clone(&sampler), None);
+
+        logger.log("INFO", "cart viewed", &[("session_id", "abc")]);
+        logger.log("ERROR", "payment failed", &[("session_id", "abc"), ("order", "42")]);
+
+        let sampled = sampled.lock();
+        assert_eq!(sampled.len(), 1, "the unsampled session's INFO log is dropped");
+        assert_eq!(sampled[0].body, "payment failed");
+        let fields = [("session_id", "abc"), ("order", "42")];
+        assert_eq!(sampled[0].signature, sampler.signature_for("payment failed", &fields));
+        assert!(sampled[0].attributes.contains(&("order".to_string(), "42".to_string())));
+        assert!(sampled[0].attributes.contains(&("lipservice.sample_rate".to_string(), "1".to_string())));
+    }
 }
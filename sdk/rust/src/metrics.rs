@@ -0,0 +1,174 @@
+//! Metrics module
+//!
+//! This module provides lightweight in-process metrics for LipService's own health.
+
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Upper bounds of the latency buckets, in milliseconds
+const LATENCY_BUCKETS_MS: [u64; 14] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, u64::MAX];
+
+/// Fixed-bucket latency histogram, cheap to record from any thread
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len()],
+    max_ms: AtomicU64,
+}
+
+/// Point-in-time latency percentiles
+///
+/// Percentiles are reported as the upper bound of the bucket they fall in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySnapshot {
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one observation
+    pub fn record(&self, latency: Duration) {
+        let ms = latency.as_millis().min(u64::MAX as u128) as u64;
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|bound| ms <= *bound).unwrap_or(LATENCY_BUCKETS_MS.len() - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.max_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    /// Compute percentiles over everything recorded so far
+    pub fn snapshot(&self) -> LatencySnapshot {
+        let counts: Vec<u64> = self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect();
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return LatencySnapshot::default();
+        }
+
+        let max = Duration::from_millis(self.max_ms.load(Ordering::Relaxed));
+        let percentile = |p: f64| {
+            let target = ((count as f64) * p).ceil().max(1.0) as u64;
+            let mut cumulative = 0;
+            for (bound, bucket_count) in LATENCY_BUCKETS_MS.iter().zip(&counts) {
+                cumulative += bucket_count;
+                if cumulative >= target {
+                    return Duration::from_millis(*bound).min(max);
+                }
+            }
+            max
+        };
+
+        LatencySnapshot {
+            count,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max,
+        }
+    }
+}
+
+/// Tracks whether recent export attempts succeeded
+#[derive(Debug, Default)]
+pub struct ExportOutcomes {
+    last_success: Mutex<Option<SystemTime>>,
+    consecutive_failures: AtomicU64,
+}
+
+impl ExportOutcomes {
+    /// Create a tracker with no attempts recorded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a successful export
+    pub fn record_success(&self) {
+        *self.last_success.lock() = Some(SystemTime::now());
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Record a failed export
+    pub fn record_failure(&self) {
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// When an export last succeeded
+    pub fn last_success(&self) -> Option<SystemTime> {
+        *self.last_success.lock()
+    }
+
+    /// Failed exports since the last success
+    pub fn consecutive_failures(&self) -> u64 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+}
+
+/// Estimated serialized bytes of exported records, per severity
+#[derive(Debug, Default)]
+pub struct ExportBytes {
+    by_severity: DashMap<&'static str, (u64, u64)>,
+}
+
+/// Point-in-time export volume, for attributing PostHog cost
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportStats {
+    pub records: u64,
+    pub bytes: u64,
+    pub bytes_by_severity: BTreeMap<String, u64>,
+}
+
+impl ExportBytes {
+    /// Create empty counters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one exported record of `bytes` serialized bytes
+    pub fn record(&self, severity: &'static str, bytes: usize) {
+        let mut entry = self.by_severity.entry(severity).or_default();
+        entry.0 += 1;
+        entry.1 += bytes as u64;
+    }
+
+    /// Totals over everything recorded so far
+    pub fn snapshot(&self) -> ExportStats {
+        let mut stats = ExportStats::default();
+        for entry in self.by_severity.iter() {
+            let (records, bytes) = *entry.value();
+            stats.records += records;
+            stats.bytes += bytes;
+            stats.bytes_by_severity.insert(entry.key().to_string(), bytes);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_snapshot() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.snapshot().count, 0);
+
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(snapshot.max, Duration::from_millis(100));
+        assert_eq!(snapshot.p50, Duration::from_millis(50));
+        assert!(snapshot.p50 <= snapshot.p95 && snapshot.p95 <= snapshot.p99);
+        assert_eq!(snapshot.p99, Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,83 @@
+//! Sampling policy module
+//!
+//! This module holds the sampling policy the sampler applies, and the wire format the
+//! LipService backend serves it in.
+
+use crate::severity::Severity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Sampling policy from LipService backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingPolicy {
+    pub policy_id: String,
+    pub sampling_rate: f64,
+    pub patterns: Vec<String>,
+    pub max_logs_per_minute: u32,
+    pub severity_rates: HashMap<String, f64>,
+    /// Sampling rates for specific pattern signatures
+    #[serde(default)]
+    pub pattern_rates: HashMap<String, f64>,
+}
+
+/// Active policy as served by the backend's `GET /api/v1/policies/{service}`
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct PolicyResponse {
+    pub global_rate: f64,
+    #[serde(default)]
+    pub severity_rates: HashMap<String, f64>,
+    #[serde(default)]
+    pub pattern_rates: HashMap<String, f64>,
+    pub generated_by: String,
+    pub version: u32,
+}
+
+impl From<PolicyResponse> for SamplingPolicy {
+    fn from(response: PolicyResponse) -> Self {
+        // The backend uses Python level names such as WARNING and CRITICAL
+        let severity_rates = response
+            .severity_rates
+            .into_iter()
+            .map(|(level, rate)| match level.parse::<Severity>() {
+                Ok(severity) => (severity.as_str().to_string(), rate),
+                Err(_) => (level, rate),
+            })
+            .collect();
+
+        Self {
+            policy_id: format!("{}-v{}", response.generated_by, response.version),
+            sampling_rate: response.global_rate,
+            patterns: Vec::new(),
+            max_logs_per_minute: 0,
+            severity_rates,
+            pattern_rates: response.pattern_rates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_policy_response_converts() {
+        let body = r#"{
+            "global_rate": 0.2,
+            "severity_rates": {"DEBUG": 0.05, "INFO": 0.2, "WARNING": 0.7, "ERROR": 1.0, "CRITICAL": 1.0},
+            "pattern_rates": {"abc123": 0.01},
+            "anomaly_boost": 2.0,
+            "reasoning": "Mostly health checks",
+            "generated_by": "llm",
+            "llm_model": "some-model",
+            "version": 3
+        }"#;
+
+        let policy = SamplingPolicy::from(serde_json::from_str::<PolicyResponse>(body).unwrap());
+
+        assert_eq!(policy.policy_id, "llm-v3");
+        assert_eq!(policy.sampling_rate, 0.2);
+        assert_eq!(policy.severity_rates.get("WARN"), Some(&0.7));
+        assert_eq!(policy.severity_rates.get("FATAL"), Some(&1.0));
+        assert_eq!(policy.pattern_rates.get("abc123"), Some(&0.01));
+    }
+}
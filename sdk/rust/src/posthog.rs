@@ -1,204 +1,3177 @@
-use crate::config::Config;
-use crate::sampler::AdaptiveSampler;
+use crate::config::{AttributeBucket, Config, DuplicateKeys, ExportMode, LocalSink, TeamTarget};
+use crate::error::LipServiceError;
+use crate::health::ExporterHealth;
+use crate::internal::{internal_event, DECISION_TARGET, INTERNAL_TARGET};
+use crate::metrics::{ExportBytes, ExportOutcomes, ExportStats, LatencyHistogram, LatencySnapshot};
+use crate::queue::{ExportQueue, Pending, QueuedLog};
+use crate::reservoir::PatternReservoir;
+use crate::sampler::{AdaptiveSampler, KeptLog, Screened};
+use crate::sanitize::sanitize_message;
+use crate::severity::{severity_from_level, LabeledSeverity, Severity};
+use crate::sink::SampledLog;
 use anyhow::Result;
-use opentelemetry::logs::{LogRecord, Severity};
+use async_trait::async_trait;
+use opentelemetry::logs::{AnyValue, LogRecord, LogResult};
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::logs::LoggerProvider;
+use opentelemetry_sdk::export::logs::{LogData, LogExporter};
+use opentelemetry_sdk::logs::{LogProcessor, LoggerProvider};
 use opentelemetry_sdk::Resource;
+use parking_lot::RwLock;
+use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(feature = "debug-records")]
+use std::collections::VecDeque;
+use std::io::Write;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tracing::{debug, error, info, warn};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tracing::{debug, Level};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
 
 /// PostHog OTLP exporter for high-performance log export
 pub struct PostHogExporter {
     config: Config,
-    logger_provider: LoggerProvider,
-    _shutdown: opentelemetry_sdk::logs::Shutdown,
+    /// One provider per export pipeline, see [`Config::export_concurrency`]
+    logger_providers: Vec<LoggerProvider>,
+    next_provider: Arc<AtomicUsize>,
+    capture: Option<Arc<CaptureBatcher>>,
+    latency: Arc<LatencyHistogram>,
+    outcomes: Arc<ExportOutcomes>,
+    bytes: Arc<ExportBytes>,
+    rate_limit: Arc<RateLimitPause>,
+    promoted: Arc<PromotedAttributes>,
+    queue: Option<ExportQueue>,
+    #[cfg(feature = "debug-records")]
+    recent: Arc<RecentRecords>,
+    _shutdown: Vec<opentelemetry_sdk::logs::Shutdown>,
 }
 
+/// A record the exporter sent to PostHog, kept for debugging
+#[cfg(feature = "debug-records")]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExportedRecord {
+    pub message: String,
+    pub severity: String,
+    pub timestamp: SystemTime,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Ring buffer of the most recently exported records
+#[cfg(feature = "debug-records")]
+#[derive(Debug)]
+struct RecentRecords {
+    capacity: usize,
+    records: parking_lot::Mutex<VecDeque<ExportedRecord>>,
+}
+
+#[cfg(feature = "debug-records")]
+impl RecentRecords {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: parking_lot::Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn push(&self, record: ExportedRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    fn last(&self, n: usize) -> Vec<ExportedRecord> {
+        let records = self.records.lock();
+        records.iter().skip(records.len().saturating_sub(n)).cloned().collect()
+    }
+}
+
+/// Pause applied after a 429 without a usable Retry-After header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Export pause requested by PostHog through a 429 response
+#[derive(Debug, Default)]
+struct RateLimitPause {
+    paused_until: parking_lot::Mutex<Option<Instant>>,
+    deferred: AtomicU64,
+}
+
+impl RateLimitPause {
+    /// Hold exports for `duration`, extending any pause already in effect
+    fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut paused_until = self.paused_until.lock();
+        if paused_until.map_or(true, |current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Whether exports are currently held by a 429
+    fn is_paused(&self) -> bool {
+        self.paused_until.lock().map_or(false, |until| until > Instant::now())
+    }
+
+    /// Wait out the current pause, counting the logs as deferred
+    async fn wait(&self, logs: usize) {
+        let Some(until) = *self.paused_until.lock() else {
+            return;
+        };
+        if until > Instant::now() {
+            self.deferred.fetch_add(logs as u64, Ordering::Relaxed);
+            tokio::time::sleep_until(until.into()).await;
+        }
+    }
+}
+
+/// Parse a Retry-After header given as delay seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// OTLP/HTTP client pausing the exporter when PostHog answers 429
+#[derive(Debug)]
+struct RateLimitedClient {
+    inner: reqwest::Client,
+    rate_limit: Arc<RateLimitPause>,
+}
+
+#[async_trait]
+impl opentelemetry_http::HttpClient for RateLimitedClient {
+    async fn send(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> std::result::Result<http::Response<bytes::Bytes>, opentelemetry_http::HttpError> {
+        let response = self.inner.execute(request.try_into()?).await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after)
+                .unwrap_or(DEFAULT_RETRY_AFTER);
+            self.rate_limit.pause(retry_after);
+        }
+        let response = response.error_for_status()?;
+        let status = response.status();
+        Ok(http::Response::builder().status(status).body(response.bytes().await?)?)
+    }
+}
+
+/// Sends capture API events to PostHog's batch endpoint from a dedicated task
+///
+/// Events are handed over without blocking, so logs can be captured from any thread.
+/// Whatever queues up while a request is in flight goes out in the next batch.
+struct CaptureBatcher {
+    events: mpsc::Sender<serde_json::Value>,
+    /// Events handed over but not yet sent, including the batch in flight
+    pending: Arc<Pending>,
+    dropped: AtomicU64,
+}
+
+impl CaptureBatcher {
+    /// Start the sending task on the current tokio runtime
+    fn spawn(
+        config: &Config,
+        client: reqwest::Client,
+        latency: &Arc<LatencyHistogram>,
+        outcomes: &Arc<ExportOutcomes>,
+        rate_limit: &Arc<RateLimitPause>,
+    ) -> Self {
+        let batch_size = config.batch_size.max(1);
+        let (events, mut receiver) = mpsc::channel(config.export_queue_size.max(batch_size));
+        let pending = Arc::new(Pending::default());
+
+        let sent = Arc::clone(&pending);
+        let request = CaptureRequest {
+            client,
+            url: format!("{}/batch/", config.posthog_endpoint.trim_end_matches('/')),
+            api_key: config.posthog_api_key.clone().unwrap_or_default(),
+            max_retries: config.max_retries,
+            latency: Arc::clone(latency),
+            outcomes: Arc::clone(outcomes),
+            rate_limit: Arc::clone(rate_limit),
+            internal_level: config.internal_level(Level::WARN),
+        };
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                let mut batch = vec![event];
+                while batch.len() < batch_size {
+                    match receiver.try_recv() {
+                        Ok(event) => batch.push(event),
+                        Err(_) => break,
+                    }
+                }
+                let count = batch.len();
+                request.send(batch).await;
+                sent.done(count);
+            }
+        });
+
+        Self {
+            events,
+            pending,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Hand an event to the sending task, dropping it if the buffer is full
+    fn push(&self, event: serde_json::Value) {
+        self.pending.add(1);
+        if self.events.try_send(event).is_err() {
+            self.pending.done(1);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Events dropped because the buffer was full or the sending task was gone
+    fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until every event handed over so far has been sent
+    async fn drained(&self) {
+        self.pending.idle().await;
+    }
+}
+
+/// Everything needed to send a batch of capture API events
+struct CaptureRequest {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    max_retries: u32,
+    latency: Arc<LatencyHistogram>,
+    outcomes: Arc<ExportOutcomes>,
+    rate_limit: Arc<RateLimitPause>,
+    internal_level: Level,
+}
+
+impl CaptureRequest {
+    /// Send a batch of events, honoring PostHog's rate limit
+    async fn send(&self, batch: Vec<serde_json::Value>) {
+        let count = batch.len();
+        let body = serde_json::json!({ "api_key": self.api_key, "batch": batch });
+        let mut attempt = 0;
+        loop {
+            self.rate_limit.wait(count).await;
+
+            let started = Instant::now();
+            let result = self.client.post(&self.url).json(&body).send().await;
+            self.latency.record(started.elapsed());
+
+            // Honor PostHog's rate limit instead of hammering it
+            if let Ok(response) = &result {
+                if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && attempt < self.max_retries {
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(parse_retry_after)
+                        .unwrap_or(DEFAULT_RETRY_AFTER);
+                    internal_event!(
+                        self.internal_level,
+                        "PostHog capture API rate limited, pausing exports for {:?}",
+                        retry_after
+                    );
+                    self.rate_limit.pause(retry_after);
+                    attempt += 1;
+                    continue;
+                }
+            }
+
+            match result.and_then(|response| response.error_for_status()) {
+                Ok(_) => self.outcomes.record_success(),
+                Err(e) => {
+                    self.outcomes.record_failure();
+                    internal_event!(self.internal_level, "Failed to send {} logs to PostHog capture API: {}", count, e);
+                }
+            }
+            return;
+        }
+    }
+}
+
+/// Event name used for logs sent through the capture API
+const CAPTURE_EVENT_NAME: &str = "log";
+
+/// Attribute PostHog uses to associate an OTLP log with a person
+const DISTINCT_ID_ATTRIBUTE: &str = "distinct_id";
+
+/// Attribute carrying the rate a log was sampled at, see [`Config::attach_sampling_metadata`]
+pub(crate) const SAMPLE_RATE_ATTRIBUTE: &str = "lipservice.sample_rate";
+
+/// Endpoint scheme exporting OTLP over gRPC to a Unix domain socket
+const UNIX_SCHEME: &str = "unix://";
+
 impl PostHogExporter {
     /// Create a new PostHog exporter
     pub async fn new(config: Config) -> Result<Self> {
-        let resource = Resource::new(vec![
+        // The batch log processor spawns its export loop on the current tokio runtime
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Err(LipServiceError::RuntimeRequired {
+                component: "PostHogExporter",
+            }
+            .into());
+        }
+
+        let latency = Arc::new(LatencyHistogram::new());
+        let outcomes = Arc::new(ExportOutcomes::new());
+        let bytes = Arc::new(ExportBytes::new());
+        let rate_limit = Arc::new(RateLimitPause::default());
+        #[cfg(feature = "debug-records")]
+        let recent = Arc::new(RecentRecords::new(config.recent_records_size));
+        if config.posthog_export_mode == ExportMode::Capture {
+            let client = reqwest::Client::builder().timeout(config.export_timeout()).build()?;
+            let capture = CaptureBatcher::spawn(&config, client, &latency, &outcomes, &rate_limit);
+            let exporter = Self {
+                config,
+                logger_providers: Vec::new(),
+                next_provider: Arc::new(AtomicUsize::new(0)),
+                capture: Some(Arc::new(capture)),
+                latency,
+                outcomes,
+                bytes,
+                rate_limit,
+                // The capture API has no resource, so nothing is promoted
+                promoted: Arc::new(PromotedAttributes::new(Vec::new(), Resource::empty())),
+                queue: None,
+                #[cfg(feature = "debug-records")]
+                recent,
+                _shutdown: Vec::new(),
+            };
+            return Ok(exporter.with_export_queue());
+        }
+
+        let mut resource_attributes = vec![
             KeyValue::new("service.name", config.service_name.clone()),
             KeyValue::new("service.version", "0.2.0"),
-        ]);
-
-        let exporter = opentelemetry_otlp::new_exporter()
-            .http()
-            .with_endpoint(&format!("{}/api/v1/otlp/v1/logs", config.posthog_endpoint))
-            .with_headers(std::collections::HashMap::from([
-                ("Authorization".to_string(), format!("Bearer {}", config.posthog_api_key.as_ref().unwrap())),
-                ("X-PostHog-Team-Id".to_string(), config.posthog_team_id.as_ref().unwrap().clone()),
-            ]));
-
-        let logger_provider = LoggerProvider::builder()
-            .with_batch_log_processor(
+        ];
+        resource_attributes.extend(build_attributes(&config));
+        let resource = Resource::new(resource_attributes);
+        let promoted = Arc::new(PromotedAttributes::new(config.promote_attributes.clone(), resource.clone()));
+
+        // Each pipeline has its own batch processor, so their exports run concurrently
+        let mut logger_providers = Vec::with_capacity(config.export_concurrency);
+        for _ in 0..config.export_concurrency.max(1) {
+            let exporter = match config.posthog_endpoint.strip_prefix(UNIX_SCHEME) {
+                Some(path) => Self::unix_socket_exporter(&config, path)?,
+                None => opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(&format!("{}/api/v1/otlp/v1/logs", config.posthog_endpoint))
+                    .with_headers(Self::export_headers(&config))
+                    .with_http_client(RateLimitedClient {
+                        inner: reqwest::Client::builder().timeout(config.export_timeout()).build()?,
+                        rate_limit: Arc::clone(&rate_limit),
+                    })
+                    .build_log_exporter()?,
+            };
+
+            // Time each batch flush, since batching hides per-record latency
+            let exporter = TimedExporter {
+                inner: exporter,
+                latency: Arc::clone(&latency),
+                outcomes: Arc::clone(&outcomes),
+                promoted: Arc::clone(&promoted),
+                rate_limit: Arc::clone(&rate_limit),
+                max_retries: config.max_retries,
+            };
+
+            let mut builder = LoggerProvider::builder().with_batch_log_processor(
                 exporter,
                 opentelemetry_sdk::logs::BatchLogProcessorConfig::default()
                     .with_max_export_batch_size(config.batch_size)
-                    .with_export_timeout(config.timeout),
-            )
-            .with_resource(resource)
-            .build();
+                    .with_export_timeout(config.export_timeout()),
+            );
+            for factory in &config.log_processors {
+                builder = builder.with_log_processor(UserProcessor {
+                    inner: (factory.0)(),
+                    promoted: Arc::clone(&promoted),
+                });
+            }
+            logger_providers.push(builder.with_resource(resource.clone()).build());
+        }
 
-        let shutdown = logger_provider.shutdown();
+        let shutdown = logger_providers.iter().map(LoggerProvider::shutdown).collect();
 
-        Ok(Self {
+        let exporter = Self {
             config,
-            logger_provider,
+            logger_providers,
+            next_provider: Arc::new(AtomicUsize::new(0)),
+            capture: None,
+            latency,
+            outcomes,
+            bytes,
+            rate_limit,
+            promoted,
+            queue: None,
+            #[cfg(feature = "debug-records")]
+            recent,
             _shutdown: shutdown,
-        })
+        };
+        Ok(exporter.with_export_queue())
+    }
+
+    /// Build an OTLP/gRPC exporter connected to a collector's Unix domain socket
+    #[cfg(unix)]
+    fn unix_socket_exporter(config: &Config, path: &str) -> Result<opentelemetry_otlp::LogExporter> {
+        let path = std::path::PathBuf::from(path);
+        if !path.exists() {
+            return Err(LipServiceError::SocketNotFound { path }.into());
+        }
+
+        // The URI only satisfies tonic; every connection goes to the socket
+        let channel = tonic::transport::Endpoint::try_from("http://localhost")?
+            .timeout(config.export_timeout())
+            .connect_with_connector_lazy(tower::service_fn(move |_: tonic::transport::Uri| {
+                tokio::net::UnixStream::connect(path.clone())
+            }));
+
+        let mut metadata = tonic::metadata::MetadataMap::new();
+        for (name, value) in Self::export_headers(config) {
+            let key = tonic::metadata::MetadataKey::from_bytes(name.to_lowercase().as_bytes())?;
+            metadata.insert(key, value.parse()?);
+        }
+
+        Ok(opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_channel(channel)
+            .with_metadata(metadata)
+            .build_log_exporter()?)
+    }
+
+    #[cfg(not(unix))]
+    fn unix_socket_exporter(_config: &Config, _path: &str) -> Result<opentelemetry_otlp::LogExporter> {
+        anyhow::bail!("{} export endpoints are only supported on Unix", UNIX_SCHEME)
+    }
+
+    /// Create an exporter sending to a team target's PostHog team
+    ///
+    /// Every other setting is taken from `config`.
+    pub async fn new_for_team(config: &Config, target: &TeamTarget) -> Result<Self> {
+        let config = Config {
+            service_name: target.service_name.clone(),
+            posthog_api_key: Some(target.api_key.clone()),
+            posthog_team_id: Some(target.team_id.clone()),
+            posthog_endpoint: target.endpoint.clone().unwrap_or_else(|| config.posthog_endpoint.clone()),
+            team_targets: Vec::new(),
+            ..config.clone()
+        };
+        Self::new(config).await
+    }
+
+    /// Create one exporter per configured team target
+    pub async fn for_teams(config: &Config) -> Result<Vec<(TeamTarget, Arc<Self>)>> {
+        let mut exporters = Vec::with_capacity(config.team_targets.len());
+        for target in &config.team_targets {
+            let exporter = Self::new_for_team(config, target).await?;
+            exporters.push((target.clone(), Arc::new(exporter)));
+        }
+        Ok(exporters)
+    }
+
+    /// Move exports onto a dedicated task fed by a bounded queue, if configured
+    fn with_export_queue(mut self) -> Self {
+        if self.config.export_queue_size == 0 {
+            return self;
+        }
+
+        // The drain task exports through an unqueued handle sharing the same pipeline
+        let inline = Self {
+            config: self.config.clone(),
+            logger_providers: self.logger_providers.clone(),
+            next_provider: Arc::clone(&self.next_provider),
+            capture: self.capture.clone(),
+            latency: Arc::clone(&self.latency),
+            outcomes: Arc::clone(&self.outcomes),
+            bytes: Arc::clone(&self.bytes),
+            rate_limit: Arc::clone(&self.rate_limit),
+            promoted: Arc::clone(&self.promoted),
+            queue: None,
+            #[cfg(feature = "debug-records")]
+            recent: Arc::clone(&self.recent),
+            _shutdown: Vec::new(),
+        };
+        self.queue = Some(ExportQueue::spawn(self.config.export_queue_size, move |log| {
+            let scope = log.scope.as_deref();
+            if let Err(e) = inline.export_now(scope, &log.message, log.severity, log.timestamp, log.attributes) {
+                internal_event!(inline.config.internal_level(Level::ERROR), "Failed to export log to PostHog: {}", e);
+            }
+        }));
+        self
+    }
+
+    /// The OTLP logger providers, one per export pipeline; empty in capture mode
+    ///
+    /// Logs emitted through them go to the export pipelines and to processors from
+    /// [`Config::log_processors`]. They are shut down, flushing every processor, when
+    /// the exporter is dropped; handles cloned from them stop exporting from then on.
+    pub fn logger_providers(&self) -> &[LoggerProvider] {
+        &self.logger_providers
+    }
+
+    /// Logs dropped because the export queue, or in capture mode the batch buffer, was full
+    pub fn queue_dropped(&self) -> u64 {
+        let capture_dropped = self.capture.as_ref().map_or(0, |capture| capture.dropped());
+        self.queue.as_ref().map_or(0, ExportQueue::dropped) + capture_dropped
+    }
+
+    /// Export congestion, i.e. how full the export queue is (0.0 without a queue)
+    pub fn congestion(&self) -> f64 {
+        self.queue.as_ref().map_or(0.0, ExportQueue::fill_level)
+    }
+
+    /// Whether recent exports succeeded
+    pub fn health(&self) -> ExporterHealth {
+        ExporterHealth::new(self.outcomes.last_success(), self.outcomes.consecutive_failures())
+    }
+
+    /// Logs whose export was delayed by a PostHog rate limit
+    pub fn deferred_logs(&self) -> u64 {
+        self.rate_limit.deferred.load(Ordering::Relaxed)
+    }
+
+    /// Exported records and their estimated serialized bytes, per severity
+    pub fn stats(&self) -> ExportStats {
+        self.bytes.snapshot()
+    }
+
+    /// Per-batch export latency percentiles
+    pub fn latency_snapshot(&self) -> LatencySnapshot {
+        self.latency.snapshot()
+    }
+
+    /// The last `n` exported records, oldest first
+    #[cfg(feature = "debug-records")]
+    pub fn last_records(&self, n: usize) -> Vec<ExportedRecord> {
+        self.recent.last(n)
+    }
+
+    /// Configuration this exporter was created with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Build the OTLP export headers, merging user headers with PostHog auth
+    fn export_headers(config: &Config) -> HashMap<String, String> {
+        let auth_headers = [
+            ("Authorization".to_string(), format!("Bearer {}", config.posthog_api_key.as_deref().unwrap_or_default())),
+            ("X-PostHog-Team-Id".to_string(), config.posthog_team_id.clone().unwrap_or_default()),
+        ];
+
+        let mut headers = HashMap::new();
+        for (name, value) in &config.extra_export_headers {
+            let is_auth = auth_headers.iter().any(|(auth, _)| auth.eq_ignore_ascii_case(name));
+            if is_auth && !config.override_auth_headers {
+                internal_event!(
+                    config.internal_level(Level::WARN),
+                    "Ignoring extra export header {} that would override PostHog auth",
+                    name
+                );
+                continue;
+            }
+            headers.insert(name.clone(), value.clone());
+        }
+
+        for (name, value) in auth_headers {
+            let overridden = headers.keys().any(|existing| existing.eq_ignore_ascii_case(&name));
+            if !overridden {
+                headers.insert(name, value);
+            }
+        }
+
+        headers
     }
 
     /// Export a log to PostHog
+    ///
+    /// With an export queue this only enqueues the log; see [`Config::export_queue_size`].
+    /// Accepts a [`Severity`] or a severity string such as "INFO".
     pub fn export_log(
         &self,
         message: &str,
-        severity: &str,
+        severity: impl Into<LabeledSeverity>,
         timestamp: SystemTime,
         attributes: Vec<KeyValue>,
     ) -> Result<()> {
-        let logger = self.logger_provider.logger("lipservice-rust");
+        self.export_scoped(None, message, severity.into(), timestamp, attributes)
+    }
+
+    /// Export a log to PostHog under an OTLP instrumentation scope, e.g. its tracing target
+    pub fn export_log_with_scope(
+        &self,
+        scope: &str,
+        message: &str,
+        severity: impl Into<LabeledSeverity>,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    ) -> Result<()> {
+        self.export_scoped(Some(scope), message, severity.into(), timestamp, attributes)
+    }
+
+    /// Export a log, or enqueue it when there is an export queue
+    fn export_scoped(
+        &self,
+        scope: Option<&str>,
+        message: &str,
+        severity: LabeledSeverity,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    ) -> Result<()> {
+        if let Some(queue) = &self.queue {
+            queue.push(QueuedLog {
+                scope: scope.map(str::to_string),
+                message: message.to_string(),
+                severity,
+                timestamp,
+                attributes,
+            });
+            return Ok(());
+        }
+
+        self.export_now(scope, message, severity, timestamp, attributes)
+    }
+
+    /// Export a log on the calling thread
+    fn export_now(
+        &self,
+        scope: Option<&str>,
+        message: &str,
+        severity: LabeledSeverity,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    ) -> Result<()> {
+        let sanitized = sanitize_message(message);
+        let message = sanitized.as_ref();
+        let text = match self.config.preserve_severity_text {
+            true => severity.text.as_ref(),
+            false => severity.severity.as_str(),
+        };
+        let number = severity.otel(self.config.split_fatal_severity);
+        let severity = severity.severity;
+        #[cfg(feature = "debug-records")]
+        self.recent.push(ExportedRecord {
+            message: message.to_string(),
+            severity: text.to_string(),
+            timestamp,
+            attributes: attributes
+                .iter()
+                .map(|attribute| (attribute.key.as_str().to_string(), attribute.value.as_str().into_owned()))
+                .collect(),
+        });
+
+        if let Some(capture) = &self.capture {
+            let event = capture_event(&self.config, message, text, timestamp, &attributes);
+            self.bytes.record(severity.as_str(), event.to_string().len());
+            capture.push(event);
+            return Ok(());
+        }
+
+        if self.logger_providers.is_empty() {
+            return Ok(());
+        }
+        let next = self.next_provider.fetch_add(1, Ordering::Relaxed);
+        let scope = scope.unwrap_or(&self.config.instrumentation_scope).to_string();
+        let logger = self.logger_providers[next % self.logger_providers.len()].logger(scope);
+
+        let mut attributes = attributes;
+        if self.config.distinct_id_field.is_some() {
+            let distinct_id = resolve_distinct_id(&self.config, &attributes);
+            attributes.push(KeyValue::new(DISTINCT_ID_ATTRIBUTE, distinct_id));
+        }
+        let carried = self.promoted.promote(&mut attributes);
+
+        self.bytes.record(severity.as_str(), otlp_record_size(message, number, text, timestamp, &attributes));
+        attributes.extend(carried);
 
-        let severity = self.parse_severity(severity);
-        
         let mut log_record = LogRecord::default();
-        log_record.set_severity_text(severity.1.to_string());
-        log_record.set_severity_number(severity.0);
+        log_record.set_severity_text(text.to_string());
+        log_record.set_severity_number(number);
         log_record.set_body(message.to_string().into());
         log_record.set_timestamp(timestamp);
         log_record.set_attributes(attributes);
 
         logger.emit(log_record);
         
-        debug!("Log exported to PostHog", message = message, severity = severity.1);
+        debug!(target: INTERNAL_TARGET, "Log exported to PostHog", message = message, severity = severity.as_str());
         Ok(())
     }
 
-    /// Parse severity string to OTLP severity
-    fn parse_severity(&self, severity: &str) -> (Severity, &str) {
-        match severity.to_uppercase().as_str() {
-            "TRACE" => (Severity::Trace, "TRACE"),
-            "DEBUG" => (Severity::Debug, "DEBUG"),
-            "INFO" => (Severity::Info, "INFO"),
-            "WARN" | "WARNING" => (Severity::Warn, "WARN"),
-            "ERROR" => (Severity::Error, "ERROR"),
-            "FATAL" | "CRITICAL" => (Severity::Fatal, "FATAL"),
-            _ => (Severity::Info, "INFO"),
-        }
-    }
+    /// Flush the exporter before shutdown
+    ///
+    /// Waits, up to [`Config::export_timeout`], for the export queue to drain and for
+    /// every OTLP pipeline to export its last partial batch. The pipelines themselves
+    /// shut down when the exporter is dropped.
+    pub async fn shutdown(&self) -> Result<()> {
+        let timeout = self.config.export_timeout();
+        let flush = async {
+            if let Some(queue) = &self.queue {
+                queue.drained().await;
+            }
+            if let Some(capture) = &self.capture {
+                capture.drained().await;
+            }
 
-    /// Shutdown the exporter
-    pub async fn shutdown(self) -> Result<()> {
-        // The shutdown is handled by the Drop trait
+            // Flushing blocks until the batch task has exported, so keep it off the runtime
+            let providers = self.logger_providers.clone();
+            tokio::task::spawn_blocking(move || {
+                providers.iter().flat_map(LoggerProvider::force_flush).collect::<Vec<_>>()
+            })
+            .await
+        };
+
+        match tokio::time::timeout(timeout, flush).await {
+            Ok(results) => {
+                for result in results? {
+                    result?;
+                }
+            }
+            Err(_) => internal_event!(
+                self.config.internal_level(Level::WARN),
+                "Timed out after {:?} flushing logs to PostHog on shutdown",
+                timeout
+            ),
+        }
         Ok(())
     }
 }
 
-/// LipService logger that integrates with tracing
-pub struct LipServiceLogger {
-    sampler: Arc<AdaptiveSampler>,
-    posthog_exporter: Option<Arc<PostHogExporter>>,
+/// Person a log is associated with: the configured distinct-id field if present,
+/// otherwise the service name
+fn resolve_distinct_id(config: &Config, attributes: &[KeyValue]) -> String {
+    config
+        .distinct_id_field
+        .as_deref()
+        .and_then(|field| {
+            let prefixed = prefixed_key(field, config.attribute_prefix.as_deref());
+            attributes
+                .iter()
+                .find(|attribute| attribute.key.as_str() == field || attribute.key.as_str() == prefixed)
+        })
+        .map(|attribute| attribute.value.as_str().into_owned())
+        .unwrap_or_else(|| config.service_name.clone())
 }
 
-impl LipServiceLogger {
-    /// Create a new LipService logger
-    pub fn new(
-        sampler: Arc<AdaptiveSampler>,
-        posthog_exporter: Option<Arc<PostHogExporter>>,
-    ) -> Self {
-        Self {
-            sampler,
-            posthog_exporter,
-        }
+/// Estimated size of a log record in the OTLP/JSON encoding
+fn otlp_record_size(
+    message: &str,
+    number: opentelemetry::logs::Severity,
+    text: &str,
+    timestamp: SystemTime,
+    attributes: &[KeyValue],
+) -> usize {
+    let attributes: Vec<serde_json::Value> = attributes
+        .iter()
+        .map(|attribute| {
+            serde_json::json!({
+                "key": attribute.key.as_str(),
+                "value": { "stringValue": attribute.value.as_str() },
+            })
+        })
+        .collect();
+    let nanos = timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+
+    serde_json::json!({
+        "timeUnixNano": nanos.to_string(),
+        "severityNumber": number as i32,
+        "severityText": text,
+        "body": { "stringValue": message },
+        "attributes": attributes,
+    })
+    .to_string()
+    .len()
+}
+
+/// Deployment and build identifiers every exported log carries
+fn build_attributes(config: &Config) -> Vec<KeyValue> {
+    let mut attributes = Vec::new();
+    if let Some(deployment_id) = &config.deployment_id {
+        attributes.push(KeyValue::new("deployment.id", deployment_id.clone()));
+    }
+    if let Some(git_sha) = &config.git_sha {
+        attributes.push(KeyValue::new("git.sha", git_sha.clone()));
     }
+    attributes
+}
 
-    /// Log a message with sampling and PostHog export
-    pub fn log(
-        &self,
-        level: tracing::Level,
-        message: &str,
-        fields: &tracing::field::ValueSet,
-    ) {
-        let severity = match level {
-            tracing::Level::TRACE => "TRACE",
-            tracing::Level::DEBUG => "DEBUG",
-            tracing::Level::INFO => "INFO",
-            tracing::Level::WARN => "WARN",
-            tracing::Level::ERROR => "ERROR",
-        };
+/// Build a PostHog capture API event from a log
+fn capture_event(
+    config: &Config,
+    message: &str,
+    severity: &str,
+    timestamp: SystemTime,
+    attributes: &[KeyValue],
+) -> serde_json::Value {
+    let mut properties = serde_json::Map::new();
+    for attribute in attributes {
+        properties.insert(
+            attribute.key.as_str().to_string(),
+            serde_json::Value::String(attribute.value.as_str().into_owned()),
+        );
+    }
+    for attribute in build_attributes(config) {
+        properties.insert(attribute.key.as_str().to_string(), attribute.value.as_str().into_owned().into());
+    }
+    properties.insert("$lib".to_string(), "lipservice-rust".into());
+    properties.insert("service".to_string(), config.service_name.clone().into());
+    properties.insert("severity".to_string(), severity.into());
+    properties.insert("message".to_string(), message.into());
 
-        // Check if we should sample this log
-        if !self.sampler.should_sample(message, severity) {
-            return;
-        }
+    serde_json::json!({
+        "api_key": config.posthog_api_key.clone().unwrap_or_default(),
+        "event": CAPTURE_EVENT_NAME,
+        "distinct_id": resolve_distinct_id(config, attributes),
+        "properties": properties,
+        "timestamp": chrono::DateTime::<chrono::Utc>::from(timestamp).to_rfc3339(),
+    })
+}
 
-        // Export to PostHog if configured
-        if let Some(exporter) = &self.posthog_exporter {
-            let attributes = self.extract_attributes(fields);
-            if let Err(e) = exporter.export_log(message, severity, SystemTime::now(), attributes) {
-                error!("Failed to export log to PostHog: {}", e);
+/// Log exporter wrapper recording the duration and outcome of every batch export
+///
+/// Batches wait out a 429 pause and are retried up to `max_retries` times after one.
+#[derive(Debug)]
+struct TimedExporter<E> {
+    inner: E,
+    latency: Arc<LatencyHistogram>,
+    outcomes: Arc<ExportOutcomes>,
+    promoted: Arc<PromotedAttributes>,
+    rate_limit: Arc<RateLimitPause>,
+    max_retries: u32,
+}
+
+#[async_trait]
+impl<E: LogExporter> LogExporter for TimedExporter<E> {
+    async fn export(&mut self, mut batch: Vec<LogData>) -> LogResult<()> {
+        for data in &mut batch {
+            self.promoted.apply(data);
+        }
+        let mut attempt = 0;
+        loop {
+            self.rate_limit.wait(batch.len()).await;
+            // Only keep a copy when a rate-limited batch may still be retried
+            let attempt_batch = if attempt < self.max_retries { batch.clone() } else { std::mem::take(&mut batch) };
+            let started = Instant::now();
+            let result = self.inner.export(attempt_batch).await;
+            self.latency.record(started.elapsed());
+            if result.is_err() && attempt < self.max_retries && self.rate_limit.is_paused() {
+                attempt += 1;
+                continue;
+            }
+            match &result {
+                Ok(()) => self.outcomes.record_success(),
+                Err(_) => self.outcomes.record_failure(),
             }
+            return result;
         }
     }
 
-    /// Extract attributes from tracing fields
-    fn extract_attributes(&self, fields: &tracing::field::ValueSet) -> Vec<KeyValue> {
-        let mut attributes = Vec::new();
-        
-        fields.record(&mut |key, value| {
-            attributes.push(KeyValue::new(key.to_string(), value.to_string()));
-        });
-
-        attributes
+    fn shutdown(&mut self) {
+        self.inner.shutdown();
     }
 }
 
-/// Tracing layer for LipService integration
-pub struct LipServiceLayer {
-    logger: Arc<LipServiceLogger>,
-}
+/// Creates a processor attached to every OTLP export pipeline, see [`Config::log_processors`]
+#[derive(Clone)]
+pub struct LogProcessorFactory(Arc<dyn Fn() -> Box<dyn LogProcessor> + Send + Sync>);
 
-impl LipServiceLayer {
-    /// Create a new LipService layer
-    pub fn new(logger: Arc<LipServiceLogger>) -> Self {
-        Self { logger }
+impl LogProcessorFactory {
+    /// Wrap a function creating one processor per pipeline
+    pub fn new<P: LogProcessor + 'static>(make: impl Fn() -> P + Send + Sync + 'static) -> Self {
+        Self(Arc::new(move || Box::new(make())))
     }
 }
 
-impl<S> tracing_subscriber::Layer<S> for LipServiceLayer
-where
-    S: tracing::Subscriber,
-{
-    fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
-        let level = *event.metadata().level();
-        let message = format!("{}", event);
-        
-        self.logger.log(level, &message, event.field_set());
+impl std::fmt::Debug for LogProcessorFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LogProcessorFactory(..)")
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::Config;
+/// A user processor attached next to the batch exporter
+#[derive(Debug)]
+struct UserProcessor {
+    inner: Box<dyn LogProcessor>,
+    promoted: Arc<PromotedAttributes>,
+}
 
-    #[tokio::test]
-    async fn test_posthog_exporter_creation() {
-        let config = Config {
-            service_name: "test-service".to_string(),
-            lipservice_url: "http://localhost:8000".to_string(),
-            posthog_api_key: Some("phc_test".to_string()),
-            posthog_team_id: Some("12345".to_string()),
-            ..Default::default()
-        };
+impl LogProcessor for UserProcessor {
+    fn emit(&self, mut data: LogData) {
+        self.promoted.apply(&mut data);
+        self.inner.emit(data)
+    }
 
-        let exporter = PostHogExporter::new(config).await;
-        // This might fail in tests due to network, but we can test the creation
-        // In a real test environment, you'd mock the HTTP client
-        assert!(exporter.is_ok() || exporter.is_err());
+    fn force_flush(&self) -> LogResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> LogResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// Record attribute telling [`PromotedAttributes::apply`] which promoted keys a record
+/// carried, as a bit set of their positions
+const PROMOTED_KEYS_ATTRIBUTE: &str = "lipservice.promoted_keys";
+
+/// Attributes moved from records onto the Resource, see [`Config::promote_attributes`]
+///
+/// The first value seen for a key is promoted; records carrying a different value
+/// keep it as a record attribute. A record only gets the promoted values of the keys
+/// it carried.
+#[derive(Debug)]
+struct PromotedAttributes {
+    keys: Vec<String>,
+    /// Pipeline resource
+    base: Resource,
+    /// Value promoted for each key, once seen
+    values: RwLock<Vec<Option<opentelemetry::Value>>>,
+    /// Resource shared by the records carrying each set of promoted keys
+    ///
+    /// Records borrow their resource for `'static`, so each one is leaked; promoted
+    /// values never change and keys are capped, so there are few of them.
+    resources: dashmap::DashMap<u64, &'static Resource>,
+}
+
+impl PromotedAttributes {
+    fn new(keys: Vec<String>, base: Resource) -> Self {
+        Self {
+            values: RwLock::new(vec![None; keys.len()]),
+            keys,
+            base,
+            resources: dashmap::DashMap::new(),
+        }
+    }
+
+    /// Remove promoted attributes from a record's attributes, returning the attribute
+    /// marking which keys the record carried
+    fn promote(&self, attributes: &mut Vec<KeyValue>) -> Option<KeyValue> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let mut carried = 0u64;
+        attributes.retain(|attribute| {
+            let Some(index) = self.keys.iter().position(|key| key == attribute.key.as_str()) else {
+                return true;
+            };
+            // The read guard must be gone before a first value is written
+            let current = self.values.read()[index].clone();
+            let promoted = match current {
+                Some(promoted) => promoted,
+                None => self.values.write()[index].get_or_insert_with(|| attribute.value.clone()).clone(),
+            };
+            if promoted != attribute.value {
+                return true;
+            }
+            carried |= 1 << index;
+            false
+        });
+        (carried != 0).then(|| KeyValue::new(PROMOTED_KEYS_ATTRIBUTE, carried as i64))
+    }
+
+    /// Give a record the resource carrying the values promoted from it
+    fn apply(&self, data: &mut LogData) {
+        let Some(attributes) = data.record.attributes.as_mut() else {
+            return;
+        };
+        let Some(position) = attributes.iter().position(|(key, _)| key.as_str() == PROMOTED_KEYS_ATTRIBUTE) else {
+            return;
+        };
+        if let (_, AnyValue::Int(carried)) = attributes.remove(position) {
+            data.resource = Cow::Borrowed(self.resource_for(carried as u64));
+        }
+    }
+
+    /// Pipeline resource merged with the promoted values of the `carried` keys
+    fn resource_for(&self, carried: u64) -> &'static Resource {
+        *self.resources.entry(carried).or_insert_with(|| {
+            let values = self.values.read();
+            let promoted = self
+                .keys
+                .iter()
+                .zip(values.iter())
+                .enumerate()
+                .filter(|(index, _)| carried & (1 << index) != 0)
+                .filter_map(|(_, (key, value))| Some(KeyValue::new(key.clone(), value.clone()?)));
+            Box::leak(Box::new(self.base.merge(&Resource::new(promoted))))
+        })
+    }
+}
+
+/// What the tracing layer knows about an event beyond its own fields
+#[derive(Debug, Default)]
+pub struct EventContext<'a> {
+    /// Metadata of the event's callsite
+    pub metadata: Option<&'a tracing::Metadata<'a>>,
+    /// Fields inherited from the enclosing spans, innermost value first
+    pub span_fields: Vec<(&'static str, String)>,
+}
+
+/// Logs held back in reservoir mode, see [`Config::reservoir_size`]
+///
+/// Each pattern's sample is exported at the end of every pattern reporting window,
+/// and on [`ExportReservoir::flush`]. Held-back logs count as seen by the sampler,
+/// and as sampled once exported.
+pub(crate) struct ExportReservoir {
+    sampler: Arc<AdaptiveSampler>,
+    samples: PatternReservoir<(Arc<PostHogExporter>, String, QueuedLog)>,
+}
+
+impl ExportReservoir {
+    /// Create a reservoir of `size` logs per pattern, flushed from a task on the current runtime
+    pub(crate) fn start(sampler: Arc<AdaptiveSampler>, size: usize) -> Arc<Self> {
+        let window = sampler.config().pattern_report_interval;
+        let reservoir = Arc::new(Self {
+            sampler,
+            samples: PatternReservoir::new(size),
+        });
+        let flushed = Arc::downgrade(&reservoir);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(window);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                // Stop once the logger is gone
+                let Some(reservoir) = flushed.upgrade() else {
+                    return;
+                };
+                reservoir.flush();
+            }
+        });
+
+        reservoir
+    }
+
+    /// Hold a log deferred by [`AdaptiveSampler::screen_with_fields`] back as a candidate
+    /// for its pattern's sample
+    pub(crate) fn offer(&self, exporter: &Arc<PostHogExporter>, signature: String, log: QueuedLog) {
+        self.samples.offer(&signature, (Arc::clone(exporter), signature.clone(), log));
+    }
+
+    /// Export every pattern's sample so far, starting a new window
+    pub(crate) fn flush(&self) {
+        for (exporter, signature, log) in self.samples.drain() {
+            self.sampler.record_deferred_kept(&signature, &log.message);
+            let exported =
+                exporter.export_scoped(log.scope.as_deref(), &log.message, log.severity, log.timestamp, log.attributes);
+            if let Err(e) = exported {
+                internal_event!(
+                    exporter.config().internal_level(Level::ERROR),
+                    "Failed to export log to PostHog: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// LipService logger that integrates with tracing
+pub struct LipServiceLogger {
+    sampler: Arc<AdaptiveSampler>,
+    posthog_exporter: Option<Arc<PostHogExporter>>,
+    team_exporters: Vec<(TeamTarget, Arc<PostHogExporter>)>,
+    reservoir: Option<Arc<ExportReservoir>>,
+    local_writer: Option<NonBlocking>,
+}
+
+impl LipServiceLogger {
+    /// Create a new LipService logger
+    pub fn new(
+        sampler: Arc<AdaptiveSampler>,
+        posthog_exporter: Option<Arc<PostHogExporter>>,
+    ) -> Self {
+        let reservoir = match (sampler.config().reservoir_size, &posthog_exporter) {
+            (Some(size), Some(_)) => Some(ExportReservoir::start(Arc::clone(&sampler), size)),
+            _ => None,
+        };
+
+        Self {
+            sampler,
+            posthog_exporter,
+            team_exporters: Vec::new(),
+            reservoir,
+            local_writer: None,
+        }
+    }
+
+    /// Mirror sampled logs to `writer` from a background thread, instead of to
+    /// [`Config::local_sink`], so logging never blocks on local IO
+    ///
+    /// The returned guard must be held for as long as logs are written: dropping it
+    /// flushes the lines still buffered and stops the writer thread, after which local
+    /// lines are lost. Lines are also dropped while the buffer is full.
+    pub fn with_non_blocking_writer(mut self, writer: impl Write + Send + 'static) -> (Self, WorkerGuard) {
+        let (writer, guard) = tracing_appender::non_blocking(writer);
+        self.local_writer = Some(writer);
+        (self, guard)
+    }
+
+    /// Route logs of team targets to their own exporters
+    ///
+    /// A log goes to the first team whose service name equals its
+    /// [`Config::team_routing_field`] value or whose target prefix its tracing target
+    /// starts with; other logs go to the default exporter.
+    pub fn with_team_exporters(mut self, exporters: Vec<(TeamTarget, Arc<PostHogExporter>)>) -> Self {
+        if self.reservoir.is_none() && !exporters.is_empty() {
+            if let Some(size) = self.sampler.config().reservoir_size {
+                self.reservoir = Some(ExportReservoir::start(Arc::clone(&self.sampler), size));
+            }
+        }
+        self.team_exporters = exporters;
+        self
+    }
+
+    /// Export the logs held back in reservoir mode, then flush every exporter
+    pub async fn shutdown(&self) -> Result<()> {
+        if let Some(reservoir) = &self.reservoir {
+            reservoir.flush();
+        }
+        let teams = self.team_exporters.iter().map(|(_, exporter)| exporter);
+        for exporter in self.posthog_exporter.iter().chain(teams) {
+            exporter.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    /// Exporter a log is sent to, given its fields and tracing target
+    fn route(
+        &self,
+        metadata: Option<&tracing::Metadata<'_>>,
+        fields: &tracing::field::ValueSet,
+    ) -> Option<&Arc<PostHogExporter>> {
+        if self.team_exporters.is_empty() {
+            return self.posthog_exporter.as_ref();
+        }
+
+        let routing_field = &self.sampler.config().team_routing_field;
+        let mut service = None;
+        fields.record(&mut |key, value| {
+            if service.is_none() && key == routing_field.as_str() {
+                service = Some(value.to_string());
+            }
+        });
+
+        self.team_exporters
+            .iter()
+            .find(|(team, _)| team.matches(service.as_deref(), metadata.map(|metadata| metadata.target())))
+            .map(|(_, exporter)| exporter)
+            .or(self.posthog_exporter.as_ref())
+    }
+
+    /// Log a message with sampling and PostHog export
+    pub fn log(
+        &self,
+        level: tracing::Level,
+        message: &str,
+        fields: &tracing::field::ValueSet,
+    ) {
+        self.log_from(level, &EventContext::default(), message, fields);
+    }
+
+    /// Log a message of a tracing event, whose metadata may select its team exporter
+    pub fn log_from(
+        &self,
+        level: tracing::Level,
+        context: &EventContext<'_>,
+        message: &str,
+        fields: &tracing::field::ValueSet,
+    ) {
+        let severity = severity_from_level(level);
+
+        // Check if we should sample this log
+        let recorded = self.recorded_fields(fields);
+        let sample_fields: Vec<(&str, &str)> = recorded
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        // Shed load as the export queue backs up
+        let exporter = self.route(context.metadata, fields);
+        if let Some(exporter) = exporter {
+            self.sampler.set_congestion(exporter.congestion());
+        }
+
+        // In reservoir mode logs passing the early stages wait for the window's representative sample
+        let kept = if let (Some(reservoir), Some(exporter)) = (&self.reservoir, exporter) {
+            match self.sampler.screen_with_fields(message, severity, &sample_fields) {
+                Screened::Decided(kept) => kept,
+                Screened::Deferred(signature) => {
+                    let attributes =
+                        self.extract_attributes(fields, context, exporter.config().attribute_prefix.as_deref());
+                    let log = QueuedLog {
+                        scope: context.metadata.map(|metadata| metadata.target().to_string()),
+                        message: message.to_string(),
+                        severity: level.into(),
+                        timestamp: SystemTime::now(),
+                        attributes,
+                    };
+                    reservoir.offer(exporter, signature, log);
+                    return;
+                }
+            }
+        } else {
+            self.sampler.sample_with_fields(message, severity, &sample_fields)
+        };
+        let Some(kept) = kept else {
+            return;
+        };
+
+        self.emit_sampled(level, context, message, fields, Some(kept));
+    }
+
+    /// Write an already-sampled log to the local sink and PostHog
+    pub fn emit(
+        &self,
+        level: tracing::Level,
+        message: &str,
+        fields: &tracing::field::ValueSet,
+    ) {
+        self.emit_from(level, &EventContext::default(), message, fields);
+    }
+
+    /// Write an already-sampled log of a tracing event to the local sink and PostHog
+    pub fn emit_from(
+        &self,
+        level: tracing::Level,
+        context: &EventContext<'_>,
+        message: &str,
+        fields: &tracing::field::ValueSet,
+    ) {
+        self.emit_sampled(level, context, message, fields, None);
+    }
+
+    /// Write a sampled log, tagged with the rate it was sampled at if known and configured
+    fn emit_sampled(
+        &self,
+        level: tracing::Level,
+        context: &EventContext<'_>,
+        message: &str,
+        fields: &tracing::field::ValueSet,
+        kept: Option<KeptLog>,
+    ) {
+        let config = self.sampler.config();
+        let sample_rate = kept.as_ref().and_then(|kept| kept.rate).filter(|_| config.attach_sampling_metadata);
+        let severity = severity_from_level(level);
+        let timestamp = SystemTime::now();
+
+        if let Some(writer) = &self.local_writer {
+            let line = format!("{} {} {}\n", chrono::Utc::now().to_rfc3339(), severity, message);
+            // Only fails once the worker is gone, i.e. after the guard was dropped
+            let _ = writer.clone().write_all(line.as_bytes());
+        } else if self.sampler.config().local_sink == LocalSink::Stdout {
+            println!("{} {} {}", chrono::Utc::now().to_rfc3339(), severity, message);
+        }
+
+        let exporter = self.route(context.metadata, fields);
+        if exporter.is_none() && config.on_sampled_log.is_none() {
+            return;
+        }
+
+        // Attributes are extracted once for both the callback and the export
+        let prefix = exporter.map_or(config.attribute_prefix.as_deref(), |exporter| {
+            exporter.config().attribute_prefix.as_deref()
+        });
+        let mut attributes = self.extract_attributes(fields, context, prefix);
+        if let Some(rate) = sample_rate {
+            attributes.push(KeyValue::new(SAMPLE_RATE_ATTRIBUTE, rate));
+        }
+
+        if let Some(callback) = &config.on_sampled_log {
+            let signature = match kept.and_then(|kept| kept.signature) {
+                Some(signature) => signature,
+                None => {
+                    let recorded = self.recorded_fields(fields);
+                    let sample_fields: Vec<(&str, &str)> =
+                        recorded.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+                    self.sampler.signature_for(message, &sample_fields)
+                }
+            };
+            callback.call(&sampled_log(message, level.into(), timestamp, &attributes, signature));
+        }
+
+        // Export to PostHog if configured
+        if let Some(exporter) = exporter {
+            let scope = context.metadata.map(|metadata| metadata.target());
+            if let Err(e) = exporter.export_scoped(scope, message, severity.into(), timestamp, attributes) {
+                internal_event!(
+                    self.sampler.config().internal_level(Level::ERROR),
+                    "Failed to export log to PostHog: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    /// Event fields the sampler sees, i.e. all but the message fields
+    fn recorded_fields(&self, fields: &tracing::field::ValueSet) -> Vec<(String, String)> {
+        let message_fields = &self.sampler.config().message_field_names;
+        let mut recorded = Vec::new();
+        fields.record(&mut |key, value| {
+            if !message_fields.iter().any(|name| name == key) {
+                recorded.push((key.to_string(), value.to_string()));
+            }
+        });
+        recorded
+    }
+
+    /// Extract attributes from tracing fields, inherited span fields and, if configured,
+    /// the event's source location
+    fn extract_attributes(
+        &self,
+        fields: &tracing::field::ValueSet,
+        context: &EventContext<'_>,
+        prefix: Option<&str>,
+    ) -> Vec<KeyValue> {
+        // Span fields go first, so by default the event's own fields win
+        let attributes: Vec<KeyValue> = context
+            .span_fields
+            .iter()
+            .map(|(key, value)| KeyValue::new(prefixed_key(key, prefix), value.clone()))
+            .collect();
+        let mut visitor = AttributeVisitor {
+            prefix,
+            buckets: &self.sampler.config().attribute_buckets,
+            attributes,
+        };
+        fields.record(&mut visitor);
+        let attributes = visitor.attributes;
+        let mut attributes = resolve_duplicate_keys(attributes, self.sampler.config().duplicate_attribute_keys);
+
+        if let (true, Some(metadata)) = (self.sampler.config().capture_source_location, context.metadata) {
+            if let Some(file) = metadata.file() {
+                attributes.push(KeyValue::new("code.filepath", file.to_string()));
+            }
+            if let Some(line) = metadata.line() {
+                attributes.push(KeyValue::new("code.lineno", i64::from(line)));
+            }
+        }
+
+        attributes
+    }
+}
+
+/// The log handed to the sampled log callback
+pub(crate) fn sampled_log(
+    message: &str,
+    severity: Severity,
+    timestamp: SystemTime,
+    attributes: &[KeyValue],
+    signature: String,
+) -> SampledLog {
+    SampledLog {
+        body: message.to_string(),
+        severity,
+        timestamp,
+        attributes: attributes
+            .iter()
+            .map(|attribute| (attribute.key.as_str().to_string(), attribute.value.as_str().into_owned()))
+            .collect(),
+        signature,
+    }
+}
+
+/// Resolve attributes sharing a key according to the configured policy
+fn resolve_duplicate_keys(attributes: Vec<KeyValue>, policy: DuplicateKeys) -> Vec<KeyValue> {
+    let mut resolved: Vec<KeyValue> = Vec::with_capacity(attributes.len());
+    for attribute in attributes {
+        let Some(index) = resolved.iter().position(|existing| existing.key == attribute.key) else {
+            resolved.push(attribute);
+            continue;
+        };
+        match policy {
+            DuplicateKeys::LastWins => resolved[index] = attribute,
+            DuplicateKeys::FirstWins => {}
+            DuplicateKeys::Suffix => {
+                let key = (2..)
+                    .map(|n| format!("{}_{}", attribute.key.as_str(), n))
+                    .find(|key| !resolved.iter().any(|existing| existing.key.as_str() == key))
+                    .expect("some suffix is unused");
+                resolved.push(KeyValue::new(key, attribute.value));
+            }
+        }
+    }
+    resolved
+}
+
+/// Attribute keys with OTLP or trace meaning that are never namespaced
+const RESERVED_ATTRIBUTE_KEYS: &[&str] = &["trace_id", "span_id", "trace_flags"];
+const RESERVED_ATTRIBUTE_PREFIXES: &[&str] = &["otel.", "service.", "telemetry.", "code.", "exception."];
+
+/// Apply the configured attribute prefix, leaving reserved keys untouched
+pub(crate) fn prefixed_key(key: &str, prefix: Option<&str>) -> String {
+    let reserved = RESERVED_ATTRIBUTE_KEYS.contains(&key)
+        || RESERVED_ATTRIBUTE_PREFIXES.iter().any(|reserved| key.starts_with(reserved));
+
+    match prefix {
+        Some(prefix) if !reserved => format!("{}.{}", prefix.trim_end_matches('.'), key),
+        _ => key.to_string(),
+    }
+}
+
+/// Tracing layer for LipService integration
+pub struct LipServiceLayer {
+    logger: Arc<LipServiceLogger>,
+}
+
+impl LipServiceLayer {
+    /// Create a new LipService layer
+    pub fn new(logger: Arc<LipServiceLogger>) -> Self {
+        Self { logger }
+    }
+
+    /// Export an event without a sampling decision, counting it as kept
+    fn keep_unsampled(&self, event: &tracing::Event<'_>, context: &EventContext<'_>) {
+        let message = event_message(event, &self.logger.sampler.config().message_field_names);
+        self.logger.sampler.record_kept(&message);
+        self.logger.emit_from(*event.metadata().level(), context, &message, event.field_set());
+    }
+
+    /// Context of an event: its metadata and, if configured, its spans' fields
+    fn event_context<S>(
+        &self,
+        event: &tracing::Event<'_>,
+        ctx: &tracing_subscriber::layer::Context<'_, S>,
+    ) -> EventContext<'static>
+    where
+        S: tracing::Subscriber + for<'l> tracing_subscriber::registry::LookupSpan<'l>,
+    {
+        let mut span_fields: Vec<(&'static str, String)> = Vec::new();
+        if self.logger.sampler.config().inherit_span_fields {
+            // Scopes run innermost first, so a child span's value wins over its parent's
+            for span in ctx.event_scope(event).into_iter().flatten() {
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in &fields.0 {
+                        if !span_fields.iter().any(|(seen, _)| seen == key) {
+                            span_fields.push((*key, value.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        EventContext {
+            metadata: Some(event.metadata()),
+            span_fields,
+        }
+    }
+}
+
+/// Keep/drop decision recorded on a span when span sampling is enabled
+#[derive(Debug, Clone, Copy)]
+struct SpanSamplingDecision {
+    keep: bool,
+}
+
+/// When a span was opened, recorded when span close logs are enabled
+#[derive(Debug, Clone, Copy)]
+struct SpanOpened(Instant);
+
+/// Fields recorded on a span, inherited by the events inside it
+#[derive(Debug, Default)]
+struct SpanFields(Vec<(&'static str, String)>);
+
+impl SpanFields {
+    /// Add recorded fields, replacing earlier values of the same field
+    fn merge(&mut self, recorded: Vec<(&'static str, String)>) {
+        for (key, value) in recorded {
+            match self.0.iter_mut().find(|(existing, _)| *existing == key) {
+                Some(field) => field.1 = value,
+                None => self.0.push((key, value)),
+            }
+        }
+    }
+}
+
+/// Longest Debug-formatted attribute value exported, in bytes
+const MAX_DEBUG_VALUE_LEN: usize = 1024;
+
+/// Suffix of the attribute keeping the raw value of a bucketed numeric field
+const RAW_VALUE_SUFFIX: &str = ".raw";
+
+/// Suffix of the flag attribute set on values cut short from their Debug output
+const DEBUG_FORMATTED_SUFFIX: &str = ".debug_formatted";
+
+/// Records event fields as typed attributes, capping values that only format via Debug
+struct AttributeVisitor<'a> {
+    prefix: Option<&'a str>,
+    buckets: &'a [AttributeBucket],
+    attributes: Vec<KeyValue>,
+}
+
+impl AttributeVisitor<'_> {
+    fn push(&mut self, field: &tracing::field::Field, value: impl Into<opentelemetry::Value>) {
+        self.attributes.push(KeyValue::new(prefixed_key(field.name(), self.prefix), value));
+    }
+
+    /// Push a numeric field, as its bucket label if the field is bucketed
+    fn push_number(&mut self, field: &tracing::field::Field, number: f64, value: impl Into<opentelemetry::Value>) {
+        let Some(bucket) = self.buckets.iter().find(|bucket| bucket.field == field.name()) else {
+            self.push(field, value);
+            return;
+        };
+
+        let key = prefixed_key(field.name(), self.prefix);
+        self.attributes.push(KeyValue::new(format!("{}{}", key, RAW_VALUE_SUFFIX), value));
+        self.attributes.push(KeyValue::new(key, bucket.bucketing.label(number)));
+    }
+}
+
+impl tracing::field::Visit for AttributeVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.push_number(field, value as f64, value);
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(signed) => self.push_number(field, value as f64, signed),
+            Err(_) => self.push_number(field, value as f64, value.to_string()),
+        }
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        self.push_number(field, value, value);
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.push(field, value);
+    }
+
+    fn record_error(&mut self, field: &tracing::field::Field, value: &(dyn std::error::Error + 'static)) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        use std::fmt::Write as _;
+
+        let mut writer = CappedWriter { buf: String::new(), limit: MAX_DEBUG_VALUE_LEN, truncated: false };
+        let _ = write!(writer, "{:?}", value);
+        if !writer.truncated {
+            self.push(field, writer.buf);
+            return;
+        }
+
+        writer.buf.push('…');
+        self.push(field, writer.buf);
+        self.attributes.push(KeyValue::new(
+            format!("{}{}", prefixed_key(field.name(), self.prefix), DEBUG_FORMATTED_SUFFIX),
+            true,
+        ));
+    }
+}
+
+/// Formatting sink that stops accepting output past a byte limit
+struct CappedWriter {
+    buf: String,
+    limit: usize,
+    truncated: bool,
+}
+
+impl std::fmt::Write for CappedWriter {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        let room = self.limit - self.buf.len();
+        if s.len() <= room {
+            self.buf.push_str(s);
+            return Ok(());
+        }
+
+        let mut end = room;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buf.push_str(&s[..end]);
+        self.truncated = true;
+        // Stops the formatter, so oversized values are never fully rendered
+        Err(std::fmt::Error)
+    }
+}
+
+/// Marks a span that recorded an error, so its later events are kept
+#[derive(Debug, Clone, Copy)]
+struct SpanErrored;
+
+/// Detects an `error = true` field
+#[derive(Default)]
+struct ErrorFlag(bool);
+
+impl tracing::field::Visit for ErrorFlag {
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.0 |= field.name() == "error" && value;
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0 |= field.name() == "error" && value == "true";
+    }
+
+    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Mark a span as having recorded an error
+fn mark_errored<S>(span: &tracing_subscriber::registry::SpanRef<'_, S>)
+where
+    S: for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    let mut extensions = span.extensions_mut();
+    if extensions.get_mut::<SpanErrored>().is_none() {
+        extensions.insert(SpanErrored);
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for LipServiceLayer
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if self.logger.sampler.config().keep_errored_spans {
+            let mut flag = ErrorFlag::default();
+            attrs.record(&mut flag);
+            if flag.0 {
+                mark_errored(&span);
+            }
+        }
+
+        let config = self.logger.sampler.config();
+        if config.inherit_span_fields || config.span_close_logs {
+            let mut collector = FieldCollector(Vec::new());
+            attrs.record(&mut collector);
+            let mut fields = SpanFields::default();
+            fields.merge(collector.0);
+            span.extensions_mut().insert(fields);
+        }
+
+        if config.span_close_logs {
+            span.extensions_mut().insert(SpanOpened(Instant::now()));
+        }
+
+        if self.logger.sampler.config().sample_spans {
+            let metadata = attrs.metadata();
+            let keep = self.logger.sampler.should_keep_span(metadata.name(), severity_from_level(*metadata.level()));
+            span.extensions_mut().insert(SpanSamplingDecision { keep });
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if self.logger.sampler.config().keep_errored_spans {
+            let mut flag = ErrorFlag::default();
+            values.record(&mut flag);
+            if flag.0 {
+                mark_errored(&span);
+            }
+        }
+
+        let config = self.logger.sampler.config();
+        if config.inherit_span_fields || config.span_close_logs {
+            let mut collector = FieldCollector(Vec::new());
+            values.record(&mut collector);
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                fields.merge(collector.0);
+            }
+        }
+    }
+
+    fn on_close(&self, id: tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if !self.logger.sampler.config().span_close_logs {
+            return;
+        }
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(SpanOpened(opened)) = span.extensions().get::<SpanOpened>().copied() else {
+            return;
+        };
+        // Spans dropped by span sampling (or inside one) are not logged either
+        let dropped = span.scope().any(|scope| {
+            scope.extensions().get::<SpanSamplingDecision>().is_some_and(|decision| !decision.keep)
+        });
+        if dropped {
+            return;
+        }
+
+        let mut span_fields = vec![
+            ("span.name", span.name().to_string()),
+            ("span.duration_ms", format!("{:.3}", opened.elapsed().as_secs_f64() * 1000.0)),
+        ];
+        if let Some(fields) = span.extensions().get::<SpanFields>() {
+            span_fields.extend(fields.0.iter().cloned());
+        }
+        let context = EventContext {
+            metadata: Some(span.metadata()),
+            span_fields,
+        };
+
+        // The close log carries only the span's fields, not fields of its own
+        let field_set = span.metadata().fields();
+        let no_values: [(&tracing::field::Field, Option<&dyn tracing::Value>); 0] = [];
+        let message = format!("{} closed", span.name());
+        self.logger.log_from(*span.metadata().level(), &context, &message, &field_set.value_set(&no_values));
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        // LipService's own diagnostics and decision traces are never sampled or exported,
+        // which would recurse
+        if matches!(event.metadata().target(), INTERNAL_TARGET | DECISION_TARGET) {
+            return;
+        }
+
+        let metadata = event.metadata();
+        let level = *metadata.level();
+
+        // Logs of a sampled trace are kept so the trace stays complete
+        #[cfg(feature = "trace-correlation")]
+        if self.logger.sampler.config().follow_trace_sampling && in_sampled_trace(event, &ctx) {
+            self.keep_unsampled(event, &self.event_context(event, &ctx));
+            return;
+        }
+
+        // Once a span records an error, the rest of its events are kept for context
+        if self.logger.sampler.config().keep_errored_spans {
+            let mut flag = ErrorFlag::default();
+            event.record(&mut flag);
+            if level == tracing::Level::ERROR || flag.0 {
+                if let Some(span) = ctx.event_span(event) {
+                    mark_errored(&span);
+                }
+            }
+
+            let errored = ctx
+                .event_scope(event)
+                .is_some_and(|mut scope| scope.any(|span| span.extensions().get::<SpanErrored>().is_some()));
+            if errored {
+                self.keep_unsampled(event, &self.event_context(event, &ctx));
+                return;
+            }
+        }
+
+        // Other events inside a dropped span (or any of its descendants) are skipped
+        // outright, but errors still go through sampling, which always keeps them
+        let in_dropped_span = ctx.event_scope(event).is_some_and(|mut scope| {
+            scope.any(|span| span.extensions().get::<SpanSamplingDecision>().is_some_and(|decision| !decision.keep))
+        });
+        if in_dropped_span && level != tracing::Level::ERROR {
+            return;
+        }
+
+        // Decide on the static callsite before formatting or extracting anything, span
+        // fields included, so dropped events cost a map lookup and no allocation
+        if self.logger.sampler.config().callsite_sampling {
+            if !self.logger.sampler.should_sample_callsite(metadata) {
+                return;
+            }
+
+            let context = self.event_context(event, &ctx);
+            let message = event_message(event, &self.logger.sampler.config().message_field_names);
+            self.logger.emit_from(level, &context, &message, event.field_set());
+            return;
+        }
+
+        let context = self.event_context(event, &ctx);
+        let message = event_message(event, &self.logger.sampler.config().message_field_names);
+        
+        self.logger.log_from(level, &context, &message, event.field_set());
+    }
+}
+
+/// Whether the trace an event belongs to was sampled by OpenTelemetry
+///
+/// Uses the innermost span with a decision: its own sampling result once made,
+/// otherwise the trace flags of its parent context (e.g. a propagated remote parent).
+#[cfg(feature = "trace-correlation")]
+fn in_sampled_trace<S>(event: &tracing::Event<'_>, ctx: &tracing_subscriber::layer::Context<'_, S>) -> bool
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    use opentelemetry::trace::{SamplingDecision, TraceContextExt};
+
+    let Some(scope) = ctx.event_scope(event) else {
+        return false;
+    };
+    for span in scope {
+        let extensions = span.extensions();
+        let Some(otel) = extensions.get::<tracing_opentelemetry::OtelData>() else {
+            continue;
+        };
+        if let Some(result) = &otel.builder.sampling_result {
+            return result.decision == SamplingDecision::RecordAndSample;
+        }
+        if otel.parent_cx.has_active_span() {
+            return otel.parent_cx.span().span_context().is_sampled();
+        }
+    }
+    false
+}
+
+/// Collects field names and formatted values
+struct FieldCollector(Vec<(&'static str, String)>);
+
+impl tracing::field::Visit for FieldCollector {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.push((field.name(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name(), format!("{:?}", value)));
+    }
+}
+
+/// Body of an event: the first configured message field it carries
+fn event_message(event: &tracing::Event<'_>, message_fields: &[String]) -> String {
+    let mut collector = FieldCollector(Vec::new());
+    event.record(&mut collector);
+
+    message_fields
+        .iter()
+        .find_map(|name| collector.0.iter().find(|(field, _)| field == name))
+        .map(|(_, value)| value.clone())
+        .unwrap_or_else(|| format!("{}", event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bucketing, Config, LoadShedStep, PatternRule};
+
+    #[tokio::test]
+    async fn test_posthog_exporter_creation() {
+        let config = Config {
+            service_name: "test-service".to_string(),
+            lipservice_url: "http://localhost:8000".to_string(),
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        let exporter = PostHogExporter::new(config).await;
+        // This might fail in tests due to network, but we can test the creation
+        // In a real test environment, you'd mock the HTTP client
+        assert!(exporter.is_ok() || exporter.is_err());
+    }
+
+    #[test]
+    fn test_extra_export_headers() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            ..Default::default()
+        }
+        .with_export_header("X-Scope-OrgID".to_string(), "tenant-a".to_string())
+        .with_export_header("authorization".to_string(), "Bearer spoofed".to_string());
+
+        let headers = PostHogExporter::export_headers(&config);
+        assert_eq!(headers.get("X-Scope-OrgID"), Some(&"tenant-a".to_string()));
+        assert_eq!(headers.get("Authorization"), Some(&"Bearer phc_test".to_string()));
+        assert!(!headers.contains_key("authorization"));
+
+        let config = Config {
+            override_auth_headers: true,
+            ..config
+        };
+        let headers = PostHogExporter::export_headers(&config);
+        assert_eq!(headers.get("authorization"), Some(&"Bearer spoofed".to_string()));
+        assert!(!headers.contains_key("Authorization"));
+    }
+
+    #[test]
+    fn test_attribute_prefix() {
+        assert_eq!(prefixed_key("user_id", Some("app")), "app.user_id");
+        assert_eq!(prefixed_key("user_id", Some("app.")), "app.user_id");
+        assert_eq!(prefixed_key("user_id", None), "user_id");
+
+        // Reserved OTLP/trace attributes keep their names
+        assert_eq!(prefixed_key("trace_id", Some("app")), "trace_id");
+        assert_eq!(prefixed_key("service.name", Some("app")), "service.name");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_span_skips_events() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            sample_spans: true,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^noisy_span$", 0.0))
+        .with_pattern_rule(PatternRule::new("^kept_span$", 1.0));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler.clone(), None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("noisy_span").in_scope(|| {
+                tracing::info!("inside dropped span");
+                tracing::info_span!("child_span").in_scope(|| tracing::info!("inside dropped child span"));
+            });
+            tracing::info_span!("kept_span").in_scope(|| tracing::info!("inside kept span"));
+        });
+
+        // Only the event under the kept span reached the sampler
+        assert_eq!(sampler.counters().logs_seen(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_in_dropped_span_are_exported() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let processor = InMemoryProcessor::default();
+        let attached = processor.clone();
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 0,
+            sample_spans: true,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^noisy_span$", 0.0))
+        .with_log_processor(move || attached.clone());
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config.clone()).unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("noisy_span").in_scope(|| {
+                tracing::info!("inside dropped span");
+                tracing::error!("payment failed inside dropped span");
+            });
+        });
+
+        let records = processor.0.lock();
+        assert_eq!(records.len(), 1);
+        let body = Some(opentelemetry::logs::AnyValue::from("payment failed inside dropped span".to_string()));
+        assert_eq!(records[0].record.body, body);
+    }
+
+    #[tokio::test]
+    async fn test_reservoir_samples_are_counted_and_flushed_on_shutdown() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let processor = InMemoryProcessor::default();
+        let attached = processor.clone();
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 0,
+            reservoir_size: Some(2),
+            always_drop_patterns: vec!["^health check".to_string()],
+            ..Default::default()
+        }
+        .with_log_processor(move || attached.clone());
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config.clone()).unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(Arc::clone(&sampler), Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(Arc::clone(&logger)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..10 {
+                tracing::info!("job {} done", i);
+                tracing::info!("health check ok");
+            }
+        });
+        assert!(processor.0.lock().is_empty());
+        assert_eq!(sampler.counters().logs_seen(), 20);
+        assert_eq!(sampler.counters().logs_sampled(), 0);
+
+        // Nothing listens on the endpoint, so only the reservoir flush matters here;
+        // blocklisted logs never entered the reservoir
+        let _ = logger.shutdown().await;
+        assert_eq!(processor.0.lock().len(), 2);
+        assert_eq!(sampler.counters().logs_sampled(), 2);
+        let stats = sampler.get_pattern_stats();
+        assert_eq!((stats.len(), stats[0].count, stats[0].sampled_count), (1, 10, 2));
+    }
+
+    #[cfg(feature = "trace-correlation")]
+    #[tokio::test]
+    async fn test_logs_in_sampled_trace_are_kept() {
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider,
+        };
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            follow_trace_sampling: true,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^cart updated$", 0.0));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler.clone(), None));
+        let tracer = opentelemetry_sdk::trace::TracerProvider::default().tracer("test");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .with(LipServiceLayer::new(logger));
+
+        let remote_parent = |flags| {
+            opentelemetry::Context::new().with_remote_span_context(SpanContext::new(
+                TraceId::from_u128(1),
+                SpanId::from_u64(1),
+                flags,
+                true,
+                TraceState::default(),
+            ))
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            let sampled = tracing::info_span!("checkout");
+            sampled.set_parent(remote_parent(TraceFlags::SAMPLED));
+            sampled.in_scope(|| (0..10).for_each(|_| tracing::info!("cart updated")));
+
+            let unsampled = tracing::info_span!("browse");
+            unsampled.set_parent(remote_parent(TraceFlags::default()));
+            unsampled.in_scope(|| tracing::info!("cart updated"));
+        });
+
+        // Every log of the sampled trace is kept despite the 0.0 rule
+        assert_eq!(sampler.counters().logs_seen(), 11);
+        assert_eq!(sampler.counters().logs_sampled(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_events_after_span_error_are_kept() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            keep_errored_spans: true,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^retrying payment$", 0.0));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler.clone(), None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("checkout").in_scope(|| {
+                tracing::info!("retrying payment");
+                tracing::error!("payment declined");
+                (0..5).for_each(|_| tracing::info!("retrying payment"));
+            });
+            tracing::info_span!("browse").in_scope(|| tracing::info!("retrying payment"));
+        });
+
+        // Only the events before the error and outside the errored span were dropped
+        assert_eq!(sampler.counters().logs_seen(), 8);
+        assert_eq!(sampler.counters().logs_sampled(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_msg_field_becomes_body() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_message_field_names(vec!["message".to_string(), "msg".to_string()])
+        .with_pattern_rule(PatternRule::new("^payment captured$", 0.0));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler.clone(), None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(msg = "payment captured", order_id = 7);
+        });
+
+        // The rule only matches if `msg` was used as the body
+        assert_eq!(sampler.counters().logs_seen(), 1);
+        assert_eq!(sampler.counters().logs_sampled(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_timed_exporter_records_latency() {
+        #[derive(Debug)]
+        struct SlowExporter;
+
+        #[async_trait]
+        impl LogExporter for SlowExporter {
+            async fn export(&mut self, _batch: Vec<LogData>) -> LogResult<()> {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                Ok(())
+            }
+        }
+
+        let latency = Arc::new(LatencyHistogram::new());
+        let mut exporter = TimedExporter {
+            inner: SlowExporter,
+            latency: Arc::clone(&latency),
+            outcomes: Arc::new(ExportOutcomes::new()),
+            promoted: Arc::new(PromotedAttributes::new(Vec::new(), Resource::empty())),
+            rate_limit: Arc::new(RateLimitPause::default()),
+            max_retries: 0,
+        };
+        for _ in 0..3 {
+            exporter.export(Vec::new()).await.unwrap();
+        }
+
+        let snapshot = latency.snapshot();
+        assert_eq!(snapshot.count, 3);
+        assert!(snapshot.p50 >= std::time::Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_duplicate_attribute_keys() {
+        let attributes = || {
+            vec![
+                KeyValue::new("route", "/cart"),
+                KeyValue::new("user_id", "42"),
+                KeyValue::new("route", "/cart/items"),
+            ]
+        };
+        let resolved = |policy| -> Vec<(String, String)> {
+            resolve_duplicate_keys(attributes(), policy)
+                .into_iter()
+                .map(|attribute| (attribute.key.as_str().to_string(), attribute.value.as_str().into_owned()))
+                .collect()
+        };
+        let pair = |key: &str, value: &str| (key.to_string(), value.to_string());
+
+        assert_eq!(resolved(DuplicateKeys::LastWins), [pair("route", "/cart/items"), pair("user_id", "42")]);
+        assert_eq!(resolved(DuplicateKeys::FirstWins), [pair("route", "/cart"), pair("user_id", "42")]);
+        assert_eq!(
+            resolved(DuplicateKeys::Suffix),
+            [pair("route", "/cart"), pair("user_id", "42"), pair("route_2", "/cart/items")]
+        );
+    }
+
+    #[test]
+    fn test_capture_event_shape() {
+        let config = Config {
+            service_name: "checkout".to_string(),
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_export_mode: ExportMode::Capture,
+            ..Default::default()
+        };
+        let attributes = vec![KeyValue::new("user_id", "42")];
+
+        let event = capture_event(&config, "Order placed", "INFO", SystemTime::UNIX_EPOCH, &attributes);
+
+        assert_eq!(event["api_key"], "phc_test");
+        assert_eq!(event["event"], CAPTURE_EVENT_NAME);
+        assert_eq!(event["distinct_id"], "checkout");
+        assert_eq!(event["timestamp"], "1970-01-01T00:00:00+00:00");
+        assert_eq!(event["properties"]["message"], "Order placed");
+        assert_eq!(event["properties"]["severity"], "INFO");
+        assert_eq!(event["properties"]["service"], "checkout");
+        assert_eq!(event["properties"]["user_id"], "42");
+    }
+
+    #[test]
+    fn test_build_info_on_exported_records() {
+        let config = Config::default().with_build_info(Some("deploy-42".to_string()), Some("abc123".to_string()));
+
+        let event = capture_event(&config, "Order placed", "INFO", SystemTime::UNIX_EPOCH, &[]);
+        assert_eq!(event["properties"]["deployment.id"], "deploy-42");
+        assert_eq!(event["properties"]["git.sha"], "abc123");
+
+        let keys: Vec<String> = build_attributes(&config).iter().map(|kv| kv.key.as_str().to_string()).collect();
+        assert_eq!(keys, vec!["deployment.id", "git.sha"]);
+        assert!(build_attributes(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn test_distinct_id_field() {
+        let config = Config {
+            service_name: "checkout".to_string(),
+            distinct_id_field: Some("user_id".to_string()),
+            attribute_prefix: Some("app".to_string()),
+            ..Default::default()
+        };
+
+        let with_user = vec![KeyValue::new("app.user_id", "user-42")];
+        let event = capture_event(&config, "Order placed", "INFO", SystemTime::UNIX_EPOCH, &with_user);
+        assert_eq!(event["distinct_id"], "user-42");
+
+        // Logs without the field fall back to the service identity
+        let event = capture_event(&config, "Cache warmed", "INFO", SystemTime::UNIX_EPOCH, &[]);
+        assert_eq!(event["distinct_id"], "checkout");
+    }
+
+    #[tokio::test]
+    async fn test_exported_body_is_sanitized() {
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::OK]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            ..Default::default()
+        };
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        exporter.export_log("disk\0 full\x07 on /dev/sda", "ERROR", SystemTime::now(), Vec::new()).unwrap();
+
+        let request = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+        assert_eq!(request.json()["batch"][0]["properties"]["message"], "disk\u{FFFD} full\u{FFFD} on /dev/sda");
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_capture_honors_retry_after() {
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::RATE_LIMITED, MockResponse::OK]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            ..Default::default()
+        };
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while exporter.health().last_success.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("export should succeed after the pause");
+
+        let (first, retry) = (requests.try_recv().unwrap(), requests.try_recv().unwrap());
+        assert!(requests.try_recv().is_err());
+        assert!(retry.received_at - first.received_at >= Duration::from_secs(1));
+        assert_eq!(exporter.deferred_logs(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_otlp_export_honors_retry_after() {
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::RATE_LIMITED, MockResponse::OK]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: endpoint,
+            export_queue_size: 0,
+            ..Default::default()
+        };
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+        exporter.shutdown().await.unwrap();
+
+        let (first, retry) = (requests.try_recv().unwrap(), requests.try_recv().unwrap());
+        assert!(requests.try_recv().is_err(), "the rate-limited batch should be retried once");
+        assert!(retry.received_at - first.received_at >= Duration::from_secs(1));
+        assert_eq!(exporter.deferred_logs(), 1);
+        assert!(exporter.health().last_success.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_export_concurrency_overlaps_requests() {
+        // OTLP endpoint holding each request briefly
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::OK.delayed(Duration::from_millis(300))]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: endpoint,
+            batch_size: 1,
+            export_queue_size: 0,
+            ..Default::default()
+        }
+        .with_export_concurrency(4);
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        assert_eq!(exporter.logger_providers.len(), 4);
+        for i in 0..8 {
+            exporter.export_log(&format!("Order {} placed", i), "INFO", SystemTime::now(), Vec::new()).unwrap();
+        }
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while requests.recv().await.unwrap().in_flight < 2 {}
+        })
+        .await
+        .expect("exports should overlap");
+    }
+
+    /// Processor keeping every record it's given
+    #[derive(Debug, Clone, Default)]
+    struct InMemoryProcessor(Arc<parking_lot::Mutex<Vec<LogData>>>);
+
+    impl LogProcessor for InMemoryProcessor {
+        fn emit(&self, data: LogData) {
+            self.0.lock().push(data);
+        }
+
+        fn force_flush(&self) -> LogResult<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> LogResult<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_queue_backlog_sheds_info_logs() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 4,
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_load_shedding_ladder(vec![LoadShedStep::new(0.5, "INFO")]);
+        let sampler = Arc::new(AdaptiveSampler::new_standalone(config.clone()).unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(Arc::clone(&sampler), Some(Arc::clone(&exporter))));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // The single-threaded test runtime doesn't drain the queue until we yield, so
+        // INFO is shed once the queue is half full
+        for i in 0..10 {
+            tracing::info!("request {} served", i);
+        }
+        assert_eq!(sampler.counters().logs_sampled(), 2);
+        assert_eq!(sampler.congestion(), 0.5);
+
+        // Once the queue drains, INFO flows again
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while exporter.congestion() > 0.0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("queued logs should be drained");
+        tracing::info!("request served after the backlog");
+        assert_eq!(sampler.counters().logs_sampled(), 3);
+        assert_eq!(sampler.congestion(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_log_processors_receive_exported_logs() {
+        let processor = InMemoryProcessor::default();
+        let attached = processor.clone();
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 0,
+            ..Default::default()
+        }
+        .with_log_processor(move || attached.clone());
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        assert_eq!(exporter.logger_providers().len(), 1);
+
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+
+        let records = processor.0.lock();
+        assert_eq!(records.len(), 1);
+        let body = Some(opentelemetry::logs::AnyValue::from("Order placed".to_string()));
+        assert_eq!(records[0].record.body, body);
+    }
+
+    #[tokio::test]
+    async fn test_instrumentation_scope_follows_the_target() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let processor = InMemoryProcessor::default();
+        let attached = processor.clone();
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 0,
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_instrumentation_scope("checkout-service")
+        .with_log_processor(move || attached.clone());
+        let exporter = Arc::new(PostHogExporter::new(config.clone()).await.unwrap());
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(Arc::clone(&exporter))));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!(target: "myapp::db", "query ran"));
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+
+        let records = processor.0.lock();
+        let scopes: Vec<&str> = records.iter().map(|data| data.instrumentation.name.as_ref()).collect();
+        assert_eq!(scopes, ["myapp::db", "checkout-service"]);
+    }
+
+    #[tokio::test]
+    async fn test_promoted_attributes_move_to_the_resource() {
+        let processor = InMemoryProcessor::default();
+        let attached = processor.clone();
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: "http://127.0.0.1:1".to_string(),
+            export_queue_size: 0,
+            ..Default::default()
+        }
+        .with_promoted_attribute("region".to_string())
+        .with_log_processor(move || attached.clone());
+        let exporter = PostHogExporter::new(config).await.unwrap();
+
+        for order_id in [1, 2] {
+            let attributes = vec![KeyValue::new("region", "eu-west-1"), KeyValue::new("order_id", order_id)];
+            exporter.export_log("Order placed", "INFO", SystemTime::now(), attributes).unwrap();
+        }
+
+        // Records that never carried the key keep the plain resource
+        let attributes = vec![KeyValue::new("order_id", 3)];
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), attributes).unwrap();
+
+        let records = processor.0.lock();
+        assert_eq!(records.len(), 3);
+        for data in &records[..2] {
+            assert_eq!(data.resource.get("region".into()), Some("eu-west-1".into()));
+            assert_eq!(data.resource.get("service.name".into()), Some("lipservice-service".into()));
+            let keys: Vec<&str> = data.record.attributes.iter().flatten().map(|(key, _)| key.as_str()).collect();
+            assert_eq!(keys, ["order_id"]);
+        }
+        // Both records share one resource instead of a copy each
+        assert!(std::ptr::eq(records[0].resource.as_ref(), records[1].resource.as_ref()));
+        assert_eq!(records[2].resource.get("region".into()), None);
+        assert_eq!(records[2].resource.get("service.name".into()), Some("lipservice-service".into()));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_the_last_batch() {
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::OK]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: endpoint,
+            ..Default::default()
+        };
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        exporter.export_log("Last words", "INFO", SystemTime::now(), Vec::new()).unwrap();
+        assert!(requests.try_recv().is_err(), "a single log stays batched until flushed");
+
+        exporter.shutdown().await.unwrap();
+        let request = requests.try_recv().expect("shutdown should export the partial batch");
+        assert!(request.head.starts_with("POST /api/v1/otlp/v1/logs"), "{}", request.head);
+    }
+
+    /// One scripted answer of [`mock_endpoint`]
+    #[derive(Debug, Clone, Copy)]
+    struct MockResponse {
+        status: &'static str,
+        /// Extra header lines, each ending in CRLF
+        headers: &'static str,
+        /// How long the answer is held back
+        delay: Duration,
+    }
+
+    impl MockResponse {
+        const OK: Self = Self {
+            status: "200 OK",
+            headers: "",
+            delay: Duration::ZERO,
+        };
+
+        /// Asks to retry after one second
+        const RATE_LIMITED: Self = Self {
+            status: "429 Too Many Requests",
+            headers: "retry-after: 1\r\n",
+            delay: Duration::ZERO,
+        };
+
+        fn delayed(self, delay: Duration) -> Self {
+            Self { delay, ..self }
+        }
+    }
+
+    /// A request received by [`mock_endpoint`]
+    #[derive(Debug)]
+    struct MockRequest {
+        /// Request line and headers
+        head: String,
+        body: Vec<u8>,
+        received_at: Instant,
+        /// Requests being answered when this one arrived, itself included
+        in_flight: usize,
+    }
+
+    impl MockRequest {
+        fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+    }
+
+    /// Local HTTP endpoint answering requests with `script` in turn, repeating its last
+    /// response, and forwarding every request before answering it
+    async fn mock_endpoint(script: Vec<MockResponse>) -> (String, tokio::sync::mpsc::UnboundedReceiver<MockRequest>) {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            for served in 0.. {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let response = script[served.min(script.len() - 1)];
+                let (request_tx, in_flight) = (request_tx.clone(), Arc::clone(&in_flight));
+                tokio::spawn(async move {
+                    let Some((head, body)) = read_request(&mut socket).await else {
+                        return;
+                    };
+                    let _ = request_tx.send(MockRequest {
+                        head,
+                        body,
+                        received_at: Instant::now(),
+                        in_flight: in_flight.fetch_add(1, Ordering::SeqCst) + 1,
+                    });
+                    tokio::time::sleep(response.delay).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let reply = format!(
+                        "HTTP/1.1 {}\r\n{}content-length: 0\r\nconnection: close\r\n\r\n",
+                        response.status, response.headers
+                    );
+                    let _ = socket.write_all(reply.as_bytes()).await;
+                });
+            }
+        });
+        (endpoint, request_rx)
+    }
+
+    /// Read a request's head and its `content-length` body
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> Option<(String, Vec<u8>)> {
+        use tokio::io::AsyncReadExt;
+
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let head_len = loop {
+            if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end + 4;
+            }
+            match socket.read(&mut buf).await {
+                Ok(0) | Err(_) => return None,
+                Ok(read) => request.extend_from_slice(&buf[..read]),
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_len]).into_owned();
+        let body_len: usize = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0);
+        while request.len() < head_len + body_len {
+            match socket.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(read) => request.extend_from_slice(&buf[..read]),
+            }
+        }
+        Some((head, request.split_off(head_len)))
+    }
+
+    /// Capture endpoint forwarding every event of the batches it receives
+    async fn capture_endpoint() -> (String, tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (endpoint, mut requests) = mock_endpoint(vec![MockResponse::OK]).await;
+        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                assert!(request.head.starts_with("POST /batch/"), "{}", request.head);
+                for event in request.json()["batch"].as_array().unwrap() {
+                    let _ = event_tx.send(event.clone());
+                }
+            }
+        });
+        (endpoint, event_rx)
+    }
+
+    #[tokio::test]
+    async fn test_capture_batches_logs_from_any_thread() {
+        let (endpoint, mut events) = capture_endpoint().await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            ..Default::default()
+        };
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+
+        // A plain thread has no tokio runtime to spawn on
+        let sync_exporter = Arc::clone(&exporter);
+        std::thread::spawn(move || {
+            for i in 0..3 {
+                sync_exporter.export_log(&format!("job {} done", i), "INFO", SystemTime::now(), Vec::new()).unwrap();
+            }
+        })
+        .join()
+        .unwrap();
+
+        exporter.shutdown().await.unwrap();
+        for i in 0..3 {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+            assert_eq!(event["properties"]["message"], format!("job {} done", i));
+        }
+        assert_eq!(exporter.latency_snapshot().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_exports_use_export_timeout() {
+        // Answers well after the control-plane timeout but within the export timeout
+        let (endpoint, _requests) = mock_endpoint(vec![MockResponse::OK.delayed(Duration::from_millis(300))]).await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            max_retries: 0,
+            ..Default::default()
+        }
+        .with_timeout(Duration::from_millis(50))
+        .with_export_timeout(Duration::from_secs(5));
+        let exporter = PostHogExporter::new(config).await.unwrap();
+
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while exporter.health().last_success.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("slow export should succeed within the export timeout");
+        assert_eq!(exporter.health().consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_logs_routed_to_team_exporters() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (endpoint_a, mut team_a) = capture_endpoint().await;
+        let (endpoint_b, mut team_b) = capture_endpoint().await;
+        let config = Config {
+            default_sampling_rate: 1.0,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            ..Default::default()
+        }
+        .with_team_target(TeamTarget::new("serviceA", "1", "phc_team_a").with_endpoint(endpoint_a))
+        .with_team_target(TeamTarget::new("serviceB", "2", "phc_team_b").with_endpoint(endpoint_b));
+
+        let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await.unwrap());
+        let exporters = PostHogExporter::for_teams(&config).await.unwrap();
+        assert_eq!(exporters.len(), 2);
+        let logger = Arc::new(LipServiceLogger::new(sampler, None).with_team_exporters(exporters));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(service = "serviceA", "charge created");
+            tracing::info!(service = "serviceB", "shipment booked");
+        });
+
+        let timeout = Duration::from_secs(5);
+        let event_a = tokio::time::timeout(timeout, team_a.recv()).await.unwrap().unwrap();
+        let event_b = tokio::time::timeout(timeout, team_b.recv()).await.unwrap().unwrap();
+        assert_eq!(event_a["api_key"], "phc_team_a");
+        assert_eq!(event_a["properties"]["message"], "charge created");
+        assert_eq!(event_b["api_key"], "phc_team_b");
+        assert_eq!(event_b["properties"]["message"], "shipment booked");
+        assert!(team_a.try_recv().is_err());
+        assert!(team_b.try_recv().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_endpoint() {
+        let path = std::env::temp_dir().join(format!("lipservice-otlp-{}.sock", uuid::Uuid::new_v4()));
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_endpoint: format!("unix://{}", path.display()),
+            export_queue_size: 0,
+            ..Default::default()
+        };
+
+        let err = PostHogExporter::new(config.clone()).await.err().unwrap();
+        assert!(matches!(err.downcast_ref::<LipServiceError>(), Some(LipServiceError::SocketNotFound { .. })));
+
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        assert_eq!(exporter.logger_providers.len(), 1);
+        exporter.export_log("Order placed", "INFO", SystemTime::now(), Vec::new()).unwrap();
+
+        // The batch flush connects to the socket rather than over TCP
+        let accepted = tokio::time::timeout(Duration::from_secs(5), listener.accept()).await;
+        std::fs::remove_file(&path).unwrap();
+        assert!(accepted.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_critical_severity_text_preserved() {
+        let (endpoint, mut bodies) = capture_endpoint().await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            preserve_severity_text: true,
+            ..Default::default()
+        };
+        let exporter = PostHogExporter::new(config).await.unwrap();
+
+        exporter.export_log("Disk array degraded", "critical", SystemTime::now(), Vec::new()).unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(event["properties"]["severity"], "CRITICAL");
+        assert!(exporter.stats().bytes_by_severity.contains_key("FATAL"));
+    }
+
+    #[tokio::test]
+    async fn test_sample_rate_attribute() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (endpoint, mut bodies) = capture_endpoint().await;
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            attach_sampling_metadata: true,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^cache", 0.5));
+        let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await.unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..20 {
+                tracing::info!("cache refreshed");
+            }
+            tracing::error!("payment failed");
+        });
+
+        let mut rates = HashMap::new();
+        while rates.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap().unwrap();
+            let message = event["properties"]["message"].as_str().unwrap().to_string();
+            rates.insert(message, event["properties"]["lipservice.sample_rate"].clone());
+        }
+        assert_eq!(rates["cache refreshed"], "0.5");
+        assert_eq!(rates["payment failed"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_source_location_attributes() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (endpoint, mut bodies) = capture_endpoint().await;
+        let config = Config {
+            default_sampling_rate: 1.0,
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            capture_source_location: true,
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await.unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        let line = line!() + 1;
+        tracing::subscriber::with_default(subscriber, || tracing::info!("cache warmed"));
+
+        let event = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(event["properties"]["code.filepath"], file!());
+        assert_eq!(event["properties"]["code.lineno"], line.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_sampled_log_callback() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let config = Config {
+            default_sampling_rate: 1.0,
+            always_drop_patterns: vec!["^heartbeat".to_string()],
+            ..Default::default()
+        }
+        .with_on_sampled_log(move |log: &SampledLog| sink.lock().push(log.clone()));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(Arc::clone(&sampler), None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(order_id = 7, "order placed");
+            tracing::info!("heartbeat ok");
+            tracing::error!("payment failed");
+        });
+
+        let received = received.lock();
+        let bodies: Vec<&str> = received.iter().map(|log| log.body.as_str()).collect();
+        assert_eq!(bodies, ["order placed", "payment failed"]);
+        assert_eq!(received[0].severity, Severity::Info);
+        assert!(received[0].attributes.contains(&("order_id".to_string(), "7".to_string())));
+        assert_eq!(received[0].signature, sampler.signature_for("order placed", &[("order_id", "7")]));
+        assert_eq!(received[1].severity, Severity::Error);
+    }
+
+    #[tokio::test]
+    async fn test_decisions_are_traced_without_recursing() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        /// Records the target and fields of every event
+        struct Recorder(Arc<parking_lot::Mutex<Vec<(String, Vec<(&'static str, String)>)>>>);
+
+        impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Recorder {
+            fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+                let mut fields = FieldCollector(Vec::new());
+                event.record(&mut fields);
+                self.0.lock().push((event.metadata().target().to_string(), fields.0));
+            }
+        }
+
+        let config = Config {
+            default_sampling_rate: 1.0,
+            always_drop_patterns: vec!["^heartbeat".to_string()],
+            ..Default::default()
+        }
+        .with_decision_tracing();
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(Arc::clone(&sampler), None));
+        let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry()
+            .with(LipServiceLayer::new(logger))
+            .with(Recorder(Arc::clone(&events)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("order placed");
+            tracing::info!("heartbeat ok");
+        });
+
+        let events = events.lock();
+        let decisions: Vec<_> = events.iter().filter(|(target, _)| target == DECISION_TARGET).collect();
+        assert_eq!(decisions.len(), 2, "one trace per decision, none for the traces themselves");
+        let field = |fields: &[(&'static str, String)], name: &str| {
+            fields.iter().find(|(key, _)| *key == name).map(|(_, value)| value.clone())
+        };
+        assert_eq!(field(&decisions[0].1, "message").as_deref(), Some("Log kept"));
+        assert_eq!(field(&decisions[0].1, "signature"), Some(sampler.signature_for("order placed", &[])));
+        assert_eq!(field(&decisions[1].1, "message").as_deref(), Some("Log dropped"));
+        assert_eq!(field(&decisions[1].1, "reason").as_deref(), Some("Blocklisted"));
+        assert_eq!(field(&decisions[1].1, "severity").as_deref(), Some("INFO"));
+    }
+
+    #[tokio::test]
+    async fn test_numeric_attributes_are_bucketed() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let config = Config {
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_attribute_bucket("latency_ms", Bucketing::PowersOfTwo)
+        .with_attribute_bucket("size_kb", Bucketing::Linear { width: 100.0 })
+        .with_on_sampled_log(move |log: &SampledLog| sink.lock().push(log.clone()));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(latency_ms = 1500, size_kb = 250.5, retries = 3, "request served");
+        });
+
+        let received = received.lock();
+        let attribute = |key: &str| {
+            received[0].attributes.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+        };
+        assert_eq!(attribute("latency_ms"), Some("1024-2048"));
+        assert_eq!(attribute("latency_ms.raw"), Some("1500"));
+        assert_eq!(attribute("size_kb"), Some("200-300"));
+        assert_eq!(attribute("retries"), Some("3"));
+        assert_eq!(attribute("retries.raw"), None);
+    }
+
+    /// Field value whose Debug output is far larger than any useful attribute
+    struct Blob(Vec<u8>);
+
+    impl std::fmt::Debug for Blob {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_list().entries(&self.0).finish()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_debug_values_are_capped_and_tagged() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let config = Config {
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_on_sampled_log(move |log: &SampledLog| sink.lock().push(log.clone()));
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, None));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(payload = ?Blob(vec![7; 10_000]), attempts = 3u64, cached = false, "blob stored");
+        });
+
+        let received = received.lock();
+        let attribute = |key: &str| {
+            received[0].attributes.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone())
+        };
+        let payload = attribute("payload").unwrap();
+        assert!(payload.len() <= MAX_DEBUG_VALUE_LEN + '…'.len_utf8());
+        assert!(payload.starts_with("[7, 7") && payload.ends_with('…'));
+        assert_eq!(attribute("payload.debug_formatted").as_deref(), Some("true"));
+        assert_eq!(attribute("attempts").as_deref(), Some("3"));
+        assert_eq!(attribute("cached").as_deref(), Some("false"));
+        assert_eq!(attribute("attempts.debug_formatted"), None);
+    }
+
+    /// Writer that records what was written and from which thread
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<parking_lot::Mutex<(Vec<u8>, Vec<std::thread::ThreadId>)>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut inner = self.0.lock();
+            inner.0.extend_from_slice(buf);
+            inner.1.push(std::thread::current().id());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_blocking_local_writer() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config {
+            default_sampling_rate: 1.0,
+            always_drop_patterns: vec!["^heartbeat".to_string()],
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new(config).await.unwrap());
+        let buffer = SharedBuffer::default();
+        let (logger, guard) = LipServiceLogger::new(sampler, None).with_non_blocking_writer(buffer.clone());
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(Arc::new(logger)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("order placed");
+            tracing::info!("heartbeat ok");
+            tracing::error!("payment failed");
+        });
+        // Dropping the guard flushes the buffered lines
+        drop(guard);
+
+        let inner = buffer.0.lock();
+        let output = String::from_utf8_lossy(&inner.0);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" INFO order placed"));
+        assert!(lines[1].ends_with(" ERROR payment failed"));
+        // Written by the worker thread, not the logging thread
+        assert!(!inner.1.is_empty());
+        assert!(inner.1.iter().all(|id| *id != std::thread::current().id()));
+    }
+
+    #[tokio::test]
+    async fn test_span_close_log_carries_duration() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (endpoint, mut bodies) = capture_endpoint().await;
+        let config = Config {
+            default_sampling_rate: 1.0,
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            span_close_logs: true,
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await.unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("checkout", cart_id = "c-1");
+            span.in_scope(|| std::thread::sleep(Duration::from_millis(5)));
+        });
+
+        let event = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(event["properties"]["message"], "checkout closed");
+        assert_eq!(event["properties"]["span.name"], "checkout");
+        assert_eq!(event["properties"]["cart_id"], "c-1");
+        let duration: f64 = event["properties"]["span.duration_ms"].as_str().unwrap().parse().unwrap();
+        assert!(duration >= 5.0, "duration {}", duration);
+    }
+
+    #[tokio::test]
+    async fn test_span_fields_are_inherited() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (endpoint, mut bodies) = capture_endpoint().await;
+        let config = Config {
+            default_sampling_rate: 1.0,
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_endpoint: endpoint,
+            posthog_export_mode: ExportMode::Capture,
+            export_queue_size: 0,
+            inherit_span_fields: true,
+            ..Default::default()
+        };
+        let sampler = Arc::new(AdaptiveSampler::new(config.clone()).await.unwrap());
+        let exporter = Arc::new(PostHogExporter::new(config).await.unwrap());
+        let logger = Arc::new(LipServiceLogger::new(sampler, Some(exporter)));
+        let subscriber = tracing_subscriber::registry().with(LipServiceLayer::new(logger));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request", request_id = "req-42", route = "/cart").in_scope(|| {
+                tracing::info!("cart loaded");
+                tracing::info!(route = "/cart/items", "item added");
+            });
+        });
+
+        let timeout = Duration::from_secs(5);
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            events.push(tokio::time::timeout(timeout, bodies.recv()).await.unwrap().unwrap());
+        }
+        events.sort_by_key(|event| event["properties"]["message"].as_str().unwrap().to_string());
+
+        assert_eq!(events[0]["properties"]["message"], "cart loaded");
+        assert_eq!(events[0]["properties"]["request_id"], "req-42");
+        assert_eq!(events[0]["properties"]["route"], "/cart");
+        // The event's own field wins over the span's
+        assert_eq!(events[1]["properties"]["request_id"], "req-42");
+        assert_eq!(events[1]["properties"]["route"], "/cart/items");
+    }
+
+    #[tokio::test]
+    async fn test_capture_mode_skips_otlp() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            posthog_export_mode: ExportMode::Capture,
+            ..Default::default()
+        };
+
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        assert!(exporter.logger_providers.is_empty());
+        assert!(exporter.capture.is_some());
+    }
+
+    #[cfg(feature = "debug-records")]
+    #[tokio::test]
+    async fn test_last_records_keeps_most_recent() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            export_queue_size: 0,
+            recent_records_size: 3,
+            ..Default::default()
+        };
+
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        for i in 0..5 {
+            exporter.export_log(&format!("request {} served", i), "INFO", SystemTime::now(), Vec::new()).unwrap();
+        }
+
+        let bodies =
+            |records: Vec<ExportedRecord>| records.into_iter().map(|record| record.message).collect::<Vec<_>>();
+        assert_eq!(
+            bodies(exporter.last_records(10)),
+            vec!["request 2 served", "request 3 served", "request 4 served"]
+        );
+        assert_eq!(bodies(exporter.last_records(1)), vec!["request 4 served"]);
+    }
+
+    #[tokio::test]
+    async fn test_stats_attribute_bytes_by_severity() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            export_queue_size: 0,
+            ..Default::default()
+        };
+
+        let exporter = PostHogExporter::new(config).await.unwrap();
+        exporter.export_log("Database connection failed", "ERROR", SystemTime::now(), Vec::new()).unwrap();
+        for _ in 0..3 {
+            let attributes = vec![KeyValue::new("user_id", "42")];
+            exporter.export_log("User logged in", "INFO", SystemTime::now(), attributes).unwrap();
+        }
+
+        let stats = exporter.stats();
+        let error_bytes = stats.bytes_by_severity["ERROR"];
+        let info_bytes = stats.bytes_by_severity["INFO"];
+        assert_eq!(stats.records, 4);
+        assert!(error_bytes > 0 && info_bytes > error_bytes);
+        assert_eq!(error_bytes + info_bytes, stats.bytes);
+    }
+
+    #[test]
+    fn test_posthog_exporter_requires_runtime() {
+        let config = Config {
+            posthog_api_key: Some("phc_test".to_string()),
+            posthog_team_id: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        let err = futures::executor::block_on(PostHogExporter::new(config))
+            .err()
+            .expect("exporter creation should fail without a runtime");
+
+        assert!(matches!(
+            err.downcast_ref::<LipServiceError>(),
+            Some(LipServiceError::RuntimeRequired { .. })
+        ));
+        assert!(err.to_string().contains("tokio runtime"));
     }
 
     #[tokio::test]
@@ -0,0 +1,197 @@
+//! Export queue module
+//!
+//! This module decouples logging from export: the logging path only enqueues, and a
+//! dedicated task drains the bounded queue and exports.
+
+use crate::severity::LabeledSeverity;
+use opentelemetry::KeyValue;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
+
+/// A log waiting to be exported
+#[derive(Debug)]
+pub struct QueuedLog {
+    /// Instrumentation scope, usually the tracing target; `None` uses the configured default
+    pub scope: Option<String>,
+    pub message: String,
+    pub severity: LabeledSeverity,
+    pub timestamp: SystemTime,
+    pub attributes: Vec<KeyValue>,
+}
+
+type ExportFn = Arc<dyn Fn(QueuedLog) + Send + Sync>;
+
+/// Count of items handed to an export task and not yet exported, which can be awaited
+/// to reach zero
+#[derive(Debug, Default)]
+pub(crate) struct Pending {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl Pending {
+    pub(crate) fn add(&self, n: usize) {
+        self.count.fetch_add(n, Ordering::Acquire);
+    }
+
+    /// Mark `n` items as exported, waking the waiters once none are left
+    pub(crate) fn done(&self, n: usize) {
+        if self.count.fetch_sub(n, Ordering::Release) == n {
+            self.idle.notify_waiters();
+        }
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Wait until no item is pending
+    pub(crate) async fn idle(&self) {
+        loop {
+            // Registered before checking, so a `done` in between still wakes us
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.get() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Bounded queue between the logging path and a dedicated export task
+///
+/// When the queue is full, non-error logs are dropped and counted, while error logs
+/// are exported inline on the caller's thread so they never wait behind noise.
+pub struct ExportQueue {
+    sender: mpsc::Sender<QueuedLog>,
+    export: ExportFn,
+    dropped: AtomicU64,
+    /// Logs enqueued but not yet exported by the task
+    pending: Arc<Pending>,
+    capacity: usize,
+}
+
+impl ExportQueue {
+    /// Create a queue of `capacity` logs drained by a task on the current tokio runtime
+    pub fn spawn(capacity: usize, export: impl Fn(QueuedLog) + Send + Sync + 'static) -> Self {
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        let export: ExportFn = Arc::new(export);
+
+        let drain = Arc::clone(&export);
+        let pending = Arc::new(Pending::default());
+        let drained = Arc::clone(&pending);
+        tokio::spawn(async move {
+            while let Some(log) = receiver.recv().await {
+                drain(log);
+                drained.done(1);
+            }
+        });
+
+        Self {
+            sender,
+            export,
+            dropped: AtomicU64::new(0),
+            pending,
+            capacity,
+        }
+    }
+
+    /// Hand a log to the export task without blocking
+    pub fn push(&self, log: QueuedLog) {
+        self.pending.add(1);
+        let sent = self.sender.try_send(log);
+        if sent.is_err() {
+            self.pending.done(1);
+        }
+        match sent {
+            Ok(()) => {}
+            Err(TrySendError::Full(log)) if log.severity.severity.is_error() => (self.export)(log),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // The export task is gone with its runtime; export what we still can inline
+            Err(TrySendError::Closed(log)) => (self.export)(log),
+        }
+    }
+
+    /// Logs dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// How full the queue is, from 0.0 (empty) to 1.0 (full)
+    pub fn fill_level(&self) -> f64 {
+        (self.pending.get() as f64 / self.capacity as f64).min(1.0)
+    }
+
+    /// Wait until the export task has exported every log enqueued so far
+    pub async fn drained(&self) {
+        self.pending.idle().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    fn queued(message: &str, severity: &str) -> QueuedLog {
+        QueuedLog {
+            scope: None,
+            message: message.to_string(),
+            severity: severity.into(),
+            timestamp: SystemTime::now(),
+            attributes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_noise_but_not_errors() {
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&exported);
+        let queue = ExportQueue::spawn(2, move |log| sink.lock().push(log.message));
+
+        // The single-threaded test runtime doesn't drain until we yield
+        for i in 0..4 {
+            queue.push(queued(&format!("request {} served", i), "INFO"));
+        }
+        queue.push(queued("disk full", "ERROR"));
+
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(queue.fill_level(), 1.0);
+        assert_eq!(*exported.lock(), vec!["disk full".to_string()]);
+
+        drop(queue);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while exported.lock().len() < 3 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("queued logs should be drained");
+
+        assert_eq!(exported.lock()[1..], ["request 0 served".to_string(), "request 1 served".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_drained_wakes_once_the_last_log_is_exported() {
+        let queue = ExportQueue::spawn(4, |_| {});
+        for i in 0..3 {
+            queue.push(queued(&format!("request {} served", i), "INFO"));
+        }
+
+        tokio::time::timeout(Duration::from_secs(1), queue.drained())
+            .await
+            .expect("drained should return once the queue is empty");
+        assert_eq!(queue.fill_level(), 0.0);
+
+        // With nothing pending it returns right away
+        queue.drained().await;
+    }
+}
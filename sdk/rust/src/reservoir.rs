@@ -0,0 +1,106 @@
+//! Reservoir sampling module
+//!
+//! This module keeps a fixed-size uniform sample of each pattern's logs per window,
+//! giving every pattern the same coverage regardless of its volume.
+
+use dashmap::DashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Fixed-size uniform samples of items, kept per pattern signature
+pub struct PatternReservoir<T> {
+    size: usize,
+    reservoirs: DashMap<String, Reservoir<T>>,
+}
+
+/// One pattern's sample for the current window
+struct Reservoir<T> {
+    seen: u64,
+    samples: Vec<T>,
+    rng: u64,
+}
+
+impl<T> Reservoir<T> {
+    fn new(size: usize) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        Self {
+            seen: 0,
+            samples: Vec::with_capacity(size),
+            // xorshift must not start at zero
+            rng: seed | 1,
+        }
+    }
+
+    /// Uniform random number below `bound` (xorshift64*)
+    fn random_below(&mut self, bound: u64) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D) % bound
+    }
+}
+
+impl<T> PatternReservoir<T> {
+    /// Create a reservoir keeping up to `size` items per pattern
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            reservoirs: DashMap::new(),
+        }
+    }
+
+    /// Offer an item of the given pattern; it replaces a kept one with probability size/seen
+    pub fn offer(&self, signature: &str, item: T) {
+        let mut reservoir = self
+            .reservoirs
+            .entry(signature.to_string())
+            .or_insert_with(|| Reservoir::new(self.size));
+
+        reservoir.seen += 1;
+        if reservoir.samples.len() < self.size {
+            reservoir.samples.push(item);
+            return;
+        }
+
+        let seen = reservoir.seen;
+        let slot = reservoir.random_below(seen) as usize;
+        if slot < self.size {
+            reservoir.samples[slot] = item;
+        }
+    }
+
+    /// Take every pattern's sample, starting a new window
+    pub fn drain(&self) -> Vec<T> {
+        let signatures: Vec<String> = self.reservoirs.iter().map(|entry| entry.key().clone()).collect();
+        signatures
+            .into_iter()
+            .filter_map(|signature| self.reservoirs.remove(&signature))
+            .flat_map(|(_, reservoir)| reservoir.samples)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservoir_keeps_fixed_sample_per_window() {
+        let reservoir = PatternReservoir::new(10);
+
+        for i in 0..10_000 {
+            reservoir.offer("request served", i);
+        }
+        let window = reservoir.drain();
+        assert_eq!(window.len(), 10);
+
+        // Later arrivals make it in, not just the first ten
+        assert!(window.iter().any(|i| *i >= 10));
+
+        // Quiet patterns keep everything, and each window starts empty
+        for i in 0..3 {
+            reservoir.offer("cache warmed", i);
+        }
+        assert_eq!(reservoir.drain(), vec![0, 1, 2]);
+        assert!(reservoir.drain().is_empty());
+    }
+}
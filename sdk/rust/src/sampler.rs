@@ -1,12 +1,31 @@
-use crate::config::Config;
+use crate::audit::AuditLog;
+use crate::config::{BudgetScope, Config, EmptyMessages, NumberNormalization, ReportFormat, SignatureMode};
+use crate::error::LipServiceError;
+use crate::internal::{internal_event, DECISION_TARGET, INTERNAL_TARGET};
+use crate::policy::PolicyResponse;
+pub use crate::policy::SamplingPolicy;
+use crate::severity::Severity;
+use crate::signature::SignatureComputer;
+use crate::strategy::{key_kept, SamplingAlgorithm};
 use anyhow::Result;
-use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use dashmap::{DashMap, DashSet};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::task::JoinHandle;
 use tokio::time::interval;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, trace, Level};
+
+/// Initial delay between policy fetch retries, doubled on each attempt
+const POLICY_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Window over which volume budgets observe traffic before recomputing their rate
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
 
 /// Adaptive sampler that handles intelligent log sampling
 pub struct AdaptiveSampler {
@@ -15,70 +34,603 @@ pub struct AdaptiveSampler {
     pattern_stats: Arc<DashMap<String, PatternStats>>,
     signature_computer: Arc<SignatureComputer>,
     last_policy_update: Arc<RwLock<Instant>>,
+    policy_generation: Arc<AtomicU64>,
+    seeded_generations: DashMap<String, u64>,
+    pattern_rules: Vec<(regex::Regex, f64)>,
+    counters: Arc<SamplerCounters>,
+    represented: Arc<DashSet<String>>,
+    debounce_rules: Vec<(regex::Regex, Duration)>,
+    severity_remaps: Vec<(regex::Regex, Severity)>,
+    volume_budgets: Vec<VolumeBudgetState>,
+    last_exported: DashMap<String, Instant>,
+    policy_source: Arc<RwLock<PolicySource>>,
+    last_policy_error: Arc<RwLock<Option<PolicyError>>>,
+    http_client: reqwest::Client,
+    congestion: AtomicU64,
+    started_at: Instant,
+    audit_log: Option<AuditLog>,
+    callsite_signatures: DashMap<tracing::callsite::Identifier, String>,
+    background_tasks: parking_lot::Mutex<Vec<JoinHandle<()>>>,
+    strategy: Box<dyn SamplingAlgorithm>,
+    burst_allowances: DashMap<String, (u32, Instant)>,
+    always_keep: HashSet<String>,
+    always_drop_signatures: HashSet<String>,
+    always_drop_patterns: Vec<regex::Regex>,
+    fingerprint_rates: Option<Arc<FingerprintRates>>,
+}
+
+/// Pattern statistics report sent to the LipService backend, in the shape of its `PatternStatsRequest`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternReport {
+    pub service_name: String,
+    pub team_id: u64,
+    /// When the report was built, in Unix seconds
+    pub timestamp: f64,
+    pub patterns: Vec<PatternStat>,
+    /// Occurrences counted across the reported patterns
+    pub total_logs: u64,
+    pub unique_patterns: usize,
+}
+
+/// One pattern's statistics in a [`PatternReport`], timestamps in Unix seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternStat {
+    pub signature: String,
+    pub count: u64,
+    pub sampled_count: u64,
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+impl From<&PatternStats> for PatternStat {
+    fn from(stats: &PatternStats) -> Self {
+        Self {
+            signature: stats.signature.clone(),
+            count: stats.count,
+            sampled_count: stats.sampled_count,
+            first_seen: unix_seconds(stats.first_seen),
+            last_seen: unix_seconds(stats.last_seen),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).map_or(0.0, |since| since.as_secs_f64())
+}
+
+impl PatternReport {
+    /// Build a report of the given patterns, timestamped now
+    pub fn new(service_name: impl Into<String>, team_id: u64, patterns: &[PatternStats]) -> Self {
+        Self::with_patterns(
+            service_name.into(),
+            team_id,
+            unix_seconds(SystemTime::now()),
+            patterns.iter().map(PatternStat::from).collect(),
+        )
+    }
+
+    fn with_patterns(service_name: String, team_id: u64, timestamp: f64, patterns: Vec<PatternStat>) -> Self {
+        Self {
+            service_name,
+            team_id,
+            timestamp,
+            total_logs: patterns.iter().map(|stat| stat.count).sum(),
+            unique_patterns: patterns.len(),
+            patterns,
+        }
+    }
+
+    /// Serialize the report in the given wire format
+    pub fn encode(&self, format: ReportFormat) -> Result<Vec<u8>> {
+        Ok(match format {
+            ReportFormat::Json => serde_json::to_vec(self)?,
+            ReportFormat::MessagePack => rmp_serde::to_vec_named(self)?,
+        })
+    }
+
+    /// Split the report into self-contained reports of at most `max_bytes` each
+    ///
+    /// Sizes are measured in JSON, the larger wire format. A pattern too big on its
+    /// own still gets a report of its own.
+    pub fn chunks(&self, max_bytes: usize) -> Vec<PatternReport> {
+        let empty = |patterns: Vec<PatternStat>| {
+            PatternReport::with_patterns(self.service_name.clone(), self.team_id, self.timestamp, patterns)
+        };
+        // Totals are measured at their widest, as they grow with the chunk
+        let widest = PatternReport {
+            total_logs: u64::MAX,
+            unique_patterns: usize::MAX,
+            ..empty(Vec::new())
+        };
+        let overhead = serde_json::to_vec(&widest).map_or(0, |bytes| bytes.len());
+
+        let mut chunks = Vec::new();
+        let mut patterns = Vec::new();
+        let mut size = overhead;
+        for stats in &self.patterns {
+            // Each pattern after the first also adds a separating comma
+            let pattern_size = serde_json::to_vec(stats).map_or(0, |bytes| bytes.len()) + 1;
+            if !patterns.is_empty() && size + pattern_size > max_bytes {
+                chunks.push(empty(std::mem::take(&mut patterns)));
+                size = overhead;
+            }
+            size += pattern_size;
+            patterns.push(stats.clone());
+        }
+        if !patterns.is_empty() {
+            chunks.push(empty(patterns));
+        }
+        chunks
+    }
+
+    /// Deserialize a report from the given wire format
+    pub fn decode(bytes: &[u8], format: ReportFormat) -> Result<Self> {
+        Ok(match format {
+            ReportFormat::Json => serde_json::from_slice(bytes)?,
+            ReportFormat::MessagePack => rmp_serde::from_slice(bytes)?,
+        })
+    }
 }
 
-/// Sampling policy from LipService backend
+/// Stands in for the message in signatures of empty messages, see [`EmptyMessages::Placeholder`]
+const EMPTY_MESSAGE_PLACEHOLDER: &str = "<empty message>";
+
+/// Why a log was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// Its severity was shed because export is congested
+    LoadShed,
+    /// Its pattern was already exported within the debounce interval
+    Debounced,
+    /// A local pattern rule's rate rejected it
+    PatternRule,
+    /// The pattern's sampling rate rejected it
+    Rate,
+    /// Its pattern is on the always-drop blocklist
+    Blocklisted,
+    /// A volume budget's rate rejected it
+    Budget,
+    /// The rate set for its signature in the fingerprint rates file rejected it
+    Fingerprint,
+    /// Its message was empty or whitespace only
+    EmptyMessage,
+    /// Its session was not chosen by session sampling
+    Session,
+}
+
+/// Outcome of evaluating a single log
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Decision {
+    /// Kept with this probability (1.0 when always kept), or `None` when the log was
+    /// kept by a rule that isn't a probability, e.g. keep-one, bursts or debouncing
+    Keep(Option<f64>),
+    Drop(DropReason),
+}
+
+impl Decision {
+    /// Keep with probability `rate` if `kept`, otherwise drop for `reason`
+    fn keep_if(kept: bool, rate: f64, reason: DropReason) -> Self {
+        Self::keep_unweighted_if(kept, reason).with_rate(rate)
+    }
+
+    /// Keep without a known probability if `kept`, otherwise drop for `reason`
+    fn keep_unweighted_if(kept: bool, reason: DropReason) -> Self {
+        if kept {
+            Self::Keep(None)
+        } else {
+            Self::Drop(reason)
+        }
+    }
+
+    /// Set the probability a kept log was kept with
+    fn with_rate(self, rate: f64) -> Self {
+        match self {
+            Self::Keep(_) => Self::Keep(Some(rate)),
+            Self::Drop(reason) => Self::Drop(reason),
+        }
+    }
+
+    fn is_kept(self) -> bool {
+        matches!(self, Self::Keep(_))
+    }
+}
+
+/// A log kept by [`AdaptiveSampler::sample_with_fields`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeptLog {
+    /// Probability the log was kept with (1.0 for logs that are always kept), or
+    /// `None` when it was kept by a rule that isn't a probability: keep-one, bursts,
+    /// debouncing and the sliding window and leaky bucket strategies
+    pub rate: Option<f64>,
+    /// Pattern signature, when the decision computed it; logs kept for their
+    /// severity alone are decided without one
+    pub signature: Option<String>,
+}
+
+/// Outcome of [`AdaptiveSampler::screen_with_fields`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Screened {
+    /// An early stage decided the log's fate: kept, or dropped
+    Decided(Option<KeptLog>),
+    /// The log passed the early stages, leaving its fate to the caller; its
+    /// pattern has been counted under this signature
+    Deferred(String),
+}
+
+/// Outcome of the decision stages that run before pattern sampling
+enum Screening {
+    /// Decided, with the signature of a log whose pattern was counted
+    Decided(Decision, Option<String>),
+    /// Left to pattern sampling
+    Passed(String),
+}
+
+/// Summary of sampling activity over the sampler's lifetime
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SamplingPolicy {
-    pub policy_id: String,
-    pub sampling_rate: f64,
-    pub patterns: Vec<String>,
-    pub max_logs_per_minute: u32,
-    pub severity_rates: std::collections::HashMap<String, f64>,
+pub struct SamplingSummary {
+    pub logs_seen: u64,
+    pub logs_sampled: u64,
+    pub logs_dropped: u64,
+    pub reduction_ratio: f64,
+    pub top_patterns: Vec<PatternStats>,
 }
 
-/// Pattern statistics
+/// Projected effect of a hypothetical config on the currently tracked patterns
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub patterns: Vec<PatternProjection>,
+    /// Logs exported across all patterns at their current rates
+    pub current_exports: f64,
+    /// Logs that would be exported across all patterns under the new config
+    pub projected_exports: f64,
+}
+
+/// Expected exports of one pattern before and after a config change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternProjection {
+    pub signature: String,
+    pub count: u64,
+    pub current_rate: f64,
+    pub projected_rate: f64,
+    pub projected_exports: f64,
+}
+
+/// Where the currently cached sampling policy came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolicySource {
+    /// Freshly fetched from the LipService backend
+    Backend,
+    /// Provided locally through `Config::static_policy`
+    Static,
+    /// Built-in default, no backend policy has been fetched yet
+    Default,
+    /// Last backend policy, kept after a failed refresh
+    StaleBackend,
+}
+
+/// Why the most recent policy fetch failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyError {
+    /// HTTP status returned by the backend, `None` for network and decoding failures
+    pub status: Option<u16>,
+    /// Description of the failure
+    pub message: String,
+    /// When the fetch failed
+    pub at: SystemTime,
+}
+
+impl PolicyError {
+    /// Describe a failed policy fetch
+    fn new(error: &anyhow::Error) -> Self {
+        let status = match error.downcast_ref::<LipServiceError>() {
+            Some(
+                LipServiceError::Unauthorized { status }
+                | LipServiceError::Rejected { status }
+                | LipServiceError::Unavailable { status },
+            ) => Some(*status),
+            _ => error
+                .downcast_ref::<reqwest::Error>()
+                .and_then(reqwest::Error::status)
+                .map(|status| status.as_u16()),
+        };
+
+        Self {
+            status,
+            message: error.to_string(),
+            at: SystemTime::now(),
+        }
+    }
+}
+
+/// Per-signature sampling rates loaded from `Config::fingerprint_rates_path`
+struct FingerprintRates {
+    path: PathBuf,
+    rates: RwLock<HashMap<String, f64>>,
+    modified: parking_lot::Mutex<Option<SystemTime>>,
+    internal_level: Level,
+}
+
+impl FingerprintRates {
+    /// Load the rates file; a missing or invalid file leaves no rates
+    fn open(path: PathBuf, internal_level: Level) -> Self {
+        let rates = Self {
+            path,
+            rates: RwLock::new(HashMap::new()),
+            modified: parking_lot::Mutex::new(None),
+            internal_level,
+        };
+        rates.reload_if_changed();
+        rates
+    }
+
+    /// Rate set for a signature
+    fn get(&self, signature: &str) -> Option<f64> {
+        self.rates.read().get(signature).copied()
+    }
+
+    /// Reload the file if its modification time changed since the last load
+    ///
+    /// A file that can't be read or parsed keeps the rates loaded before.
+    fn reload_if_changed(&self) {
+        let modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+        let mut last = self.modified.lock();
+        if modified.is_none() || modified == *last {
+            return;
+        }
+
+        let loaded = std::fs::read(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<HashMap<String, f64>>(&bytes)?));
+        match loaded {
+            Ok(mut rates) => {
+                rates.retain(|signature, rate| {
+                    let valid = (0.0..=1.0).contains(rate);
+                    if !valid {
+                        internal_event!(self.internal_level, "Ignoring fingerprint rate {} for {}", rate, signature);
+                    }
+                    valid
+                });
+                info!(target: INTERNAL_TARGET, "Loaded {} fingerprint rates from {}", rates.len(), self.path.display());
+                *self.rates.write() = rates;
+                *last = modified;
+            }
+            Err(e) => internal_event!(
+                self.internal_level,
+                "Failed to load fingerprint rates from {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Logs a volume budget applies to
+enum BudgetMatcher {
+    Pattern(regex::Regex),
+    Severity(Severity),
+}
+
+/// A configured volume budget and its observed traffic
+struct VolumeBudgetState {
+    matcher: BudgetMatcher,
+    logs_per_minute: f64,
+    window: parking_lot::Mutex<BudgetWindow>,
+}
+
+impl VolumeBudgetState {
+    fn matches(&self, message: &str, severity: Severity) -> bool {
+        match &self.matcher {
+            BudgetMatcher::Pattern(regex) => regex.is_match(message),
+            BudgetMatcher::Severity(budgeted) => *budgeted == severity,
+        }
+    }
+
+    /// Key the sampling strategy decides the budget's logs under
+    fn key(&self) -> &str {
+        match &self.matcher {
+            BudgetMatcher::Pattern(regex) => regex.as_str(),
+            BudgetMatcher::Severity(severity) => severity.as_str(),
+        }
+    }
+
+    /// Count a matching log and return the rate it is sampled at
+    fn rate(&self, now: Instant) -> f64 {
+        self.window.lock().observe(now, self.logs_per_minute)
+    }
+}
+
+/// Traffic seen by a volume budget in the current window
+struct BudgetWindow {
+    started: Instant,
+    seen: u64,
+    /// Rate derived from the previous window, if one has completed
+    rate: Option<f64>,
+}
+
+impl BudgetWindow {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            seen: 0,
+            rate: None,
+        }
+    }
+
+    /// Count a log at `now`, recomputing the rate when a window has elapsed
+    fn observe(&mut self, now: Instant, logs_per_minute: f64) -> f64 {
+        let elapsed = now.duration_since(self.started);
+        if elapsed >= BUDGET_WINDOW {
+            let observed_per_minute = self.seen as f64 * BUDGET_WINDOW.as_secs_f64() / elapsed.as_secs_f64();
+            self.rate = Some((logs_per_minute / observed_per_minute).min(1.0));
+            self.started = now;
+            self.seen = 0;
+        }
+        self.seen += 1;
+
+        match self.rate {
+            Some(rate) => rate,
+            // Without a full window to go on, admit the first logs up to the budget
+            None if self.seen as f64 <= logs_per_minute => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+/// The sampler state its background tasks hold on to
+#[derive(Clone)]
+struct SharedState {
+    config: Config,
+    http_client: reqwest::Client,
+    policy: Arc<RwLock<Option<SamplingPolicy>>>,
+    policy_source: Arc<RwLock<PolicySource>>,
+    last_policy_update: Arc<RwLock<Instant>>,
+    policy_generation: Arc<AtomicU64>,
+    last_policy_error: Arc<RwLock<Option<PolicyError>>>,
+    pattern_stats: Arc<DashMap<String, PatternStats>>,
+    counters: Arc<SamplerCounters>,
+    represented: Arc<DashSet<String>>,
+}
+
+impl SharedState {
+    fn policy_tracking(&self) -> PolicyTracking<'_> {
+        PolicyTracking {
+            policy: &self.policy,
+            source: &self.policy_source,
+            last_update: &self.last_policy_update,
+            generation: &self.policy_generation,
+            last_error: &self.last_policy_error,
+        }
+    }
+
+    /// Fetch the policy once and install it
+    async fn refresh(&self) {
+        AdaptiveSampler::refresh_policy(&self.http_client, &self.config, &self.policy_tracking()).await;
+    }
+
+    /// Send one pattern report
+    async fn report(&self) {
+        AdaptiveSampler::report_patterns(
+            &self.http_client,
+            &self.config,
+            &self.pattern_stats,
+            &self.counters,
+            &self.represented,
+        )
+        .await;
+    }
+}
+
+/// The sampler's shared policy state
+struct PolicyTracking<'a> {
+    policy: &'a RwLock<Option<SamplingPolicy>>,
+    source: &'a RwLock<PolicySource>,
+    last_update: &'a RwLock<Instant>,
+    /// Bumped on every policy change, invalidating pattern rates seeded earlier
+    generation: &'a AtomicU64,
+    /// Cleared once a policy loads again
+    last_error: &'a RwLock<Option<PolicyError>>,
+}
+
+impl PolicyTracking<'_> {
+    /// Install a newly loaded policy
+    fn apply(&self, new_policy: SamplingPolicy, source: PolicySource) {
+        *self.policy.write() = Some(new_policy);
+        *self.source.write() = source;
+        *self.last_update.write() = Instant::now();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.write() = None;
+    }
+}
+
+/// Pattern statistics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PatternStats {
     pub count: u64,
+    /// Occurrences that were kept
+    #[serde(default)]
+    pub sampled_count: u64,
+    #[serde(default = "SystemTime::now")]
+    pub first_seen: SystemTime,
     pub last_seen: SystemTime,
     pub signature: String,
     pub sampling_rate: f64,
 }
 
-/// Signature computer for log pattern analysis
-pub struct SignatureComputer {
-    patterns: Vec<(regex::Regex, String)>,
+/// Lifetime counters of sampling decisions
+#[derive(Debug, Default)]
+pub struct SamplerCounters {
+    logs_seen: AtomicU64,
+    logs_sampled: AtomicU64,
+    bytes_seen: AtomicU64,
+    bytes_sampled: AtomicU64,
+    patterns_untracked: AtomicU64,
 }
 
-impl SignatureComputer {
-    pub fn new() -> Self {
-        let patterns = vec![
-            (regex::Regex::new(r"\b\d+\b").unwrap(), "N".to_string()),
-            (regex::Regex::new(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}").unwrap(), "UUID".to_string()),
-            (regex::Regex::new(r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}").unwrap(), "TIMESTAMP".to_string()),
-            (regex::Regex::new(r"\b(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b").unwrap(), "IP".to_string()),
-            (regex::Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Z|a-z]{2,}\b").unwrap(), "EMAIL".to_string()),
-            (regex::Regex::new(r"https?://[^\s]+").unwrap(), "URL".to_string()),
-        ];
+impl SamplerCounters {
+    /// Record a sampling decision for a message of the given size
+    fn record(&self, bytes: usize, sampled: bool) {
+        self.logs_seen.fetch_add(1, Ordering::Relaxed);
+        self.bytes_seen.fetch_add(bytes as u64, Ordering::Relaxed);
+        if sampled {
+            self.record_sampled(bytes);
+        }
+    }
 
-        Self { patterns }
+    /// Record a log already counted as seen as sampled
+    fn record_sampled(&self, bytes: usize) {
+        self.logs_sampled.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sampled.fetch_add(bytes as u64, Ordering::Relaxed);
     }
 
-    pub fn compute_signature(&self, message: &str) -> String {
-        let mut normalized = message.to_lowercase().trim().to_string();
+    /// Total number of logs evaluated
+    pub fn logs_seen(&self) -> u64 {
+        self.logs_seen.load(Ordering::Relaxed)
+    }
 
-        // Apply pattern replacements
-        for (pattern, replacement) in &self.patterns {
-            normalized = pattern.replace_all(&normalized, replacement).to_string();
-        }
+    /// Logs of new patterns left untracked because `Config::max_tracked_patterns` was reached
+    pub fn patterns_untracked(&self) -> u64 {
+        self.patterns_untracked.load(Ordering::Relaxed)
+    }
 
-        // Compute MD5 hash
-        let digest = md5::compute(normalized.as_bytes());
-        format!("{:x}", digest)
+    /// Total number of logs kept
+    pub fn logs_sampled(&self) -> u64 {
+        self.logs_sampled.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of logs dropped (0.0 when nothing has been seen)
+    pub fn reduction_ratio(&self) -> f64 {
+        Self::ratio(self.logs_sampled(), self.logs_seen())
+    }
+
+    /// Fraction of message bytes dropped (0.0 when nothing has been seen)
+    pub fn byte_reduction_ratio(&self) -> f64 {
+        Self::ratio(
+            self.bytes_sampled.load(Ordering::Relaxed),
+            self.bytes_seen.load(Ordering::Relaxed),
+        )
+    }
+
+    fn ratio(kept: u64, seen: u64) -> f64 {
+        if seen == 0 {
+            return 0.0;
+        }
+        1.0 - kept as f64 / seen as f64
     }
 }
 
 impl AdaptiveSampler {
     /// Create a new adaptive sampler
     pub async fn new(config: Config) -> Result<Self> {
-        let sampler = Self {
-            config: config.clone(),
-            policy: Arc::new(RwLock::new(None)),
-            pattern_stats: Arc::new(DashMap::new()),
-            signature_computer: Arc::new(SignatureComputer::new()),
-            last_policy_update: Arc::new(RwLock::new(Instant::now())),
-        };
+        let signature_computer = Self::configured_signature_computer(&config);
+
+        Self::with_signature_computer(config, signature_computer).await
+    }
+
+    /// Create a new adaptive sampler using a custom signature computer
+    pub async fn with_signature_computer(config: Config, signature_computer: SignatureComputer) -> Result<Self> {
+        let sampler = Self::build(config, signature_computer)?;
 
         // Start background tasks
         sampler.start_background_tasks().await;
@@ -86,142 +638,2654 @@ impl AdaptiveSampler {
         Ok(sampler)
     }
 
-    /// Determine if a log should be sampled
-    pub fn should_sample(&self, message: &str, severity: &str) -> bool {
-        // Always sample errors and critical logs
-        if matches!(severity.to_uppercase().as_str(), "ERROR" | "CRITICAL" | "FATAL") {
-            return true;
+    /// Create a sampler that only makes sampling decisions, without a tokio runtime
+    ///
+    /// No background tasks are spawned and the backend is never contacted: the policy
+    /// stays at `Config::static_policy` if set, otherwise at the built-in default, and
+    /// no pattern reports are sent. Use this to drive your own export pipeline.
+    pub fn new_standalone(config: Config) -> Result<Self> {
+        let signature_computer = Self::configured_signature_computer(&config);
+
+        let sampler = Self::build(config, signature_computer)?;
+        if let Some(static_policy) = &sampler.config.static_policy {
+            sampler.set_policy(static_policy.clone());
+        }
+
+        Ok(sampler)
+    }
+
+    /// Signature computer with the normalizations selected in the config
+    fn configured_signature_computer(config: &Config) -> SignatureComputer {
+        let mut signature_computer = SignatureComputer::new();
+        if config.normalize_url_paths {
+            signature_computer = signature_computer.with_path_normalization();
+        }
+        if config.normalize_file_paths {
+            signature_computer = signature_computer.with_file_path_normalization();
         }
+        if config.number_normalization == NumberNormalization::Aggressive {
+            signature_computer = signature_computer.with_aggressive_numbers();
+        }
+        if config.normalize_json {
+            signature_computer = signature_computer.with_json_normalization();
+        }
+        if config.min_normalization_length > 0 {
+            signature_computer = signature_computer.with_min_normalization_length(config.min_normalization_length);
+        }
+        signature_computer
+    }
+
+    /// Set up sampler state without starting any background work
+    fn build(config: Config, signature_computer: SignatureComputer) -> Result<Self> {
+        config.validate().map_err(LipServiceError::InvalidConfig)?;
+        let pattern_rules = config
+            .pattern_rules
+            .iter()
+            .map(|rule| Ok((regex::Regex::new(&rule.pattern)?, rule.rate)))
+            .collect::<Result<Vec<_>>>()?;
+        let always_drop_patterns = config
+            .always_drop_patterns
+            .iter()
+            .map(|pattern| Ok(regex::Regex::new(pattern)?))
+            .collect::<Result<Vec<_>>>()?;
+        let debounce_rules = config
+            .debounce_rules
+            .iter()
+            .map(|rule| Ok((regex::Regex::new(&rule.pattern)?, rule.min_interval)))
+            .collect::<Result<Vec<_>>>()?;
+        let severity_remaps = config
+            .severity_remaps
+            .iter()
+            .map(|remap| Ok((regex::Regex::new(&remap.pattern)?, Severity::from(remap.severity.as_str()))))
+            .collect::<Result<Vec<_>>>()?;
+        let volume_budgets = config
+            .volume_budgets
+            .iter()
+            .map(|budget| {
+                let matcher = match &budget.scope {
+                    BudgetScope::Pattern(pattern) => BudgetMatcher::Pattern(regex::Regex::new(pattern)?),
+                    BudgetScope::Severity(severity) => BudgetMatcher::Severity(Severity::from(severity.as_str())),
+                };
+                Ok(VolumeBudgetState {
+                    matcher,
+                    logs_per_minute: budget.logs_per_minute as f64,
+                    window: parking_lot::Mutex::new(BudgetWindow::new(Instant::now())),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut http_client = reqwest::Client::builder().timeout(config.timeout);
+        if let Some(api_key) = &config.api_key {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(reqwest::header::AUTHORIZATION, format!("Bearer {}", api_key).parse()?);
+            http_client = http_client.default_headers(headers);
+        }
+
+        let mut sampler = Self {
+            config: config.clone(),
+            policy: Arc::new(RwLock::new(None)),
+            pattern_stats: Arc::new(DashMap::new()),
+            signature_computer: Arc::new(signature_computer),
+            last_policy_update: Arc::new(RwLock::new(Instant::now())),
+            policy_generation: Arc::new(AtomicU64::new(0)),
+            seeded_generations: DashMap::new(),
+            pattern_rules,
+            counters: Arc::new(SamplerCounters::default()),
+            represented: Arc::new(DashSet::new()),
+            debounce_rules,
+            severity_remaps,
+            volume_budgets,
+            last_exported: DashMap::new(),
+            policy_source: Arc::new(RwLock::new(PolicySource::Default)),
+            last_policy_error: Arc::new(RwLock::new(None)),
+            http_client: http_client.build()?,
+            congestion: AtomicU64::new(0f64.to_bits()),
+            started_at: Instant::now(),
+            audit_log: config
+                .audit_log_path
+                .as_deref()
+                .map(|path| AuditLog::open(path, config.internal_level(Level::WARN)))
+                .transpose()?,
+            callsite_signatures: DashMap::new(),
+            background_tasks: parking_lot::Mutex::new(Vec::new()),
+            strategy: config.sampling_strategy.build(config.sampling_seed, config.sampling_salt()),
+            burst_allowances: DashMap::new(),
+            always_keep: HashSet::new(),
+            always_drop_signatures: config.always_drop_signatures.iter().cloned().collect(),
+            always_drop_patterns,
+            fingerprint_rates: config
+                .fingerprint_rates_path
+                .clone()
+                .map(|path| Arc::new(FingerprintRates::open(path, config.internal_level(Level::WARN)))),
+        };
 
-        // Compute signature
-        let signature = self.signature_computer.compute_signature(message);
+        // Example messages are signed once, like logs without fields, so lookups are a set hit
+        let examples: Vec<String> =
+            config.always_keep_messages.iter().map(|message| sampler.compute_signature(message, &[])).collect();
+        sampler.always_keep = config.always_keep_signatures.iter().cloned().chain(examples).collect();
 
-        // Update pattern stats
-        if let Some(mut stats) = self.pattern_stats.get_mut(&signature) {
-            stats.count += 1;
-            stats.last_seen = SystemTime::now();
-            return self.decide_sampling(stats.sampling_rate);
+        // Resume learned pattern rates from the previous run
+        if let Some(path) = &sampler.config.pattern_state_path {
+            for stats in load_pattern_state(path, sampler.config.internal_level(Level::WARN)) {
+                sampler.pattern_stats.insert(stats.signature.clone(), stats);
+            }
         }
 
-        // Default sampling rate
-        self.decide_sampling(0.1) // 10% default
+        Ok(sampler)
     }
 
-    /// Make a sampling decision based on rate
-    fn decide_sampling(&self, rate: f64) -> bool {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// Determine if a log should be sampled
+    ///
+    /// Accepts a [`Severity`] or a severity string such as "INFO".
+    pub fn should_sample(&self, message: &str, severity: impl Into<Severity>) -> bool {
+        self.should_sample_with_fields(message, severity, &[])
+    }
 
-        let mut hasher = DefaultHasher::new();
-        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos().hash(&mut hasher);
-        let hash = hasher.finish();
-        
-        (hash % 10000) < (rate * 10000.0) as u64
+    /// Determine if a log with the given structured fields should be sampled
+    pub fn should_sample_with_fields(
+        &self,
+        message: &str,
+        severity: impl Into<Severity>,
+        fields: &[(&str, &str)],
+    ) -> bool {
+        self.sample_with_fields(message, severity, fields).is_some()
     }
 
-    /// Start background tasks for policy refresh and pattern reporting
-    async fn start_background_tasks(&self) {
-        let policy_refresh_interval = self.config.policy_refresh_interval;
-        let pattern_report_interval = self.config.pattern_report_interval;
-        let policy = Arc::clone(&self.policy);
-        let pattern_stats = Arc::clone(&self.pattern_stats);
-        let last_policy_update = Arc::clone(&self.last_policy_update);
+    /// Like [`AdaptiveSampler::should_sample_with_fields`], returning the probability a
+    /// kept log was kept with and the signature the decision computed, so callers don't
+    /// compute it again
+    pub fn sample_with_fields(
+        &self,
+        message: &str,
+        severity: impl Into<Severity>,
+        fields: &[(&str, &str)],
+    ) -> Option<KeptLog> {
+        let severity = self.remapped_severity(message, severity.into());
+        let (decision, counted) = self.evaluate(message, severity, fields);
+        self.record_decision(message, severity, fields, decision, counted)
+    }
 
-        // Policy refresh task
-        tokio::spawn(async move {
-            let mut interval = interval(policy_refresh_interval);
-            loop {
-                interval.tick().await;
-                Self::refresh_policy(&policy, &last_policy_update).await;
+    /// Run only the decision stages before pattern sampling (severity, empty messages,
+    /// the blocklist, the allowlist, fingerprint rates and session sampling), e.g. to
+    /// hand the logs that pass them to a reservoir
+    ///
+    /// A deferred log counts as seen; pass its signature to
+    /// [`AdaptiveSampler::record_deferred_kept`] if it ends up exported.
+    pub(crate) fn screen_with_fields(
+        &self,
+        message: &str,
+        severity: impl Into<Severity>,
+        fields: &[(&str, &str)],
+    ) -> Screened {
+        let severity = self.remapped_severity(message, severity.into());
+        match self.screen(message, severity, fields) {
+            Screening::Decided(decision, counted) => {
+                Screened::Decided(self.record_decision(message, severity, fields, decision, counted))
             }
-        });
-
-        // Pattern reporting task
-        tokio::spawn(async move {
-            let mut interval = interval(pattern_report_interval);
-            loop {
-                interval.tick().await;
-                Self::report_patterns(&pattern_stats).await;
+            Screening::Passed(signature) => {
+                self.record_occurrence(&signature, severity);
+                self.counters.record(message.len(), false);
+                Screened::Deferred(signature)
             }
-        });
+        }
     }
 
-    /// Refresh the sampling policy
-    async fn refresh_policy(
-        policy: &Arc<RwLock<Option<SamplingPolicy>>>,
-        last_update: &Arc<RwLock<Instant>>,
-    ) {
-        debug!("Refreshing sampling policy");
+    /// Audit, trace and count a decision
+    fn record_decision(
+        &self,
+        message: &str,
+        severity: Severity,
+        fields: &[(&str, &str)],
+        decision: Decision,
+        counted: Option<String>,
+    ) -> Option<KeptLog> {
+        if let (Decision::Drop(reason), Some(audit)) = (decision, &self.audit_log) {
+            audit.record(&self.compute_signature(message, fields), severity.as_str(), reason);
+        }
+        if self.traces_decisions() {
+            self.trace_decision(&self.compute_signature(message, fields), severity, decision);
+        }
 
-        // For now, use a default policy
-        // In a real implementation, this would fetch from LipService backend
-        let new_policy = SamplingPolicy {
-            policy_id: "default".to_string(),
-            sampling_rate: 0.1,
-            patterns: vec!["error".to_string(), "warning".to_string()],
-            max_logs_per_minute: 1000,
-            severity_rates: std::collections::HashMap::from([
-                ("ERROR".to_string(), 1.0),
-                ("WARNING".to_string(), 0.5),
-                ("INFO".to_string(), 0.1),
-                ("DEBUG".to_string(), 0.05),
-            ]),
+        let Decision::Keep(rate) = decision else {
+            self.counters.record(message.len(), false);
+            return None;
         };
+        self.counters.record(message.len(), true);
+        if let Some(signature) = &counted {
+            self.record_sampled(signature);
+        }
+        Some(KeptLog { rate, signature: counted })
+    }
 
-        {
-            let mut policy_guard = policy.write();
-            *policy_guard = Some(new_policy);
+    /// Determine if a log should be sampled from its precomputed pattern signature
+    ///
+    /// Message-based local rules (pattern and debounce rules) are skipped since the
+    /// message is not available, and message bytes are not counted.
+    pub fn should_sample_with_signature(&self, signature: &str, severity: impl Into<Severity>) -> bool {
+        let severity = severity.into();
+        let decision = self.severity_decision(severity).unwrap_or_else(|| {
+            if self.always_drop_signatures.contains(signature) {
+                return Decision::Drop(DropReason::Blocklisted);
+            }
+            if self.always_keep.contains(signature) {
+                return Decision::Keep(Some(1.0));
+            }
+            if let Some(rate) = self.fingerprint_rate(signature) {
+                self.record_occurrence(signature, severity);
+                return self.sample_at(signature, rate, None, DropReason::Fingerprint);
+            }
+            self.decide_for_signature(signature, severity, None, 0.0, 1.0)
+        });
+        if let (Decision::Drop(reason), Some(audit)) = (decision, &self.audit_log) {
+            audit.record(signature, severity.as_str(), reason);
+        }
+        if self.traces_decisions() {
+            self.trace_decision(signature, severity, decision);
         }
 
-        {
-            let mut last_update_guard = last_update.write();
-            *last_update_guard = Instant::now();
+        let sampled = decision.is_kept();
+        self.counters.record(0, sampled);
+        if sampled {
+            self.record_sampled(signature);
         }
+        sampled
+    }
 
-        info!("Sampling policy refreshed");
+    /// Whether decisions should be traced right now
+    fn traces_decisions(&self) -> bool {
+        self.config.trace_decisions && tracing::enabled!(target: DECISION_TARGET, Level::TRACE)
     }
 
-    /// Report pattern statistics
-    async fn report_patterns(pattern_stats: &Arc<DashMap<String, PatternStats>>) {
-        let count = pattern_stats.len();
-        debug!("Reporting {} patterns", count);
+    /// Trace a sampling decision under [`DECISION_TARGET`], which the layer never samples
+    fn trace_decision(&self, signature: &str, severity: Severity, decision: Decision) {
+        match decision {
+            Decision::Keep(rate) => {
+                trace!(target: DECISION_TARGET, signature, severity = severity.as_str(), rate, "Log kept")
+            }
+            Decision::Drop(reason) => trace!(
+                target: DECISION_TARGET,
+                signature,
+                severity = severity.as_str(),
+                reason = ?reason,
+                "Log dropped"
+            ),
+        }
+    }
 
-        // In a real implementation, this would send stats to LipService backend
-        // For now, just log the count
-        info!("Pattern statistics reported", pattern_count = count);
+    /// Record a log kept without a sampling decision, e.g. to keep its trace or span complete
+    pub fn record_kept(&self, message: &str) {
+        self.counters.record(message.len(), true);
     }
 
-    /// Get current policy
-    pub fn get_policy(&self) -> Option<SamplingPolicy> {
-        self.policy.read().clone()
+    /// Count a log deferred by [`AdaptiveSampler::screen_with_fields`] as kept
+    pub(crate) fn record_deferred_kept(&self, signature: &str, message: &str) {
+        self.counters.record_sampled(message.len());
+        self.record_sampled(signature);
     }
 
-    /// Get pattern statistics
-    pub fn get_pattern_stats(&self) -> Vec<PatternStats> {
-        self.pattern_stats.iter().map(|entry| entry.value().clone()).collect()
+    /// Determine if an event should be sampled from its static callsite alone
+    ///
+    /// The callsite signature is computed once and cached, so repeat decisions do not
+    /// allocate. Message-based local rules do not apply on this path.
+    pub fn should_sample_callsite(&self, metadata: &'static tracing::Metadata<'static>) -> bool {
+        let severity = Severity::from(*metadata.level());
+        let callsite = metadata.callsite();
+
+        if let Some(signature) = self.callsite_signatures.get(&callsite) {
+            return self.should_sample_with_signature(&signature, severity);
+        }
+
+        let signature = self
+            .signature_computer
+            .hash_raw(&format!("{}::{}", metadata.target(), metadata.name()));
+        let sampled = self.should_sample_with_signature(&signature, severity);
+        self.callsite_signatures.insert(callsite, signature);
+        sampled
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Decide whether events inside a newly created span should be kept
+    ///
+    /// Local pattern rules are matched against the span name.
+    pub fn should_keep_span(&self, name: &str, severity: impl Into<Severity>) -> bool {
+        let severity = severity.into();
+        if let Some(decision) = self.severity_decision(severity) {
+            return decision.is_kept();
+        }
 
-    #[tokio::test]
-    async fn test_adaptive_sampler_creation() {
-        let config = Config::default();
-        let sampler = AdaptiveSampler::new(config).await;
-        assert!(sampler.is_ok());
+        if let Some((regex, rate)) = self.pattern_rules.iter().find(|(regex, _)| regex.is_match(name)) {
+            return self.decide_sampling(regex.as_str(), *rate, None);
+        }
+
+        let signature = self.signature_computer.compute_signature(&format!("span:{}", name));
+        self.decide_for_signature(&signature, severity, None, 0.0, 1.0).is_kept()
     }
 
-    #[tokio::test]
-    async fn test_error_sampling() {
-        let config = Config::default();
-        let sampler = AdaptiveSampler::new(config).await.unwrap();
+    /// Pattern signature of a log, as used for sampling decisions
+    pub fn signature_for(&self, message: &str, fields: &[(&str, &str)]) -> String {
+        self.compute_signature(message, fields)
+    }
 
-        // Error logs should always be sampled
-        assert!(sampler.should_sample("Database connection failed", "ERROR"));
-        assert!(sampler.should_sample("System critical error", "CRITICAL"));
-        assert!(sampler.should_sample("Fatal system error", "FATAL"));
+    /// The signature computer, e.g. to inspect or clear its cache
+    pub fn signature_computer(&self) -> &SignatureComputer {
+        &self.signature_computer
+    }
+
+    /// Compute the pattern signature according to the configured mode
+    fn compute_signature(&self, message: &str, fields: &[(&str, &str)]) -> String {
+        // Blank messages are signed by field names alone, whatever the signature mode
+        if self.config.empty_messages == EmptyMessages::Placeholder && message.trim().is_empty() {
+            let mut keys: Vec<&str> = vec![EMPTY_MESSAGE_PLACEHOLDER];
+            keys.extend(fields.iter().map(|(key, _)| *key));
+            return self.signature_computer.compute_from_fields(&keys);
+        }
+
+        // Values of signature fields tell otherwise identical logs apart
+        let values: Vec<(&str, &str)> = fields
+            .iter()
+            .filter(|(key, _)| self.config.signature_fields.iter().any(|field| field == key))
+            .copied()
+            .collect();
+
+        match self.config.signature_mode {
+            SignatureMode::Fields if !fields.is_empty() => {
+                let mut keys: Vec<String> = fields.iter().map(|(key, _)| key.to_string()).collect();
+                keys.extend(
+                    values
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, self.signature_computer.normalize(value))),
+                );
+                let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                self.signature_computer.compute_from_fields(&keys)
+            }
+            _ => self.signature_computer.compute_with_values(message, &values),
+        }
+    }
+
+    /// Severity a log is sampled at, after the first matching remap rule
+    fn remapped_severity(&self, message: &str, severity: Severity) -> Severity {
+        self.severity_remaps
+            .iter()
+            .find(|(regex, _)| regex.is_match(message))
+            .map_or(severity, |(_, remapped)| *remapped)
+    }
+
+    /// Evaluate the sampling decision without recording it
+    ///
+    /// Also returns the signature of logs whose pattern was counted.
+    fn evaluate(&self, message: &str, severity: Severity, fields: &[(&str, &str)]) -> (Decision, Option<String>) {
+        match self.screen(message, severity, fields) {
+            Screening::Decided(decision, counted) => (decision, counted),
+            Screening::Passed(signature) => {
+                let decision = self.evaluate_pattern(message, severity, fields, &signature);
+                (decision, Some(signature))
+            }
+        }
+    }
+
+    /// Decision stages that run before pattern sampling
+    fn screen(&self, message: &str, severity: Severity, fields: &[(&str, &str)]) -> Screening {
+        if let Some(decision) = self.severity_decision(severity) {
+            return Screening::Decided(decision, None);
+        }
+
+        // Empty messages carry nothing worth exporting; errors were already kept above
+        if self.config.empty_messages == EmptyMessages::Drop && message.trim().is_empty() {
+            return Screening::Decided(Decision::Drop(DropReason::EmptyMessage), None);
+        }
+
+        // Blocklisted noise is dropped outright
+        if self.always_drop_patterns.iter().any(|regex| regex.is_match(message))
+            || (!self.always_drop_signatures.is_empty()
+                && self.always_drop_signatures.contains(&self.compute_signature(message, fields)))
+        {
+            return Screening::Decided(Decision::Drop(DropReason::Blocklisted), None);
+        }
+
+        // Every other pattern is counted, whatever decides its fate below
+        let signature = self.compute_signature(message, fields);
+
+        // Allowlisted patterns are never sampled out
+        if self.always_keep.contains(&signature) {
+            self.record_occurrence(&signature, severity);
+            return Screening::Decided(Decision::Keep(Some(1.0)), Some(signature));
+        }
+
+        // Operators throttle specific signatures through the fingerprint rates file
+        if let Some(rate) = self.fingerprint_rate(&signature) {
+            self.record_occurrence(&signature, severity);
+            let decision = self.sample_at(&signature, rate, self.sample_key(fields), DropReason::Fingerprint);
+            return Screening::Decided(decision, Some(signature));
+        }
+
+        // Chosen sessions are kept whole; the rest are dropped below error severity
+        if let Some(session) = &self.config.session_sampling {
+            if let Some((_, id)) = fields.iter().find(|(key, _)| *key == session.field) {
+                self.record_occurrence(&signature, severity);
+                let kept = key_kept(self.config.sampling_seed, id, session.keep_rate);
+                let decision = Decision::keep_if(kept, session.keep_rate, DropReason::Session);
+                return Screening::Decided(decision, Some(signature));
+            }
+        }
+
+        Screening::Passed(signature)
+    }
+
+    /// Sampling decision for a counted pattern
+    fn evaluate_pattern(
+        &self,
+        message: &str,
+        severity: Severity,
+        fields: &[(&str, &str)],
+        signature: &str,
+    ) -> Decision {
+        let length_multiplier = self.length_multiplier(message.len());
+
+        let sample_key = self.sample_key(fields);
+
+        // Debounced patterns are exported at most once per interval
+        if let Some((_, min_interval)) = self.debounce_rules.iter().find(|(regex, _)| regex.is_match(message)) {
+            self.record_occurrence(signature, severity);
+            let kept = self.debounce(signature.to_string(), *min_interval);
+            return Decision::keep_unweighted_if(kept, DropReason::Debounced);
+        }
+
+        let priority = self.config.priority_field.as_deref().map_or(0.0, |field| {
+            fields
+                .iter()
+                .find(|(key, _)| *key == field)
+                .and_then(|(_, value)| value.parse::<f64>().ok())
+                .unwrap_or(0.0)
+        });
+
+        // Local pattern rules take precedence over learned rates; the pattern is still
+        // counted so reports show it, even when the rule drops all of it
+        if let Some((regex, rate)) = self.pattern_rules.iter().find(|(regex, _)| regex.is_match(message)) {
+            self.record_occurrence(signature, severity);
+            let rate = priority_scaled_rate(*rate * length_multiplier, priority);
+            return self.sample_at(regex.as_str(), rate, sample_key, DropReason::PatternRule);
+        }
+
+        // Volume budgets derive their rate from the observed volume
+        if let Some(budget) = self.volume_budgets.iter().find(|budget| budget.matches(message, severity)) {
+            self.record_occurrence(signature, severity);
+            let rate = priority_scaled_rate(budget.rate(Instant::now()) * length_multiplier, priority);
+            return self.sample_at(budget.key(), rate, sample_key, DropReason::Budget);
+        }
+
+        self.decide_for_signature(signature, severity, sample_key, priority, length_multiplier)
+    }
+
+    /// Value correlated logs share a keep/drop decision by across services
+    fn sample_key<'a>(&self, fields: &[(&str, &'a str)]) -> Option<&'a str> {
+        let field = self.config.consistent_sampling_field.as_deref()?;
+        fields.iter().find(|(key, _)| *key == field).map(|(_, value)| *value)
+    }
+
+    /// Rate set for a signature in the fingerprint rates file
+    fn fingerprint_rate(&self, signature: &str) -> Option<f64> {
+        self.fingerprint_rates.as_ref().and_then(|rates| rates.get(signature))
+    }
+
+    /// Rate multiplier of a message's length, see [`LengthAdjustment`](crate::config::LengthAdjustment)
+    fn length_multiplier(&self, len: usize) -> f64 {
+        self.config
+            .length_adjustments
+            .iter()
+            .filter(|adjustment| adjustment.min_bytes <= len)
+            .max_by_key(|adjustment| adjustment.min_bytes)
+            .map_or(1.0, |adjustment| adjustment.multiplier)
+    }
+
+    /// Decisions that depend only on severity
+    fn severity_decision(&self, severity: Severity) -> Option<Decision> {
+        // Keep everything during the post-start warmup window
+        if self.in_warmup() {
+            return Some(Decision::Keep(Some(1.0)));
+        }
+
+        // Always sample errors and critical logs
+        if severity.is_error() {
+            return Some(Decision::Keep(Some(1.0)));
+        }
+
+        // Shed low severities first while export is congested
+        if self.is_shed(severity) {
+            return Some(Decision::Drop(DropReason::LoadShed));
+        }
+
+        None
+    }
+
+    /// Sampling decision for a pattern signature, updating its stats
+    fn decide_for_signature(
+        &self,
+        signature: &str,
+        severity: Severity,
+        sample_key: Option<&str>,
+        priority: f64,
+        length_multiplier: f64,
+    ) -> Decision {
+        let rate = self.bounded_rate(self.record_occurrence(signature, severity));
+
+        // Guarantee each active pattern is represented once per report interval
+        if self.config.keep_one_per_interval
+            && !self.represented.contains(signature)
+            && self.represented.insert(signature.to_string())
+        {
+            return Decision::Keep(None);
+        }
+
+        // The start of a burst is kept at full fidelity
+        if self.take_burst_allowance(signature) {
+            return Decision::Keep(None);
+        }
+
+        let rate = priority_scaled_rate(rate * length_multiplier, priority);
+        self.sample_at(signature, rate, sample_key, DropReason::Rate)
+    }
+
+    /// Start tracking a new pattern, returning its rate
+    ///
+    /// Once `Config::max_tracked_patterns` is reached, new patterns aren't inserted
+    /// and are sampled at their severity's rate instead.
+    fn track_new_pattern(&self, signature: &str, severity: Severity) -> f64 {
+        if let Some(max) = self.config.max_tracked_patterns.filter(|max| self.pattern_stats.len() >= *max) {
+            if self.counters.patterns_untracked.fetch_add(1, Ordering::Relaxed) == 0 {
+                internal_event!(
+                    self.config.internal_level(Level::WARN),
+                    "Too many patterns: tracking is capped at {}, new patterns use severity rates",
+                    max
+                );
+            }
+            return self.severity_rate(severity);
+        }
+
+        let generation = self.policy_generation.load(Ordering::Relaxed);
+        let rate = self.initial_rate(signature, severity);
+        self.seeded_generations.insert(signature.to_string(), generation);
+        self.pattern_stats
+            .entry(signature.to_string())
+            .and_modify(|stats| stats.count += 1)
+            .or_insert_with(|| {
+                let now = SystemTime::now();
+                PatternStats {
+                    count: 1,
+                    sampled_count: 0,
+                    first_seen: now,
+                    last_seen: now,
+                    signature: signature.to_string(),
+                    sampling_rate: rate,
+                }
+            })
+            .sampling_rate
+    }
+
+    /// Count a kept occurrence of a tracked pattern
+    fn record_sampled(&self, signature: &str) {
+        if let Some(mut stats) = self.pattern_stats.get_mut(signature) {
+            stats.sampled_count += 1;
+        }
+    }
+
+    /// Count an occurrence of a pattern, returning its sampling rate
+    ///
+    /// Patterns are counted whatever the decision, so a rate of 0.0 still shows up in
+    /// reports.
+    fn record_occurrence(&self, signature: &str, severity: Severity) -> f64 {
+        match self.pattern_stats.get_mut(signature) {
+            Some(mut stats) => {
+                stats.count += 1;
+                stats.last_seen = SystemTime::now();
+                if let Some(rate) = self.reseed_stale_rate(signature, severity) {
+                    stats.sampling_rate = rate;
+                }
+                stats.sampling_rate
+            }
+            None => self.track_new_pattern(signature, severity),
+        }
+    }
+
+    /// New rate for a pattern whose rate was seeded from a policy that has since changed
+    ///
+    /// Rates restored from saved pattern state were not seeded here and are kept.
+    fn reseed_stale_rate(&self, signature: &str, severity: Severity) -> Option<f64> {
+        let generation = self.policy_generation.load(Ordering::Relaxed);
+        let mut seeded = self.seeded_generations.get_mut(signature)?;
+        if *seeded == generation {
+            return None;
+        }
+        *seeded = generation;
+        Some(self.initial_rate(signature, severity))
+    }
+
+    /// Sampling rate for a pattern seen for the first time, seeded from the policy
+    ///
+    /// Looks up the signature in the policy's pattern rates, then the severity rate,
+    /// then the policy-wide rate. The built-in default policy is ignored in favour of
+    /// `Config::default_sampling_rate`.
+    fn initial_rate(&self, signature: &str, severity: Severity) -> f64 {
+        if self.policy_source() == PolicySource::Default {
+            return self.config.default_sampling_rate;
+        }
+
+        let pattern_rate = self.policy.read().as_ref().and_then(|policy| policy.pattern_rates.get(signature).copied());
+        pattern_rate.unwrap_or_else(|| self.severity_rate(severity))
+    }
+
+    /// Sampling rate for a severity, ignoring pattern-specific rates
+    fn severity_rate(&self, severity: Severity) -> f64 {
+        if self.policy_source() == PolicySource::Default {
+            return self.config.default_sampling_rate;
+        }
+
+        match self.policy.read().as_ref() {
+            Some(policy) => policy
+                .severity_rates
+                .iter()
+                .find(|(level, _)| Severity::from(level.as_str()) == severity)
+                .map_or(policy.sampling_rate, |(_, rate)| *rate),
+            None => self.config.default_sampling_rate,
+        }
+    }
+
+    /// Clamp a pattern's rate to `Config::min_pattern_rate..=Config::max_pattern_rate`
+    ///
+    /// Local pattern rules are explicit and are not bounded.
+    fn bounded_rate(&self, rate: f64) -> f64 {
+        rate.max(self.config.min_pattern_rate).min(self.config.max_pattern_rate)
+    }
+
+    /// Whether the sampler is still inside its configured warmup window
+    pub fn in_warmup(&self) -> bool {
+        self.config
+            .warmup_duration
+            .is_some_and(|warmup| self.started_at.elapsed() < warmup)
+    }
+
+    /// Whether the load shedding ladder currently drops this severity
+    fn is_shed(&self, severity: Severity) -> bool {
+        if severity == Severity::Warn {
+            return false;
+        }
+
+        let congestion = self.congestion();
+        self.config
+            .load_shedding_ladder
+            .iter()
+            .any(|step| congestion >= step.congestion && Severity::from(step.severity.as_str()) == severity)
+    }
+
+    /// Report the current export congestion level (0.0 idle - 1.0 saturated)
+    ///
+    /// The loggers report the export queue's fill level before every decision.
+    pub fn set_congestion(&self, level: f64) {
+        self.congestion.store(level.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Current export congestion level
+    pub fn congestion(&self) -> f64 {
+        f64::from_bits(self.congestion.load(Ordering::Relaxed))
+    }
+
+    /// Spend one log of the pattern's burst allowance, refilling it after a quiet period
+    fn take_burst_allowance(&self, signature: &str) -> bool {
+        let Some(allowance) = self.config.burst_allowance else {
+            return false;
+        };
+
+        let now = Instant::now();
+        let mut entry = self
+            .burst_allowances
+            .entry(signature.to_string())
+            .or_insert((allowance.size, now));
+        let (remaining, last_seen) = &mut *entry;
+        if now.duration_since(*last_seen) >= allowance.quiet_period {
+            *remaining = allowance.size;
+        }
+        *last_seen = now;
+
+        if *remaining == 0 {
+            return false;
+        }
+        *remaining -= 1;
+        true
+    }
+
+    /// Admit a pattern if it has not been exported within `min_interval`
+    fn debounce(&self, signature: String, min_interval: Duration) -> bool {
+        let now = Instant::now();
+        match self.last_exported.entry(signature) {
+            Entry::Occupied(mut last) => {
+                if now.duration_since(*last.get()) < min_interval {
+                    return false;
+                }
+                last.insert(now);
+            }
+            Entry::Vacant(last) => {
+                last.insert(now);
+            }
+        }
+        true
+    }
+
+    /// Make a sampling decision for a pattern with the configured strategy
+    fn decide_sampling(&self, pattern: &str, rate: f64, sample_key: Option<&str>) -> bool {
+        self.strategy.decide(pattern, rate, sample_key)
+    }
+
+    /// Sample at `rate` through the strategy, dropping for `reason`
+    fn sample_at(&self, pattern: &str, rate: f64, sample_key: Option<&str>, reason: DropReason) -> Decision {
+        let decision = Decision::keep_unweighted_if(self.decide_sampling(pattern, rate, sample_key), reason);
+        if self.strategy.applies_rate() {
+            decision.with_rate(rate)
+        } else {
+            decision
+        }
+    }
+
+    /// Start background tasks for policy refresh and pattern reporting
+    async fn start_background_tasks(&self) {
+        let policy_refresh_interval = self.config.policy_refresh_interval;
+        let pattern_report_interval = self.config.pattern_report_interval;
+        let shared = self.shared_state();
+        let report_shared = shared.clone();
+
+        // Policy refresh task
+        let refresh_task = tokio::spawn(async move {
+            let mut interval = interval(policy_refresh_interval);
+            let config = &shared.config;
+            let mut streaming = config.policy_streaming && config.static_policy.is_none();
+            loop {
+                interval.tick().await;
+                shared.refresh().await;
+
+                // Apply pushed updates until the stream drops, then fall back to polling
+                if streaming {
+                    match Self::stream_policy(&shared.http_client, config, &shared.policy_tracking()).await {
+                        Ok(()) => {}
+                        Err(e) if serves_no_policy_stream(&e) => {
+                            streaming = false;
+                            internal_event!(
+                                config.internal_level(Level::WARN),
+                                "Backend does not serve policy streams, polling only: {}",
+                                e
+                            );
+                        }
+                        Err(e) => {
+                            internal_event!(config.internal_level(Level::WARN), "Policy stream disconnected: {}", e)
+                        }
+                    }
+                }
+            }
+        });
+
+        // Pattern reporting task
+        let align_reports = self.config.align_pattern_reports;
+        let report_task = tokio::spawn(async move {
+            let mut interval = report_interval(pattern_report_interval, align_reports);
+            loop {
+                interval.tick().await;
+                let policy_source = *report_shared.policy_source.read();
+                let policy_age = report_shared.last_policy_update.read().elapsed();
+                report_shared.report().await;
+                info!(
+                    target: INTERNAL_TARGET,
+                    policy_source = ?policy_source,
+                    policy_age_secs = policy_age.as_secs(),
+                    "Sampling policy status"
+                );
+            }
+        });
+
+        self.background_tasks.lock().extend([refresh_task, report_task]);
+
+        // Fingerprint rates file reload task
+        if let Some(rates) = self.fingerprint_rates.clone() {
+            let reload_interval = self.config.fingerprint_reload_interval;
+            let reload_task = tokio::spawn(async move {
+                let mut interval = interval(reload_interval);
+                loop {
+                    interval.tick().await;
+                    rates.reload_if_changed();
+                }
+            });
+            self.background_tasks.lock().push(reload_task);
+        }
+    }
+
+    /// Refresh the sampling policy
+    async fn refresh_policy(http_client: &reqwest::Client, config: &Config, tracking: &PolicyTracking<'_>) {
+        debug!(target: INTERNAL_TARGET, "Refreshing sampling policy");
+
+        let (new_policy, source) = match &config.static_policy {
+            Some(static_policy) => (static_policy.clone(), PolicySource::Static),
+            None => match Self::fetch_policy(http_client, config).await {
+                Ok(fetched) => (fetched, PolicySource::Backend),
+                Err(e) => {
+                    let level = match e.downcast_ref::<LipServiceError>() {
+                        Some(LipServiceError::Unauthorized { .. }) => Level::ERROR,
+                        _ => Level::WARN,
+                    };
+                    internal_event!(config.internal_level(level), "Failed to refresh sampling policy: {}", e);
+                    *tracking.last_error.write() = Some(PolicyError::new(&e));
+                    let mut source_guard = tracking.source.write();
+                    match *source_guard {
+                        // Keep serving the last backend policy, but flag it as stale
+                        PolicySource::Backend | PolicySource::StaleBackend => {
+                            *source_guard = PolicySource::StaleBackend;
+                        }
+                        PolicySource::Static | PolicySource::Default => {
+                            *source_guard = PolicySource::Default;
+                            *tracking.policy.write() = Some(Self::default_policy());
+                            tracking.generation.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    return;
+                }
+            },
+        };
+
+        tracking.apply(new_policy, source);
+        info!(target: INTERNAL_TARGET, "Sampling policy refreshed");
+    }
+
+    /// Refresh the sampling policy once, now, as the background refresh task does
+    ///
+    /// A failed refresh is available from [`AdaptiveSampler::last_policy_error`].
+    pub async fn refresh_now(&self) {
+        self.shared_state().refresh().await;
+    }
+
+    /// Send one pattern report now, as the background report task does
+    pub async fn report_now(&self) {
+        self.shared_state().report().await;
+    }
+
+    /// Replace the sampling policy with a locally provided one
+    ///
+    /// Pattern rates seeded from the previous policy are re-seeded on their next log.
+    pub fn set_policy(&self, policy: SamplingPolicy) {
+        self.policy_tracking().apply(policy, PolicySource::Static);
+    }
+
+    /// Handles to the state the background tasks share with the sampler
+    fn shared_state(&self) -> SharedState {
+        SharedState {
+            config: self.config.clone(),
+            http_client: self.http_client.clone(),
+            policy: Arc::clone(&self.policy),
+            policy_source: Arc::clone(&self.policy_source),
+            last_policy_update: Arc::clone(&self.last_policy_update),
+            policy_generation: Arc::clone(&self.policy_generation),
+            last_policy_error: Arc::clone(&self.last_policy_error),
+            pattern_stats: Arc::clone(&self.pattern_stats),
+            counters: Arc::clone(&self.counters),
+            represented: Arc::clone(&self.represented),
+        }
+    }
+
+    /// Shared policy state
+    fn policy_tracking(&self) -> PolicyTracking<'_> {
+        PolicyTracking {
+            policy: &self.policy,
+            source: &self.policy_source,
+            last_update: &self.last_policy_update,
+            generation: &self.policy_generation,
+            last_error: &self.last_policy_error,
+        }
+    }
+
+    /// Subscribe to the backend's server-sent policy updates and apply each one
+    ///
+    /// The stream is re-established every `Config::policy_refresh_interval`; it returns
+    /// `Ok` when the server closes it or the interval elapses.
+    async fn stream_policy(
+        http_client: &reqwest::Client,
+        config: &Config,
+        tracking: &PolicyTracking<'_>,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/policies/{}/stream?team_id={}",
+            config.lipservice_url.trim_end_matches('/'),
+            config.service_name,
+            config.backend_team_id()?
+        );
+        let mut response = http_client
+            .get(&url)
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .timeout(config.policy_refresh_interval)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LipServiceError::from_status(status.as_u16()).into());
+        }
+
+        let mut buffer = String::new();
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Ok(()),
+                Err(e) if e.is_timeout() => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk).replace("\r\n", "\n"));
+
+            // Events are separated by a blank line
+            while let Some(end) = buffer.find("\n\n") {
+                let event: String = buffer.drain(..end + 2).collect();
+                match parse_policy_event(&event) {
+                    Some(Ok(pushed)) => {
+                        tracking.apply(pushed, PolicySource::Backend);
+                        info!(target: INTERNAL_TARGET, "Sampling policy updated from stream");
+                    }
+                    Some(Err(e)) => {
+                        internal_event!(
+                            config.internal_level(Level::WARN),
+                            "Ignoring malformed policy stream event: {}",
+                            e
+                        )
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Fetch the active policy for this service from the LipService backend
+    ///
+    /// Server errors and network failures are retried with exponential backoff up to
+    /// `Config::max_retries`; client errors such as a bad API key fail immediately.
+    async fn fetch_policy(http_client: &reqwest::Client, config: &Config) -> Result<SamplingPolicy> {
+        let url = format!(
+            "{}/api/v1/policies/{}?team_id={}",
+            config.lipservice_url.trim_end_matches('/'),
+            config.service_name,
+            config.backend_team_id()?
+        );
+
+        let mut attempt = 0;
+        loop {
+            match Self::fetch_policy_once(http_client, &url).await {
+                Ok(policy) => return Ok(policy),
+                Err(e) if attempt < config.max_retries && Self::is_transient(&e) => {
+                    let backoff = POLICY_RETRY_BACKOFF * 2u32.pow(attempt);
+                    attempt += 1;
+                    debug!(
+                        target: INTERNAL_TARGET,
+                        "Policy fetch attempt {} failed, retrying in {:?}: {}",
+                        attempt,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Single policy fetch attempt
+    async fn fetch_policy_once(http_client: &reqwest::Client, url: &str) -> Result<SamplingPolicy> {
+        let response = http_client.get(url).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LipServiceError::from_status(status.as_u16()).into());
+        }
+
+        Ok(response.json::<PolicyResponse>().await?.into())
+    }
+
+    /// Whether a policy fetch error is worth retrying
+    fn is_transient(error: &anyhow::Error) -> bool {
+        if let Some(e) = error.downcast_ref::<LipServiceError>() {
+            return e.is_transient();
+        }
+        error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout() || e.is_connect())
+    }
+
+    /// Built-in policy used until a backend policy is available
+    fn default_policy() -> SamplingPolicy {
+        SamplingPolicy {
+            policy_id: "default".to_string(),
+            sampling_rate: 0.1,
+            patterns: vec!["error".to_string(), "warning".to_string()],
+            max_logs_per_minute: 1000,
+            severity_rates: std::collections::HashMap::from([
+                ("ERROR".to_string(), 1.0),
+                ("WARNING".to_string(), 0.5),
+                ("INFO".to_string(), 0.1),
+                ("DEBUG".to_string(), 0.05),
+            ]),
+            pattern_rates: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Report pattern statistics
+    async fn report_patterns(
+        http_client: &reqwest::Client,
+        config: &Config,
+        pattern_stats: &Arc<DashMap<String, PatternStats>>,
+        counters: &Arc<SamplerCounters>,
+        represented: &Arc<DashSet<String>>,
+    ) {
+        let count = pattern_stats.len();
+        debug!(target: INTERNAL_TARGET, "Reporting {} patterns", count);
+
+        if count > 0 {
+            let team_id = match config.backend_team_id() {
+                Ok(team_id) => team_id,
+                Err(e) => {
+                    internal_event!(config.internal_level(Level::WARN), "Pattern statistics not reported: {}", e);
+                    represented.clear();
+                    return;
+                }
+            };
+            let patterns: Vec<PatternStats> = pattern_stats.iter().map(|entry| entry.value().clone()).collect();
+            let report = PatternReport::new(config.service_name.clone(), team_id, &patterns);
+            let chunks = report.chunks(config.pattern_report_max_bytes);
+            let total = chunks.len();
+            let mut failed = 0;
+            for chunk in &chunks {
+                match Self::send_pattern_report(http_client, config, chunk).await {
+                    Ok(()) => Self::clear_reported(pattern_stats, chunk),
+                    Err(e) => {
+                        failed += 1;
+                        debug!(target: INTERNAL_TARGET, "Failed to send pattern report chunk: {}", e);
+                    }
+                }
+            }
+            if failed > 0 {
+                internal_event!(
+                    config.internal_level(Level::WARN),
+                    "Failed to report pattern statistics: {} of {} chunks not sent",
+                    failed,
+                    total
+                );
+            }
+        }
+
+        info!(
+            target: INTERNAL_TARGET,
+            pattern_count = count,
+            logs_seen = counters.logs_seen(),
+            logs_sampled = counters.logs_sampled(),
+            reduction_ratio = counters.reduction_ratio(),
+            byte_reduction_ratio = counters.byte_reduction_ratio(),
+            "Pattern statistics reported"
+        );
+
+        // Start a new interval for the keep-one-per-pattern guarantee
+        represented.clear();
+    }
+
+    /// Subtract the counts of a sent report, keeping the occurrences logged since
+    fn clear_reported(pattern_stats: &DashMap<String, PatternStats>, report: &PatternReport) {
+        for reported in &report.patterns {
+            if let Some(mut stats) = pattern_stats.get_mut(&reported.signature) {
+                stats.count = stats.count.saturating_sub(reported.count);
+                stats.sampled_count = stats.sampled_count.saturating_sub(reported.sampled_count);
+            }
+        }
+    }
+
+    /// Send a pattern report, falling back to JSON if the backend rejects the configured format
+    ///
+    /// Backends that only read JSON answer other formats with 415, or with 422 when
+    /// they try to parse the body as JSON anyway.
+    async fn send_pattern_report(http_client: &reqwest::Client, config: &Config, report: &PatternReport) -> Result<()> {
+        let mut format = config.pattern_report_format;
+        loop {
+            let request = Self::pattern_report_request(http_client, config, report, format)?;
+            let status = http_client.execute(request).await?.status();
+
+            let rejected = matches!(
+                status,
+                reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE | reqwest::StatusCode::UNPROCESSABLE_ENTITY
+            );
+            if rejected && format != ReportFormat::Json {
+                internal_event!(
+                    config.internal_level(Level::WARN),
+                    "Backend does not accept {} pattern reports, falling back to JSON",
+                    format.content_type()
+                );
+                format = ReportFormat::Json;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(LipServiceError::from_status(status.as_u16()).into());
+            }
+            return Ok(());
+        }
+    }
+
+    /// Build the HTTP request carrying an encoded pattern report
+    fn pattern_report_request(
+        http_client: &reqwest::Client,
+        config: &Config,
+        report: &PatternReport,
+        format: ReportFormat,
+    ) -> Result<reqwest::Request> {
+        let url = format!("{}/api/v1/patterns/stats", config.lipservice_url.trim_end_matches('/'));
+
+        let request = http_client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, format.content_type())
+            .body(report.encode(format)?)
+            .build()?;
+
+        Ok(request)
+    }
+
+    /// Configuration this sampler was created with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Get current policy
+    pub fn get_policy(&self) -> Option<SamplingPolicy> {
+        self.policy.read().clone()
+    }
+
+    /// Where the current policy came from
+    pub fn policy_source(&self) -> PolicySource {
+        *self.policy_source.read()
+    }
+
+    /// Time since the policy was last successfully loaded
+    pub fn policy_age(&self) -> Duration {
+        self.last_policy_update.read().elapsed()
+    }
+
+    /// Why the latest policy refresh failed, `None` once a policy loads again
+    pub fn last_policy_error(&self) -> Option<PolicyError> {
+        self.last_policy_error.read().clone()
+    }
+
+    /// Whether the policy refresh and pattern report tasks are still running
+    pub fn background_tasks_alive(&self) -> bool {
+        self.background_tasks.lock().iter().all(|task| !task.is_finished())
+    }
+
+    /// Stop the policy refresh and pattern report tasks; also done when dropped
+    pub fn stop_background_tasks(&self) {
+        for task in self.background_tasks.lock().iter() {
+            task.abort();
+        }
+    }
+
+    /// Do the local per-interval work of the background tasks by hand
+    ///
+    /// For samplers from [`AdaptiveSampler::new_standalone`]: call it once per report
+    /// interval to start a new `keep_one_per_interval` window and pick up changes to
+    /// the fingerprint rates file. The backend is not contacted.
+    pub fn tick(&self) {
+        self.represented.clear();
+        if let Some(rates) = &self.fingerprint_rates {
+            rates.reload_if_changed();
+        }
+    }
+
+    /// Fraction of logs dropped over the sampler's lifetime
+    pub fn reduction_ratio(&self) -> f64 {
+        self.counters.reduction_ratio()
+    }
+
+    /// Lifetime sampling counters
+    pub fn counters(&self) -> &SamplerCounters {
+        &self.counters
+    }
+
+    /// Summarize sampling so far, including the `top_n` most frequent patterns
+    pub fn summary(&self, top_n: usize) -> SamplingSummary {
+        let mut top_patterns = self.get_pattern_stats();
+        top_patterns.sort_unstable_by(|a, b| b.count.cmp(&a.count));
+        top_patterns.truncate(top_n);
+
+        let logs_seen = self.counters.logs_seen();
+        let logs_sampled = self.counters.logs_sampled();
+        SamplingSummary {
+            logs_seen,
+            logs_sampled,
+            logs_dropped: logs_seen.saturating_sub(logs_sampled),
+            reduction_ratio: self.counters.reduction_ratio(),
+            top_patterns,
+        }
+    }
+
+    /// Emit the final sampling summary as a self-stats log
+    pub fn log_final_summary(&self) {
+        let summary = self.summary(5);
+        let top_patterns: Vec<String> = summary
+            .top_patterns
+            .iter()
+            .map(|pattern| format!("{}={}", pattern.signature, pattern.count))
+            .collect();
+
+        info!(
+            target: INTERNAL_TARGET,
+            logs_seen = summary.logs_seen,
+            logs_sampled = summary.logs_sampled,
+            logs_dropped = summary.logs_dropped,
+            reduction_ratio = summary.reduction_ratio,
+            top_patterns = ?top_patterns,
+            "Final sampling summary"
+        );
+    }
+
+    /// Write pattern statistics to `Config::pattern_state_path`, if configured
+    ///
+    /// The file is replaced atomically so a crash mid-write can't corrupt it.
+    pub fn save_pattern_state(&self) -> Result<()> {
+        let Some(path) = &self.config.pattern_state_path else {
+            return Ok(());
+        };
+
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_vec(&self.get_pattern_stats())?)?;
+        std::fs::rename(&temp_path, path)?;
+        debug!(target: INTERNAL_TARGET, "Saved {} pattern states to {}", self.pattern_stats.len(), path.display());
+        Ok(())
+    }
+
+    /// Project how `config` would change export volume for the tracked patterns
+    ///
+    /// Each pattern's recorded count is replayed at the rate the new config would give
+    /// it: its blocklist/allowlist entry, otherwise the new static policy, the current
+    /// backend policy or the new default rate, clamped to the new pattern rate bounds.
+    /// Severity and message-based rules can't be replayed, since patterns only keep
+    /// their signature. Live state is left untouched.
+    pub fn simulate(&self, config: &Config) -> SimulationReport {
+        let always_keep: HashSet<String> = config
+            .always_keep_signatures
+            .iter()
+            .cloned()
+            .chain(config.always_keep_messages.iter().map(|message| self.compute_signature(message, &[])))
+            .collect();
+        let backend_policy = match self.policy_source() {
+            PolicySource::Backend | PolicySource::StaleBackend => self.get_policy(),
+            PolicySource::Static | PolicySource::Default => None,
+        };
+
+        let projected_rate = |signature: &str| {
+            if config.always_drop_signatures.iter().any(|dropped| dropped == signature) {
+                return 0.0;
+            }
+            if always_keep.contains(signature) {
+                return 1.0;
+            }
+            let rate = match config.static_policy.as_ref().or(backend_policy.as_ref()) {
+                Some(policy) => policy.pattern_rates.get(signature).copied().unwrap_or(policy.sampling_rate),
+                None => config.default_sampling_rate,
+            };
+            rate.max(config.min_pattern_rate).min(config.max_pattern_rate)
+        };
+
+        let patterns: Vec<PatternProjection> = self
+            .pattern_stats
+            .iter()
+            .map(|entry| {
+                let stats = entry.value();
+                let projected_rate = projected_rate(&stats.signature);
+                PatternProjection {
+                    signature: stats.signature.clone(),
+                    count: stats.count,
+                    current_rate: self.bounded_rate(stats.sampling_rate),
+                    projected_rate,
+                    projected_exports: stats.count as f64 * projected_rate,
+                }
+            })
+            .collect();
+
+        SimulationReport {
+            current_exports: patterns.iter().map(|pattern| pattern.count as f64 * pattern.current_rate).sum(),
+            projected_exports: patterns.iter().map(|pattern| pattern.projected_exports).sum(),
+            patterns,
+        }
+    }
+
+    /// Get pattern statistics
+    pub fn get_pattern_stats(&self) -> Vec<PatternStats> {
+        self.pattern_stats.iter().map(|entry| entry.value().clone()).collect()
+    }
+}
+
+impl Drop for AdaptiveSampler {
+    fn drop(&mut self) {
+        self.stop_background_tasks();
+    }
+}
+
+/// Pattern statistics saved by a previous run; missing or corrupt files yield none
+fn load_pattern_state(path: &Path, internal_level: Level) -> Vec<PatternStats> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            internal_event!(internal_level, "Failed to read pattern state from {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+        internal_event!(internal_level, "Ignoring corrupt pattern state in {}: {}", path.display(), e);
+        Vec::new()
+    })
+}
+
+/// Whether a policy stream failed because the backend has no stream endpoint
+fn serves_no_policy_stream(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<LipServiceError>(),
+        Some(LipServiceError::Rejected { status: 404 | 405 })
+    )
+}
+
+/// Policy carried by a server-sent event's `data:` lines, if it has any
+fn parse_policy_event(event: &str) -> Option<serde_json::Result<SamplingPolicy>> {
+    let data: Vec<&str> = event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    if data.is_empty() {
+        return None;
+    }
+
+    Some(serde_json::from_str(&data.join("\n")))
+}
+
+/// Sampling rate scaled up by a log's priority: `rate * (1 + priority)`, clamped to 1.0
+///
+/// A priority of 0 (or a negative or missing one) leaves the rate unchanged; a priority
+/// of 1 doubles it, and so on.
+fn priority_scaled_rate(rate: f64, priority: f64) -> f64 {
+    let priority = if priority.is_finite() { priority.max(0.0) } else { 0.0 };
+    (rate * (1.0 + priority)).min(1.0)
+}
+
+/// Ticker for pattern reports, first firing now or, if aligned, at the next
+/// wall-clock multiple of `period`
+fn report_interval(period: Duration, aligned: bool) -> tokio::time::Interval {
+    if !aligned {
+        return interval(period);
+    }
+    aligned_interval(SystemTime::now(), period)
+}
+
+/// Ticker first firing at the wall-clock multiple of `period` following `now`
+fn aligned_interval(now: SystemTime, period: Duration) -> tokio::time::Interval {
+    tokio::time::interval_at(tokio::time::Instant::now() + until_next_boundary(now, period), period)
+}
+
+/// Time from `now` to the next multiple of `period` since the Unix epoch
+fn until_next_boundary(now: SystemTime, period: Duration) -> Duration {
+    let period = period.as_nanos();
+    if period == 0 {
+        return Duration::ZERO;
+    }
+    let since_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+    match since_epoch % period {
+        0 => Duration::ZERO,
+        offset => Duration::from_nanos((period - offset) as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        BurstAllowance, DebounceRule, LengthAdjustment, PatternRule, SamplingStrategy, SeverityRemap, VolumeBudget,
+    };
+
+    #[tokio::test]
+    async fn test_adaptive_sampler_creation() {
+        let config = Config::default();
+        let sampler = AdaptiveSampler::new(config).await;
+        assert!(sampler.is_ok());
+    }
+
+    #[test]
+    fn test_standalone_sampler_without_runtime() {
+        let config = Config {
+            default_sampling_rate: 0.0,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^GET /health", 0.0));
+
+        // Constructing and deciding outside any tokio runtime must not panic
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        assert!(sampler.background_tasks.lock().is_empty());
+        assert_eq!(sampler.policy_source(), PolicySource::Default);
+
+        assert!(sampler.should_sample("Database connection failed", "ERROR"));
+        assert!(!sampler.should_sample("GET /health 200", "INFO"));
+        assert_eq!(sampler.counters().logs_seen(), 2);
+    }
+
+    #[test]
+    fn test_empty_message_handling() {
+        let sampler = |handling| {
+            let config = Config::default()
+                .with_empty_messages(handling)
+                .with_pattern_rule(PatternRule::new(".*", 1.0));
+            AdaptiveSampler::new_standalone(config).unwrap()
+        };
+
+        // By default blank messages are sampled and share one signature
+        let keep = sampler(EmptyMessages::Keep);
+        assert!(keep.should_sample("   ", "INFO"));
+        assert_eq!(keep.signature_for("", &[]), keep.signature_for(" \t ", &[("user_id", "7")]));
+
+        let drop = sampler(EmptyMessages::Drop);
+        assert!(!drop.should_sample("   ", "INFO"));
+        assert!(!drop.should_sample("", "DEBUG"));
+        assert!(drop.should_sample(" ", "ERROR"));
+        assert!(drop.should_sample("cache warmed", "INFO"));
+
+        // Placeholders keep blank messages apart by their fields, and away from real messages
+        let placeholder = sampler(EmptyMessages::Placeholder);
+        assert!(placeholder.should_sample("   ", "INFO"));
+        let blank = placeholder.signature_for("   ", &[("user_id", "7")]);
+        assert_eq!(blank, placeholder.signature_for("", &[("user_id", "8")]));
+        assert_ne!(blank, placeholder.signature_for("", &[("order_id", "7")]));
+        assert_ne!(placeholder.signature_for("", &[]), keep.signature_for("", &[]));
+    }
+
+    #[test]
+    fn test_standalone_sampler_driven_by_tick() {
+        let config = Config {
+            default_sampling_rate: 0.0,
+            ..Default::default()
+        }
+        .with_keep_one_per_interval(true);
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        let message = "cache refreshed";
+        track_pattern(&sampler, message, 0.0);
+
+        // Each tick starts a new interval in which the pattern is represented once
+        for _ in 0..3 {
+            let sampled = (0..20).filter(|_| sampler.should_sample(message, "INFO")).count();
+            assert_eq!(sampled, 1);
+            sampler.tick();
+        }
+        assert!(sampler.background_tasks.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_error_sampling() {
+        let config = Config::default();
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        // Error logs should always be sampled
+        assert!(sampler.should_sample("Database connection failed", "ERROR"));
+        assert!(sampler.should_sample("System critical error", "CRITICAL"));
+        assert!(sampler.should_sample("Fatal system error", "FATAL"));
+    }
+
+    #[test]
+    fn test_should_sample_accepts_severity_enum() {
+        let config = Config::default().with_pattern_rule(PatternRule::new("^GET /health", 0.0));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+
+        assert!(sampler.should_sample("Database connection failed", Severity::Error));
+        assert!(sampler.should_sample("Database connection failed", Severity::Fatal));
+        assert!(!sampler.should_sample("GET /health 200", Severity::Info));
+        assert_eq!(
+            sampler.should_sample("GET /health 200", Severity::Info),
+            sampler.should_sample("GET /health 200", "info")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pattern_rule_drops_health_checks() {
+        let config = Config::default().with_pattern_rule(PatternRule::new("/health", 0.0));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        for _ in 0..100 {
+            assert!(!sampler.should_sample("GET /health 200", "INFO"));
+        }
+
+        // Errors still bypass local rules
+        assert!(sampler.should_sample("GET /health 500", "ERROR"));
+    }
+
+    #[tokio::test]
+    async fn test_reduction_ratio() {
+        let config = Config::default().with_pattern_rule(PatternRule::new("^noisy", 0.0));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+        assert_eq!(sampler.reduction_ratio(), 0.0);
+
+        // 25 kept errors, 75 dropped noisy logs of the same size
+        for _ in 0..25 {
+            sampler.should_sample("error job", "ERROR");
+        }
+        for _ in 0..75 {
+            sampler.should_sample("noisy job", "INFO");
+        }
+
+        assert_eq!(sampler.counters().logs_seen(), 100);
+        assert!((sampler.reduction_ratio() - 0.75).abs() < 1e-9);
+        assert!((sampler.counters().byte_reduction_ratio() - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_simulate_lower_default_rate() {
+        let sampler = AdaptiveSampler::new_standalone(Config::default()).unwrap();
+        for (message, count) in [("cache refreshed", 1000), ("user logged in", 200)] {
+            let signature = sampler.signature_computer.compute_signature(message);
+            sampler.pattern_stats.insert(
+                signature.clone(),
+                PatternStats {
+                    count,
+                    sampled_count: 0,
+                    first_seen: SystemTime::now(),
+                    last_seen: SystemTime::now(),
+                    signature,
+                    sampling_rate: 0.1,
+                },
+            );
+        }
+
+        let report = sampler.simulate(&Config::default().with_default_sampling_rate(0.01));
+        assert_eq!(report.patterns.len(), 2);
+        assert!((report.current_exports - 120.0).abs() < 1e-9);
+        assert!((report.projected_exports - 12.0).abs() < 1e-9);
+
+        // Live state is unchanged
+        assert!(sampler.get_pattern_stats().iter().all(|stats| stats.sampling_rate == 0.1));
+        assert_eq!(sampler.config().default_sampling_rate, 0.1);
+    }
+
+    #[test]
+    fn test_severity_remap_downgrades_error() {
+        let policy = SamplingPolicy {
+            policy_id: "static".to_string(),
+            sampling_rate: 1.0,
+            patterns: Vec::new(),
+            max_logs_per_minute: 0,
+            severity_rates: [("WARN".to_string(), 0.0)].into_iter().collect(),
+            pattern_rates: std::collections::HashMap::new(),
+        };
+        let config = Config {
+            static_policy: Some(policy),
+            ..Default::default()
+        }
+        .with_severity_remap(SeverityRemap::new("connection reset by peer", "WARN"));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+
+        // The remapped ERROR is sampled at the WARN rate instead of always kept
+        assert!(!sampler.should_sample("upstream: connection reset by peer", "ERROR"));
+        assert!(sampler.should_sample("Database connection failed", "ERROR"));
+    }
+
+    #[test]
+    fn test_internal_logs_use_internal_target() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        /// Records the target and level of every event
+        struct Recorder(Arc<parking_lot::Mutex<Vec<(String, Level)>>>);
+
+        impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Recorder {
+            fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+                self.0.lock().push((event.metadata().target().to_string(), *event.metadata().level()));
+            }
+        }
+
+        let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(Recorder(Arc::clone(&events)));
+        let config = Config {
+            default_sampling_rate: 1.0,
+            max_tracked_patterns: Some(1),
+            ..Default::default()
+        }
+        .with_internal_log_level("DEBUG");
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        track_pattern(&sampler, "cache warmed", 1.0);
+
+        tracing::subscriber::with_default(subscriber, || {
+            sampler.should_sample("cache warmed", "INFO");
+            sampler.should_sample("user logged in", "INFO");
+            sampler.should_sample("queue drained", "INFO");
+        });
+
+        // The tracking cap is reported once, under the internal target and level
+        assert_eq!(*events.lock(), [(INTERNAL_TARGET.to_string(), Level::DEBUG)]);
+    }
+
+    #[test]
+    fn test_rate_zero_patterns_are_still_counted() {
+        let config = Config::default().with_pattern_rule(PatternRule::new("^GET /health", 0.0));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        let mut policy = AdaptiveSampler::default_policy();
+        policy.pattern_rates.insert(sampler.signature_for("Cache miss for key 1", &[]), 0.0);
+        sampler.set_policy(policy);
+        track_pattern(&sampler, "Cache miss for key 1", 0.0);
+        track_pattern(&sampler, "GET /health 200", 1.0);
+
+        for i in 0..50 {
+            assert!(!sampler.should_sample(&format!("Cache miss for key {}", i), "INFO"));
+            assert!(!sampler.should_sample("GET /health 200", "INFO"));
+        }
+
+        let count = |message| sampler.pattern_stats.get(&sampler.signature_for(message, &[])).map(|stats| stats.count);
+        assert_eq!(count("Cache miss for key 1"), Some(50));
+        assert_eq!(count("GET /health 200"), Some(50));
+    }
+
+    /// Track a pattern as if it had been seen before, with no logs counted yet
+    fn track_pattern(sampler: &AdaptiveSampler, message: &str, sampling_rate: f64) {
+        let signature = sampler.signature_computer.compute_signature(message);
+        sampler.pattern_stats.insert(
+            signature.clone(),
+            PatternStats {
+                count: 0,
+                sampled_count: 0,
+                first_seen: SystemTime::now(),
+                last_seen: SystemTime::now(),
+                signature,
+                sampling_rate,
+            },
+        );
+    }
+
+    #[test]
+    fn test_max_tracked_patterns() {
+        let sampler = AdaptiveSampler::new_standalone(Config::default().with_max_tracked_patterns(2)).unwrap();
+        track_pattern(&sampler, "cache refreshed", 1.0);
+        track_pattern(&sampler, "user logged in", 1.0);
+
+        sampler.should_sample("job queued", "INFO");
+        sampler.should_sample("job finished", "INFO");
+        assert_eq!(sampler.get_pattern_stats().len(), 2);
+        assert_eq!(sampler.counters().patterns_untracked(), 2);
+
+        // Tracked patterns keep updating at the cap
+        sampler.should_sample("cache refreshed", "INFO");
+        let signature = sampler.signature_computer.compute_signature("cache refreshed");
+        assert_eq!(sampler.pattern_stats.get(&signature).unwrap().count, 1);
+        assert!(sampler.pattern_stats.get(&sampler.signature_computer.compute_signature("job queued")).is_none());
+    }
+
+    #[test]
+    fn test_policy_change_reseeds_pattern_rates() {
+        let policy = |rate| SamplingPolicy {
+            policy_id: format!("rate-{}", rate),
+            sampling_rate: rate,
+            patterns: Vec::new(),
+            max_logs_per_minute: 0,
+            severity_rates: std::collections::HashMap::new(),
+            pattern_rates: std::collections::HashMap::new(),
+        };
+        let config = Config {
+            static_policy: Some(policy(1.0)),
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        track_pattern(&sampler, "cache refreshed", 1.0);
+        let signature = sampler.signature_computer.compute_signature("cache refreshed");
+        let generation = sampler.policy_generation.load(Ordering::Relaxed);
+        sampler.seeded_generations.insert(signature.clone(), generation);
+
+        assert!(sampler.should_sample("cache refreshed", "INFO"));
+
+        sampler.set_policy(policy(0.0));
+        assert!((0..20).all(|_| !sampler.should_sample("cache refreshed", "INFO")));
+        assert_eq!(sampler.pattern_stats.get(&signature).unwrap().sampling_rate, 0.0);
+    }
+
+    #[test]
+    fn test_first_seen_pattern_is_tracked() {
+        let sampler = AdaptiveSampler::new_standalone(Config::default()).unwrap();
+        let signature = sampler.signature_computer.compute_signature("cache refreshed");
+        let checkout = sampler.signature_computer.compute_signature("checkout started");
+        sampler.set_policy(SamplingPolicy {
+            policy_id: "pattern-rates".to_string(),
+            sampling_rate: 0.5,
+            patterns: Vec::new(),
+            max_logs_per_minute: 0,
+            severity_rates: std::collections::HashMap::new(),
+            pattern_rates: [(checkout.clone(), 0.25)].into_iter().collect(),
+        });
+        assert!(sampler.get_pattern_stats().is_empty());
+
+        sampler.should_sample("cache refreshed", "INFO");
+        sampler.should_sample("checkout started", "INFO");
+
+        let stats = sampler.get_pattern_stats();
+        assert_eq!(stats.len(), 2);
+        let tracked = |signature: &str| stats.iter().find(|stats| stats.signature == signature).unwrap().clone();
+        assert_eq!(tracked(&signature).count, 1);
+        assert_eq!(tracked(&signature).sampling_rate, 0.5);
+        assert_eq!(tracked(&checkout).sampling_rate, 0.25);
+    }
+
+    #[test]
+    fn test_every_sampled_pattern_is_counted() {
+        let config = Config {
+            always_keep_messages: vec!["user signed in".to_string()],
+            debounce_rules: vec![DebounceRule::new("^disk usage", Duration::from_secs(60))],
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+
+        sampler.should_sample("cache refreshed", "INFO");
+        sampler.should_sample("user signed in", "INFO");
+        sampler.should_sample("disk usage at 91 percent", "INFO");
+        sampler.should_sample("disk usage at 92 percent", "INFO");
+
+        assert_eq!(sampler.pattern_stats.len(), 3);
+        let signature = sampler.signature_computer.compute_signature("disk usage at 91 percent");
+        assert_eq!(sampler.pattern_stats.get(&signature).unwrap().count, 2);
+    }
+
+    #[test]
+    fn test_volume_budget_rate_from_observed_volume() {
+        // A 1000/min pattern under a 100/min budget
+        let start = Instant::now();
+        let mut window = BudgetWindow::new(start);
+        let rates: Vec<f64> = (0..2000u32)
+            .map(|i| window.observe(start + Duration::from_millis(60) * i, 100.0))
+            .collect();
+
+        // The first minute keeps the first 100, later minutes sample at ~10%
+        assert_eq!(rates[..1000].iter().sum::<f64>(), 100.0);
+        let admitted: f64 = rates[1000..].iter().sum();
+        assert!((admitted - 100.0).abs() < 1.0, "admitted {}", admitted);
+    }
+
+    #[test]
+    fn test_volume_budget_caps_first_minute() {
+        let config = Config {
+            default_sampling_rate: 1.0,
+            ..Default::default()
+        }
+        .with_volume_budget(VolumeBudget::for_pattern("^cache miss", 100));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+
+        let kept = (0..1000).filter(|i| sampler.should_sample(&format!("cache miss for key {}", i), "INFO")).count();
+        assert_eq!(kept, 100);
+        assert!(sampler.should_sample("cache warmed", "INFO"));
+    }
+
+    #[tokio::test]
+    async fn test_kept_rate_is_only_reported_for_probabilities() {
+        let config = Config::default()
+            .with_keep_one_per_interval(true)
+            .with_pattern_rule(PatternRule::new("^cache", 1.0))
+            .with_debounce_rule(DebounceRule::new("^disk", Duration::from_secs(60)));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        let rate = |message: &str| sampler.sample_with_fields(message, "INFO", &[]).map(|kept| kept.rate);
+
+        assert_eq!(rate("cache refreshed"), Some(Some(1.0)));
+        assert_eq!(rate("disk almost full"), Some(None));
+        assert_eq!(rate("user signed in"), Some(None), "keep-one isn't a probability");
+        assert_eq!(sampler.sample_with_fields("payment failed", "ERROR", &[]).unwrap().rate, Some(1.0));
+
+        let config = Config::default()
+            .with_pattern_rule(PatternRule::new("^cache", 1.0))
+            .with_sampling_strategy(SamplingStrategy::LeakyBucket { capacity: 10, drain_per_sec: 1.0 });
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        assert_eq!(sampler.sample_with_fields("cache refreshed", "INFO", &[]).unwrap().rate, None);
+    }
+
+    #[tokio::test]
+    async fn test_keep_one_per_interval() {
+        let config = Config::default().with_keep_one_per_interval(true);
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let message = "cache refreshed";
+        let signature = sampler.signature_computer.compute_signature(message);
+        sampler.pattern_stats.insert(
+            signature.clone(),
+            PatternStats {
+                count: 0,
+                sampled_count: 0,
+                first_seen: SystemTime::now(),
+                last_seen: SystemTime::now(),
+                signature,
+                sampling_rate: 0.0,
+            },
+        );
+
+        for _ in 0..2 {
+            let sampled = (0..50).filter(|_| sampler.should_sample(message, "INFO")).count();
+            assert_eq!(sampled, 1);
+
+            sampler.report_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signature_fields() {
+        let config = Config {
+            signature_fields: vec!["status".to_string()],
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let failed = sampler.compute_signature("Payment processed", &[("status", "error"), ("order_id", "1")]);
+        let succeeded = sampler.compute_signature("Payment processed", &[("status", "ok"), ("order_id", "1")]);
+        let other_order = sampler.compute_signature("Payment processed", &[("status", "error"), ("order_id", "2")]);
+
+        assert_ne!(failed, succeeded);
+        assert_eq!(failed, other_order);
+    }
+
+    #[tokio::test]
+    async fn test_always_keep_allowlist() {
+        let config = Config {
+            default_sampling_rate: 0.0,
+            always_keep_messages: vec!["Payment 123 failed for order 456".to_string()],
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        // Any message normalizing to the example's pattern is kept
+        assert!((0..100).all(|i| sampler.should_sample(&format!("Payment {} failed for order {}", i, i * 7), "INFO")));
+        assert!(!sampler.should_sample("Payment succeeded", "INFO"));
+
+        let signature = sampler.signature_computer.compute_signature("Payment 1 failed for order 2");
+        assert!(sampler.should_sample_with_signature(&signature, "INFO"));
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        // The allowlist examples could never match signatures built from field names
+        let config = Config {
+            signature_mode: SignatureMode::Fields,
+            always_keep_messages: vec!["payment failed".to_string()],
+            ..Default::default()
+        };
+        let err = AdaptiveSampler::new_standalone(config).err().unwrap();
+        assert!(matches!(err.downcast_ref::<LipServiceError>(), Some(LipServiceError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_always_drop_blocklist() {
+        let config = Config {
+            default_sampling_rate: 1.0,
+            always_drop_patterns: vec!["^GET /health".to_string()],
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        assert!((0..100).all(|_| !sampler.should_sample("GET /health 200", "INFO")));
+        assert!(sampler.should_sample("GET /orders 200", "INFO"));
+
+        // Zero data loss: errors are never blocklisted
+        assert!(sampler.should_sample("GET /health 200", "ERROR"));
+        assert!(sampler.should_sample("GET /health 200", "CRITICAL"));
+    }
+
+    #[tokio::test]
+    async fn test_burst_allowance() {
+        let config = Config {
+            default_sampling_rate: 0.0,
+            ..Default::default()
+        }
+        .with_burst_allowance(BurstAllowance::new(10, Duration::from_millis(50)));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let burst: Vec<bool> = (0..50).map(|_| sampler.should_sample("retrying upstream call", "INFO")).collect();
+        assert!(burst[..10].iter().all(|kept| *kept));
+        assert!(burst[10..].iter().all(|kept| !*kept));
+
+        // The allowance refills once the pattern goes quiet
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(sampler.should_sample("retrying upstream call", "INFO"));
+    }
+
+    #[tokio::test]
+    async fn test_debounce_rule() {
+        let config = Config::default().with_debounce_rule(DebounceRule::new("^heartbeat", Duration::from_secs(1)));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let sampled = (0..100).filter(|_| sampler.should_sample("heartbeat from worker 7", "INFO")).count();
+        assert_eq!(sampled, 1);
+    }
+
+    #[tokio::test]
+    async fn test_policy_source_after_failed_refresh() {
+        let config = Config::new("test-service".to_string(), "http://127.0.0.1:1".to_string())
+            .with_timeout(Duration::from_millis(200));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let tracking = sampler.policy_tracking();
+        let refresh = || AdaptiveSampler::refresh_policy(&sampler.http_client, &sampler.config, &tracking);
+
+        refresh().await;
+        assert_eq!(sampler.policy_source(), PolicySource::Default);
+        assert!(sampler.get_policy().is_some());
+
+        // A previously fetched backend policy goes stale rather than being replaced
+        *sampler.policy_source.write() = PolicySource::Backend;
+        refresh().await;
+        assert_eq!(sampler.policy_source(), PolicySource::StaleBackend);
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding_ladder() {
+        let config = Config::default().with_pattern_rule(PatternRule::new(".*", 1.0));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+        let kept = |severity| sampler.should_sample("queue drained", severity);
+
+        sampler.set_congestion(0.3);
+        assert!(kept("DEBUG") && kept("TRACE") && kept("INFO"));
+
+        sampler.set_congestion(0.5);
+        assert!(!kept("DEBUG") && kept("TRACE") && kept("INFO"));
+
+        sampler.set_congestion(0.7);
+        assert!(!kept("DEBUG") && !kept("TRACE") && kept("INFO"));
+
+        sampler.set_congestion(1.0);
+        assert!(!kept("DEBUG") && !kept("TRACE") && !kept("INFO"));
+        assert!(kept("WARN") && kept("ERROR"));
+    }
+
+    #[tokio::test]
+    async fn test_consistent_sampling_field() {
+        let config = Config {
+            consistent_sampling_field: Some("session_id".to_string()),
+            sampling_seed: 42,
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^checkout", 0.5));
+        let service_a = AdaptiveSampler::new(config.clone()).await.unwrap();
+        let service_b = AdaptiveSampler::new(config).await.unwrap();
+
+        let mut kept = 0;
+        for i in 0..200 {
+            let session = format!("session-{}", i);
+            let fields = [("session_id", session.as_str())];
+            let decision = service_a.should_sample_with_fields("checkout started", "INFO", &fields);
+
+            // Same session gets the same decision in every service and on every call
+            assert_eq!(decision, service_b.should_sample_with_fields("checkout step 2", "INFO", &fields));
+            assert_eq!(decision, service_a.should_sample_with_fields("checkout started", "INFO", &fields));
+            kept += decision as usize;
+        }
+
+        // Sessions are still spread across the keep and drop buckets
+        assert!(kept > 50 && kept < 150);
+    }
+
+    #[tokio::test]
+    async fn test_session_sampling_keeps_or_drops_whole_sessions() {
+        let config = Config {
+            default_sampling_rate: 0.0,
+            always_keep_messages: vec!["payment declined".to_string()],
+            ..Default::default()
+        }
+        .with_session_sampling("session_id".to_string(), 0.5);
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let chosen = |session: &str| key_kept(sampler.config.sampling_seed, session, 0.5);
+        let sessions: Vec<String> = (0..50).map(|i| format!("session-{}", i)).collect();
+        let kept_session = sessions.iter().find(|session| chosen(session)).unwrap();
+        let dropped_session = sessions.iter().find(|session| !chosen(session)).unwrap();
+
+        let messages = ["cart viewed", "item added", "checkout started", "payment page loaded"];
+        for (i, message) in messages.iter().enumerate() {
+            let severity = if i % 2 == 0 { "INFO" } else { "DEBUG" };
+            assert!(sampler.should_sample_with_fields(message, severity, &[("session_id", kept_session)]));
+            assert!(!sampler.should_sample_with_fields(message, "INFO", &[("session_id", dropped_session)]));
+        }
+
+        // Errors and allowlisted logs survive in sessions that were not chosen
+        assert!(sampler.should_sample_with_fields("payment failed", "ERROR", &[("session_id", dropped_session)]));
+        assert!(sampler.should_sample_with_fields("payment declined", "INFO", &[("session_id", dropped_session)]));
+    }
+
+    #[test]
+    fn test_services_sample_independently() {
+        let sampler = |service_name: &str| {
+            let config = Config {
+                service_name: service_name.to_string(),
+                default_sampling_rate: 0.5,
+                sampling_strategy: SamplingStrategy::DeterministicHash,
+                ..Default::default()
+            };
+            AdaptiveSampler::new_standalone(config).unwrap()
+        };
+        let decisions = |sampler: &AdaptiveSampler| -> Vec<bool> {
+            (0..200).map(|_| sampler.should_sample("cache refreshed", "INFO")).collect()
+        };
+
+        let checkout = decisions(&sampler("checkout"));
+        assert_eq!(checkout, decisions(&sampler("checkout")));
+        assert_ne!(checkout, decisions(&sampler("payments")));
+    }
+
+    #[tokio::test]
+    async fn test_priority_field_scales_rate() {
+        let config = Config {
+            default_sampling_rate: 0.1,
+            ..Default::default()
+        }
+        .with_priority_field("priority".to_string());
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        let urgent = [("priority", "10")];
+        assert!((0..200).all(|_| sampler.should_sample_with_fields("job finished", "INFO", &urgent)));
+
+        let routine = [("priority", "0")];
+        let kept = (0..1000)
+            .filter(|_| sampler.should_sample_with_fields("job finished", "INFO", &routine))
+            .count();
+        assert!(kept > 50 && kept < 200);
+
+        assert_eq!(priority_scaled_rate(0.1, 1.0), 0.2);
+        assert_eq!(priority_scaled_rate(0.1, -3.0), 0.1);
+    }
+
+    #[test]
+    fn test_length_adjustments_favor_long_messages() {
+        let config = Config {
+            default_sampling_rate: 0.1,
+            ..Default::default()
+        }
+        .with_length_adjustment(LengthAdjustment::new(0, 0.5))
+        .with_length_adjustment(LengthAdjustment::new(1024, 5.0));
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        assert_eq!(sampler.length_multiplier(20), 0.5);
+        assert_eq!(sampler.length_multiplier(2048), 5.0);
+
+        let long = format!("request dump {}", "a".repeat(2048 - 13));
+        let short = "cache miss for key";
+        let kept_long = (0..2000).filter(|_| sampler.should_sample(&long, "INFO")).count();
+        let kept_short = (0..2000).filter(|_| sampler.should_sample(short, "INFO")).count();
+
+        // Roughly 50% versus 5% of the same 0.1 base rate
+        assert!(kept_long > 800, "kept {} long messages", kept_long);
+        assert!(kept_short < 200, "kept {} short messages", kept_short);
+    }
+
+    #[tokio::test]
+    async fn test_should_sample_with_signature_matches_should_sample() {
+        let sampler = AdaptiveSampler::new(Config::default()).await.unwrap();
+
+        for (message, rate) in [("always kept", 1.0), ("never kept", 0.0)] {
+            let signature = sampler.signature_computer.compute_signature(message);
+            sampler.pattern_stats.insert(
+                signature.clone(),
+                PatternStats {
+                    count: 0,
+                    sampled_count: 0,
+                    first_seen: SystemTime::now(),
+                    last_seen: SystemTime::now(),
+                    signature: signature.clone(),
+                    sampling_rate: rate,
+                },
+            );
+
+            for severity in ["INFO", "DEBUG", "ERROR"] {
+                assert_eq!(
+                    sampler.should_sample(message, severity),
+                    sampler.should_sample_with_signature(&signature, severity)
+                );
+            }
+        }
+    }
+
+    /// Config pointing at a mock backend, with the team id backend requests need
+    fn backend_config(url: String) -> Config {
+        Config::new("test-service".to_string(), url).with_posthog("phc_test".to_string(), "12345".to_string())
+    }
+
+    /// Serve every request with an empty response of the given status, counting requests
+    async fn mock_backend(status: u16) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        scripted_backend(&[status]).await
+    }
+
+    /// Serve requests with empty responses of the given statuses in turn, repeating the
+    /// last one, counting requests
+    async fn scripted_backend(statuses: &[u16]) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let statuses = statuses.to_vec();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[request.min(statuses.len() - 1)];
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = format!("HTTP/1.1 {} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_now_applies_the_fetched_policy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // The backend's PolicyResponse
+        let body = r#"{"global_rate": 0.5, "severity_rates": {"INFO": 0.25}, "pattern_rates": {},
+            "anomaly_boost": 2.0, "reasoning": null, "generated_by": "llm", "llm_model": null, "version": 2}"#;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let read = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..read]);
+                seen.lock().push(request.lines().next().unwrap_or_default().to_string());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        // Without background tasks nothing is fetched until asked
+        let sampler = AdaptiveSampler::new_standalone(backend_config(url)).unwrap();
+        assert_eq!(sampler.policy_source(), PolicySource::Default);
+
+        sampler.refresh_now().await;
+        assert_eq!(sampler.policy_source(), PolicySource::Backend);
+        let policy = sampler.get_policy().unwrap();
+        assert_eq!(policy.policy_id, "llm-v2");
+        assert_eq!(policy.severity_rates.get("INFO"), Some(&0.25));
+        assert_eq!(sampler.last_policy_error(), None);
+        assert_eq!(*requests.lock(), ["GET /api/v1/policies/test-service?team_id=12345 HTTP/1.1"]);
+    }
+
+    #[tokio::test]
+    async fn test_last_policy_error_reports_failed_fetch() {
+        let (url, _hits) = mock_backend(500).await;
+        let config = backend_config(url).with_max_retries(0);
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+        sampler.stop_background_tasks();
+        let before = SystemTime::now();
+
+        sampler.refresh_now().await;
+
+        let error = sampler.last_policy_error().expect("failed refresh should be recorded");
+        assert_eq!(error.status, Some(500));
+        assert!(error.message.contains("500"), "{}", error.message);
+        assert!(error.at >= before);
+
+        // A policy loading again clears it
+        sampler.set_policy(AdaptiveSampler::default_policy());
+        assert_eq!(sampler.last_policy_error(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_sampler_stops_background_tasks() {
+        let (url, hits) = mock_backend(404).await;
+        let config = Config {
+            policy_refresh_interval: Duration::from_millis(20),
+            ..backend_config(url)
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(hits.load(Ordering::SeqCst) > 1);
+
+        drop(sampler);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let after_drop = hits.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(hits.load(Ordering::SeqCst), after_drop);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_policy_is_applied() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut pushed = AdaptiveSampler::default_policy();
+        pushed.policy_id = "pushed".to_string();
+        pushed.sampling_rate = 0.25;
+        let event = format!("event: policy\ndata: {}\n\n", serde_json::to_string(&pushed).unwrap());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let read = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..read]).into_owned();
+                let event = event.clone();
+                tokio::spawn(async move {
+                    if !request.contains("/stream?team_id=12345 ") {
+                        let response = b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                        let _ = socket.write_all(response).await;
+                        return;
+                    }
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n")
+                        .await;
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    let _ = socket.write_all(event.as_bytes()).await;
+                    // Keep the stream open like a real SSE endpoint
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+
+        let config = Config {
+            policy_streaming: true,
+            ..backend_config(url)
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while sampler.get_policy().map(|policy| policy.policy_id) != Some("pushed".to_string()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("pushed policy should be applied");
+        assert_eq!(sampler.policy_source(), PolicySource::Backend);
+    }
+
+    #[tokio::test]
+    async fn test_policy_stream_stops_when_backend_lacks_it() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let streams = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&streams);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let read = socket.read(&mut buf).await.unwrap_or(0);
+                if String::from_utf8_lossy(&buf[..read]).contains("/stream") {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+                let response = b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                let _ = socket.write_all(response).await;
+            }
+        });
+
+        let config = Config {
+            policy_streaming: true,
+            policy_refresh_interval: Duration::from_millis(20),
+            max_retries: 0,
+            ..backend_config(url)
+        };
+        let _sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(streams.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_policy_fetch_does_not_retry_auth_errors() {
+        let (url, hits) = mock_backend(401).await;
+        let config = backend_config(url).with_max_retries(3);
+
+        let err = AdaptiveSampler::fetch_policy(&reqwest::Client::new(), &config).await.unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LipServiceError>(),
+            Some(LipServiceError::Unauthorized { status: 401 })
+        ));
+        assert!(err.to_string().contains("api_key"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_policy_fetch_retries_unavailable() {
+        let (url, hits) = mock_backend(503).await;
+        let config = backend_config(url).with_max_retries(2);
+
+        let err = AdaptiveSampler::fetch_policy(&reqwest::Client::new(), &config).await.unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LipServiceError>(),
+            Some(LipServiceError::Unavailable { status: 503 })
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_window() {
+        let config = Config {
+            warmup_duration: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^noisy", 0.0));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        assert!(sampler.in_warmup());
+        assert!((0..20).all(|_| sampler.should_sample("noisy startup log", "DEBUG")));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        assert!(!sampler.in_warmup());
+        assert!(!sampler.should_sample("noisy startup log", "DEBUG"));
+    }
+
+    #[test]
+    fn test_pattern_report_formats() {
+        let report = PatternReport::new(
+            "test-service",
+            12345,
+            &[PatternStats {
+                count: 42,
+                sampled_count: 10,
+                first_seen: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+                last_seen: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_060),
+                signature: "abc123".to_string(),
+                sampling_rate: 0.25,
+            }],
+        );
+        let config = Config::default();
+
+        // The backend's PatternStatsRequest
+        let json: serde_json::Value = serde_json::from_slice(&report.encode(ReportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["team_id"], 12345);
+        assert_eq!(json["total_logs"], 42);
+        assert_eq!(json["unique_patterns"], 1);
+        assert!(json["timestamp"].as_f64().unwrap() > 1_700_000_000.0);
+        assert_eq!(
+            json["patterns"][0],
+            serde_json::json!({
+                "signature": "abc123",
+                "count": 42,
+                "sampled_count": 10,
+                "first_seen": 1_700_000_000.0,
+                "last_seen": 1_700_000_060.0,
+            })
+        );
+        let client = reqwest::Client::new();
+
+        for format in [ReportFormat::Json, ReportFormat::MessagePack] {
+            let bytes = report.encode(format).unwrap();
+            assert_eq!(PatternReport::decode(&bytes, format).unwrap(), report);
+
+            let request = AdaptiveSampler::pattern_report_request(&client, &config, &report, format).unwrap();
+            assert_eq!(request.headers()[reqwest::header::CONTENT_TYPE], format.content_type());
+        }
+
+        assert_eq!(ReportFormat::MessagePack.content_type(), "application/msgpack");
+        assert!(
+            report.encode(ReportFormat::MessagePack).unwrap().len() < report.encode(ReportFormat::Json).unwrap().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pattern_report_is_chunked() {
+        let (url, hits) = mock_backend(200).await;
+        let config = Config {
+            pattern_report_max_bytes: 1024,
+            ..backend_config(url)
+        };
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        for i in 0..100 {
+            sampler.should_sample(&format!("job {} finished", "x".repeat(i)), "INFO");
+        }
+        assert_eq!(sampler.pattern_stats.len(), 100);
+
+        let report = PatternReport::new("test-service", 12345, &sampler.get_pattern_stats());
+        let chunks = report.chunks(1024);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.encode(ReportFormat::Json).unwrap().len() <= 1024));
+        let mut sent: Vec<&str> =
+            chunks.iter().flat_map(|chunk| &chunk.patterns).map(|stats| stats.signature.as_str()).collect();
+        sent.sort();
+        sent.dedup();
+        assert_eq!(sent.len(), 100);
+
+        AdaptiveSampler::report_patterns(
+            &sampler.http_client,
+            &sampler.config,
+            &sampler.pattern_stats,
+            &sampler.counters,
+            &sampler.represented,
+        )
+        .await;
+        assert_eq!(hits.load(Ordering::SeqCst), chunks.len());
+        assert_eq!(chunks.iter().map(|chunk| chunk.total_logs).sum::<u64>(), 100);
+        assert!(chunks.iter().all(|chunk| chunk.unique_patterns == chunk.patterns.len()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_report_chunks_keep_their_counts() {
+        let (url, hits) = scripted_backend(&[500, 200]).await;
+        let config = Config {
+            pattern_report_max_bytes: 1024,
+            ..backend_config(url)
+        };
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        for i in 0..100 {
+            sampler.should_sample(&format!("job {} finished", "x".repeat(i)), "INFO");
+        }
+        let chunks = PatternReport::new("test-service", 12345, &sampler.get_pattern_stats()).chunks(1024);
+        assert!(chunks.len() > 1);
+
+        sampler.report_now().await;
+        assert_eq!(hits.load(Ordering::SeqCst), chunks.len());
+
+        // Only the patterns of the rejected first chunk are still counted, for the next report
+        let mut pending: Vec<String> = sampler
+            .get_pattern_stats()
+            .into_iter()
+            .filter(|stats| stats.count > 0)
+            .map(|stats| stats.signature)
+            .collect();
+        pending.sort();
+        let mut rejected: Vec<String> = chunks[0].patterns.iter().map(|stats| stats.signature.clone()).collect();
+        rejected.sort();
+        assert_eq!(pending, rejected);
+        assert_eq!(sampler.pattern_stats.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_unprocessable_msgpack_report_falls_back_to_json() {
+        let (url, hits) = mock_backend(422).await;
+        let config = backend_config(url).with_pattern_report_format(ReportFormat::MessagePack);
+        let report = PatternReport::new("test-service", 12345, &[]);
+        let client = reqwest::Client::new();
+
+        assert!(AdaptiveSampler::send_pattern_report(&client, &config, &report).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // JSON itself is not retried
+        let config = Config {
+            pattern_report_format: ReportFormat::Json,
+            ..config
+        };
+        assert!(AdaptiveSampler::send_pattern_report(&client, &config, &report).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_pattern_stats_count_kept_logs() {
+        let config = Config::default()
+            .with_pattern_rule(PatternRule::new("^GET /health", 0.0))
+            .with_keep_one_per_interval(true);
+        let sampler = AdaptiveSampler::new_standalone(config).unwrap();
+        for _ in 0..5 {
+            sampler.should_sample("GET /health 200", "INFO");
+            sampler.should_sample_with_fields("cache warmed", "INFO", &[]);
+        }
+
+        let stats = |message| sampler.pattern_stats.get(&sampler.signature_for(message, &[])).unwrap().clone();
+        assert_eq!((stats("GET /health 200").count, stats("GET /health 200").sampled_count), (5, 0));
+        let warmed = stats("cache warmed");
+        assert_eq!(warmed.count, 5);
+        assert!(warmed.sampled_count >= 1 && warmed.sampled_count <= 5);
+        assert!(warmed.first_seen <= warmed.last_seen);
+    }
+
+    #[test]
+    fn test_until_next_boundary() {
+        let minute = Duration::from_secs(60);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(until_next_boundary(at(120), minute), Duration::ZERO);
+        assert_eq!(until_next_boundary(at(125), minute), Duration::from_secs(55));
+        assert_eq!(until_next_boundary(at(179), minute), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_aligned_report_interval_fires_on_boundary() {
+        let minute = Duration::from_secs(60);
+        let start = tokio::time::Instant::now();
+        let mut interval = aligned_interval(SystemTime::UNIX_EPOCH + Duration::from_secs(150), minute);
+
+        // Paused time jumps straight to each tick
+        interval.tick().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+        interval.tick().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(90));
+    }
+
+    #[tokio::test]
+    async fn test_initial_rate_from_policy() {
+        let sampler = AdaptiveSampler::new(Config::default()).await.unwrap();
+        let message = "poll completed";
+        let signature = sampler.signature_computer.compute_signature(message);
+
+        let mut policy = AdaptiveSampler::default_policy();
+        policy.severity_rates.insert("INFO".to_string(), 1.0);
+        policy.pattern_rates.insert(signature, 0.0);
+        *sampler.policy.write() = Some(policy);
+        *sampler.policy_source.write() = PolicySource::Backend;
+
+        // Even the first occurrence uses the policy's pattern rate
+        assert!(!sampler.should_sample(message, "INFO"));
+        assert!((0..20).all(|_| !sampler.should_sample(message, "INFO")));
+
+        // Patterns without a policy rate fall back to the severity rate
+        assert!(sampler.should_sample("another message", "INFO"));
+    }
+
+    #[tokio::test]
+    async fn test_pattern_rate_bounds() {
+        let config = Config {
+            min_pattern_rate: 0.05,
+            max_pattern_rate: 0.9,
+            ..Default::default()
+        };
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        assert_eq!(sampler.bounded_rate(0.001), 0.05);
+        assert_eq!(sampler.bounded_rate(1.0), 0.9);
+        assert_eq!(sampler.bounded_rate(0.3), 0.3);
+
+        // A policy rate of zero no longer hides the pattern entirely
+        let message = "poll completed";
+        let mut policy = AdaptiveSampler::default_policy();
+        policy.pattern_rates.insert(sampler.signature_computer.compute_signature(message), 0.0);
+        *sampler.policy.write() = Some(policy);
+        *sampler.policy_source.write() = PolicySource::Backend;
+        assert!((0..1000).any(|_| sampler.should_sample(message, "INFO")));
+    }
+
+    #[tokio::test]
+    async fn test_pattern_state_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!("lipservice-state-{}.json", uuid::Uuid::new_v4()));
+        let config = Config {
+            pattern_state_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let sampler = AdaptiveSampler::new(config.clone()).await.unwrap();
+        assert!(sampler.get_pattern_stats().is_empty());
+        sampler.pattern_stats.insert(
+            "learned".to_string(),
+            PatternStats {
+                count: 12,
+                sampled_count: 0,
+                first_seen: SystemTime::now(),
+                last_seen: SystemTime::now(),
+                signature: "learned".to_string(),
+                sampling_rate: 0.37,
+            },
+        );
+        sampler.save_pattern_state().unwrap();
+
+        let restarted = AdaptiveSampler::new(config.clone()).await.unwrap();
+        let restored = restarted.get_pattern_stats();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].signature, "learned");
+        assert_eq!(restored[0].sampling_rate, 0.37);
+
+        // A corrupt file starts from scratch instead of failing
+        std::fs::write(&path, b"{not json").unwrap();
+        let recovered = AdaptiveSampler::new(config).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(recovered.get_pattern_stats().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_rates_file_throttles_signatures() {
+        let path = std::env::temp_dir().join(format!("lipservice-fingerprints-{}.json", uuid::Uuid::new_v4()));
+        let noisy = SignatureComputer::new().compute_signature("Cache miss for key 42");
+        let write_rate = |rate: f64| {
+            std::fs::write(&path, serde_json::to_string(&HashMap::from([(noisy.clone(), rate)])).unwrap()).unwrap()
+        };
+        write_rate(0.0);
+        let config = Config::default()
+            .with_fingerprint_rates(&path)
+            .with_pattern_rule(PatternRule::new(".*", 1.0))
+            .with_session_sampling("session_id".to_string(), 0.0);
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        assert!(!sampler.should_sample("Cache miss for key 7", "INFO"));
+        assert!(!sampler.should_sample_with_signature(&noisy, "WARN"));
+        assert!(sampler.should_sample("Cache hit for key 7", "INFO"));
+        assert!(sampler.should_sample("Cache miss for key 7", "ERROR"));
+
+        // Edits are picked up on reload
+        let rates = sampler.fingerprint_rates.as_ref().unwrap();
+        write_rate(1.0);
+        *rates.modified.lock() = None;
+        rates.reload_if_changed();
+        std::fs::remove_file(&path).unwrap();
+        assert!(sampler.should_sample("Cache miss for key 7", "INFO"));
+
+        // Fingerprint rates outrank session sampling
+        assert!(!sampler.should_sample_with_fields("Cache hit for key 7", "INFO", &[("session_id", "s1")]));
+        assert!(sampler.should_sample_with_fields("Cache miss for key 7", "INFO", &[("session_id", "s1")]));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_drops() {
+        let path = std::env::temp_dir().join(format!("lipservice-audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let config = Config {
+            audit_log_path: Some(path.clone()),
+            ..Default::default()
+        }
+        .with_pattern_rule(PatternRule::new("^noisy", 0.0));
+        let sampler = AdaptiveSampler::new(config).await.unwrap();
+
+        assert!(!sampler.should_sample("noisy poll", "INFO"));
+        assert!(sampler.should_sample("noisy failure", "ERROR"));
+        assert!(sampler.should_sample("noisy crash", "FATAL"));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("\"severity\":\"INFO\""));
+        assert!(lines[0].contains("\"reason\":\"pattern_rule\""));
+        assert!(!contents.contains("ERROR") && !contents.contains("FATAL"));
     }
 
     #[tokio::test]
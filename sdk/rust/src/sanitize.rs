@@ -0,0 +1,90 @@
+//! Message sanitization module
+//!
+//! This module cleans log messages before they are signed or exported, so binary or
+//! control bytes can't corrupt signatures or OTLP payloads.
+
+use std::borrow::Cow;
+
+/// Longest run of non-whitespace characters kept intact
+pub const MAX_TOKEN_CHARS: usize = 256;
+
+/// Marker appended to a token cut at [`MAX_TOKEN_CHARS`]
+const TRUNCATION_MARKER: char = '…';
+
+/// Replace control characters (other than newlines and tabs) with U+FFFD and cut
+/// tokens longer than [`MAX_TOKEN_CHARS`]
+///
+/// Clean messages are returned without allocating.
+pub fn sanitize_message(message: &str) -> Cow<'_, str> {
+    if is_clean(message) {
+        return Cow::Borrowed(message);
+    }
+
+    let mut sanitized = String::with_capacity(message.len());
+    let mut token_chars = 0;
+    for c in message.chars() {
+        if c.is_whitespace() {
+            token_chars = 0;
+        } else {
+            token_chars += 1;
+            if token_chars == MAX_TOKEN_CHARS + 1 {
+                sanitized.push(TRUNCATION_MARKER);
+            }
+            if token_chars > MAX_TOKEN_CHARS {
+                continue;
+            }
+        }
+
+        if is_unwanted_control(c) {
+            sanitized.push(char::REPLACEMENT_CHARACTER);
+        } else {
+            sanitized.push(c);
+        }
+    }
+
+    Cow::Owned(sanitized)
+}
+
+/// Decode possibly invalid UTF-8 and sanitize the result
+pub fn sanitize_bytes(bytes: &[u8]) -> String {
+    sanitize_message(&String::from_utf8_lossy(bytes)).into_owned()
+}
+
+fn is_unwanted_control(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+fn is_clean(message: &str) -> bool {
+    let mut token_chars = 0;
+    for c in message.chars() {
+        if is_unwanted_control(c) {
+            return false;
+        }
+        if c.is_whitespace() {
+            token_chars = 0;
+        } else {
+            token_chars += 1;
+            if token_chars > MAX_TOKEN_CHARS {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_message() {
+        assert!(matches!(sanitize_message("User 42 logged in\n\tat login()"), Cow::Borrowed(_)));
+
+        assert_eq!(sanitize_message("bad\0byte\x1b[31m here"), "bad\u{FFFD}byte\u{FFFD}[31m here");
+        assert_eq!(sanitize_bytes(b"invalid \xff utf8"), "invalid \u{FFFD} utf8");
+
+        let long_token = "a".repeat(MAX_TOKEN_CHARS + 100);
+        let sanitized = sanitize_message(&format!("blob {} end", long_token));
+        assert_eq!(sanitized, format!("blob {}… end", "a".repeat(MAX_TOKEN_CHARS)));
+    }
+}
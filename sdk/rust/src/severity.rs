@@ -0,0 +1,253 @@
+//! Severity mapping module
+//!
+//! This module is the single place where tracing levels, OTLP severities and
+//! LipService severity strings are converted into each other.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::Level;
+
+/// Severity of a log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+/// Error returned when parsing an unknown severity
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown severity {0:?}")]
+pub struct ParseSeverityError(String);
+
+impl Severity {
+    /// Every severity, from least to most severe
+    pub const ALL: [Severity; 6] = [Self::Trace, Self::Debug, Self::Info, Self::Warn, Self::Error, Self::Fatal];
+
+    /// Canonical uppercase name, e.g. "WARN"
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+            Self::Fatal => "FATAL",
+        }
+    }
+
+    /// Whether logs of this severity are always kept
+    pub fn is_error(self) -> bool {
+        self >= Self::Error
+    }
+
+    /// Closest tracing level; tracing has no level above ERROR
+    pub fn level(self) -> Level {
+        match self {
+            Self::Trace => Level::TRACE,
+            Self::Debug => Level::DEBUG,
+            Self::Info => Level::INFO,
+            Self::Warn => Level::WARN,
+            Self::Error | Self::Fatal => Level::ERROR,
+        }
+    }
+
+    /// OTLP severity number
+    pub fn otel(self) -> opentelemetry::logs::Severity {
+        use opentelemetry::logs::Severity as Otel;
+        match self {
+            Self::Trace => Otel::Trace,
+            Self::Debug => Otel::Debug,
+            Self::Info => Otel::Info,
+            Self::Warn => Otel::Warn,
+            Self::Error => Otel::Error,
+            Self::Fatal => Otel::Fatal,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = ParseSeverityError;
+
+    /// Parse a severity case-insensitively, accepting WARNING and CRITICAL as aliases
+    fn from_str(severity: &str) -> Result<Self, Self::Err> {
+        match severity.to_uppercase().as_str() {
+            "TRACE" => Ok(Self::Trace),
+            "DEBUG" => Ok(Self::Debug),
+            "INFO" => Ok(Self::Info),
+            "WARN" | "WARNING" => Ok(Self::Warn),
+            "ERROR" => Ok(Self::Error),
+            "FATAL" | "CRITICAL" => Ok(Self::Fatal),
+            _ => Err(ParseSeverityError(severity.to_string())),
+        }
+    }
+}
+
+/// Lenient conversion for string APIs: unknown severities are treated as WARN
+impl From<&str> for Severity {
+    fn from(severity: &str) -> Self {
+        severity.parse().unwrap_or_else(|_| unrecognised(severity))
+    }
+}
+
+/// Severity of unknown strings, high enough that typos like "EROR" aren't sampled away
+const UNRECOGNISED: Severity = Severity::Warn;
+
+/// Map an unknown severity to [`UNRECOGNISED`], warning the first time it happens
+fn unrecognised(severity: &str) -> Severity {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            target: crate::internal::INTERNAL_TARGET,
+            severity,
+            "Unrecognised severity, treating it as {}",
+            UNRECOGNISED
+        );
+    }
+    UNRECOGNISED
+}
+
+impl From<Level> for Severity {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::TRACE => Self::Trace,
+            Level::DEBUG => Self::Debug,
+            Level::INFO => Self::Info,
+            Level::WARN => Self::Warn,
+            Level::ERROR => Self::Error,
+        }
+    }
+}
+
+/// A severity together with the label it was given, e.g. CRITICAL for [`Severity::Fatal`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabeledSeverity {
+    pub severity: Severity,
+    /// Uppercase label; the canonical name unless an alias was used
+    pub text: Cow<'static, str>,
+}
+
+impl From<Severity> for LabeledSeverity {
+    fn from(severity: Severity) -> Self {
+        Self {
+            severity,
+            text: Cow::Borrowed(severity.as_str()),
+        }
+    }
+}
+
+impl LabeledSeverity {
+    /// OTLP severity number; with `split_fatal`, FATAL is exported as FATAL4 so it
+    /// stays distinguishable from CRITICAL, which is exported as FATAL
+    pub fn otel(&self, split_fatal: bool) -> opentelemetry::logs::Severity {
+        if split_fatal && self.severity == Severity::Fatal && self.text != "CRITICAL" {
+            return opentelemetry::logs::Severity::Fatal4;
+        }
+        self.severity.otel()
+    }
+}
+
+/// Lenient like `Severity::from`: unknown severities are labeled WARN
+impl From<&str> for LabeledSeverity {
+    fn from(label: &str) -> Self {
+        let Ok(severity) = label.parse::<Severity>() else {
+            return unrecognised(label).into();
+        };
+        let text = label.to_uppercase();
+        if text == severity.as_str() {
+            return severity.into();
+        }
+        Self {
+            severity,
+            text: Cow::Owned(text),
+        }
+    }
+}
+
+impl From<Level> for LabeledSeverity {
+    fn from(level: Level) -> Self {
+        Severity::from(level).into()
+    }
+}
+
+/// Map a tracing level to its LipService severity
+pub fn severity_from_level(level: Level) -> &'static str {
+    Severity::from(level).as_str()
+}
+
+/// Map a LipService severity to the closest tracing level
+///
+/// Tracing has no level above ERROR, so FATAL and CRITICAL map to ERROR. Unknown
+/// severities map to WARN.
+pub fn level_from_severity(severity: &str) -> Level {
+    Severity::from(severity).level()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_round_trip() {
+        for (level, severity) in [
+            (Level::TRACE, "TRACE"),
+            (Level::DEBUG, "DEBUG"),
+            (Level::INFO, "INFO"),
+            (Level::WARN, "WARN"),
+            (Level::ERROR, "ERROR"),
+        ] {
+            assert_eq!(severity_from_level(level), severity);
+            assert_eq!(level_from_severity(severity), level);
+        }
+    }
+
+    #[test]
+    fn test_severity_aliases() {
+        assert_eq!(level_from_severity("warning"), Level::WARN);
+        assert_eq!(level_from_severity("FATAL"), Level::ERROR);
+        assert_eq!(level_from_severity("CRITICAL"), Level::ERROR);
+        assert_eq!(level_from_severity("unknown"), Level::WARN);
+        assert_eq!(level_from_severity("EROR"), Level::WARN);
+    }
+
+    #[test]
+    fn test_severity_from_str_display_round_trip() {
+        for severity in Severity::ALL {
+            assert_eq!(severity.to_string().parse::<Severity>(), Ok(severity));
+            assert_eq!(severity.to_string().to_lowercase().parse::<Severity>(), Ok(severity));
+        }
+        assert_eq!("critical".parse::<Severity>(), Ok(Severity::Fatal));
+        assert!("verbose".parse::<Severity>().is_err());
+    }
+
+    #[test]
+    fn test_labeled_severity_keeps_alias() {
+        let critical = LabeledSeverity::from("critical");
+        assert_eq!(critical.severity, Severity::Fatal);
+        assert_eq!(critical.text, "CRITICAL");
+        assert_eq!(LabeledSeverity::from("error").text, "ERROR");
+        assert_eq!(LabeledSeverity::from("verbose"), LabeledSeverity::from(Severity::Warn));
+    }
+
+    #[test]
+    fn test_split_fatal_severity_numbers() {
+        let critical = LabeledSeverity::from("CRITICAL");
+        let fatal = LabeledSeverity::from("FATAL");
+        assert_eq!(critical.otel(false) as i32, fatal.otel(false) as i32);
+
+        assert_eq!(critical.otel(true) as i32, 21);
+        assert_eq!(fatal.otel(true) as i32, 24);
+        assert_eq!(LabeledSeverity::from(Severity::Error).otel(true) as i32, 17);
+    }
+}
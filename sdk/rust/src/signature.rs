@@ -2,44 +2,448 @@
 //! 
 //! This module provides efficient signature computation for log pattern analysis.
 
+use crate::sanitize::sanitize_message;
+use lru::LruCache;
 use md5::{Digest, Md5};
+use parking_lot::Mutex;
 use regex::Regex;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Turns a normalized log pattern into its signature
+pub trait SignatureHasher: Send + Sync {
+    /// Hash a normalized pattern
+    fn hash(&self, normalized: &str) -> String;
+}
+
+/// Default hasher producing a hex-encoded MD5 digest
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Md5Hasher;
+
+impl SignatureHasher for Md5Hasher {
+    fn hash(&self, normalized: &str) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(normalized.as_bytes());
+        let result = hasher.finalize();
+        format!("{:x}", result)
+    }
+}
+
+/// Collapses whole URLs; replaced by a host-only rule under path normalization
+const URL_PATTERN: &str = r"https?://[^\s]+";
+
+/// Absolute Unix file paths of two or more segments, keeping the file extension
+const UNIX_FILE_PATH_PATTERN: &str =
+    r#"(^|[\s'"(=\[])(?:/[\w.@-]+)+/[\w.@-]*?(\.[a-z0-9]{1,8})?([\s'")\],;:.]|$)"#;
+
+/// Absolute Windows file paths (messages are lowercased first), keeping the file extension
+const WINDOWS_FILE_PATH_PATTERN: &str =
+    r#"(^|[\s'"(=\[])[a-z]:\\(?:[^\s\\]+\\)*[^\s\\]*?(\.[a-z0-9]{1,8})?([\s'")\],;:.]|$)"#;
+
+/// Default number of messages whose signatures are cached
+const SIGNATURE_CACHE_CAPACITY: usize = 10_000;
+
+/// Independently locked parts of the signature cache, so threads rarely contend
+const SIGNATURE_CACHE_SHARDS: usize = 16;
+
+/// Longer messages are rarely repeated verbatim, so they are never cached
+const MAX_CACHED_MESSAGE_LEN: usize = 1024;
+
+/// Bounded cache from raw messages to signatures, evicting the least recently used
+struct SignatureCache {
+    shards: Vec<Mutex<LruCache<String, String>>>,
+}
+
+impl SignatureCache {
+    /// Cache up to about `capacity` messages; 0 disables caching
+    fn new(capacity: usize) -> Self {
+        let shards = SIGNATURE_CACHE_SHARDS.min(capacity);
+        let per_shard = NonZeroUsize::new(capacity.div_ceil(shards.max(1))).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(LruCache::new(per_shard))).collect(),
+        }
+    }
+
+    /// Shard holding a message, if the message may be cached at all
+    fn shard(&self, message: &str) -> Option<&Mutex<LruCache<String, String>>> {
+        if self.shards.is_empty() || message.len() > MAX_CACHED_MESSAGE_LEN {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        message.hash(&mut hasher);
+        Some(&self.shards[hasher.finish() as usize % self.shards.len()])
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    fn clear(&self) {
+        for shard in &self.shards {
+            shard.lock().clear();
+        }
+    }
+}
 
 /// Signature computer for log pattern analysis
 pub struct SignatureComputer {
-    patterns: Vec<(Regex, String)>,
+    patterns: Vec<NormalizationRule>,
+    hasher: Box<dyn SignatureHasher>,
+    json_shapes: bool,
+    min_normalization_len: usize,
+    cache: SignatureCache,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+/// Size and effectiveness of the message signature cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SignatureCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl SignatureCacheStats {
+    /// Share of lookups answered from the cache
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// A named regex replacement applied during normalization
+struct NormalizationRule {
+    name: &'static str,
+    regex: Regex,
+    replacement: &'static str,
+}
+
+impl NormalizationRule {
+    fn new(name: &'static str, pattern: &str, replacement: &'static str) -> Self {
+        Self {
+            name,
+            regex: Regex::new(pattern).unwrap(),
+            replacement,
+        }
+    }
+}
+
+/// How a message was turned into its signature, for tuning pattern rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureExplanation {
+    /// Sanitized, lowercased message before any rule applied
+    pub input: String,
+    /// Rules that changed the text, in application order
+    pub steps: Vec<NormalizationStep>,
+    /// Text after every rule applied
+    pub normalized: String,
+    /// Final signature hash
+    pub signature: String,
+}
+
+/// One normalization rule that changed the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizationStep {
+    pub rule: &'static str,
+    pub before: String,
+    pub after: String,
 }
 
 impl SignatureComputer {
     /// Create a new signature computer
     pub fn new() -> Self {
+        Self::with_hasher(Box::new(Md5Hasher))
+    }
+
+    /// Create a signature computer using a custom hasher
+    ///
+    /// The rule order is part of every signature: persisted pattern state, allow and
+    /// block lists and backend pattern rates are keyed by it, so don't reorder rules.
+    pub fn with_hasher(hasher: Box<dyn SignatureHasher>) -> Self {
         let patterns = vec![
-            (Regex::new(r"\b\d+\b").unwrap(), "N".to_string()),
-            (Regex::new(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}").unwrap(), "UUID".to_string()),
-            (Regex::new(r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}").unwrap(), "TIMESTAMP".to_string()),
-            (Regex::new(r"\b(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b").unwrap(), "IP".to_string()),
-            (Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Z|a-z]{2,}\b").unwrap(), "EMAIL".to_string()),
-            (Regex::new(r"https?://[^\s]+").unwrap(), "URL".to_string()),
+            NormalizationRule::new("NUMBER", r"\b\d+\b", "N"),
+            NormalizationRule::new("UUID", r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}", "UUID"),
+            NormalizationRule::new("TIMESTAMP", r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}", "TIMESTAMP"),
+            NormalizationRule::new("IP", r"\b(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b", "IP"),
+            NormalizationRule::new("EMAIL", r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Z|a-z]{2,}\b", "EMAIL"),
+            NormalizationRule::new("URL", URL_PATTERN, "URL"),
+        ];
+
+        Self {
+            patterns,
+            hasher,
+            json_shapes: false,
+            min_normalization_len: 0,
+            cache: SignatureCache::new(SIGNATURE_CACHE_CAPACITY),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep URL and path structure in signatures while collapsing dynamic parts
+    ///
+    /// Query strings and fragments are stripped, hex/UUID path segments become `ID`,
+    /// numeric segments become `N` and URL hosts become `URL`, so
+    /// `https://api.example.com/users/123?token=abc` normalizes to `URL/users/N`.
+    pub fn with_path_normalization(mut self) -> Self {
+        let mut patterns = vec![
+            NormalizationRule::new("QUERY", r"(/[^\s?#]*)[?#]\S*", "$1"),
+            NormalizationRule::new("PATH_ID", r"/[0-9a-f]{8,}(?:-[0-9a-f]{4,})*\b", "/ID"),
         ];
 
-        Self { patterns }
+        for rule in self.patterns.drain(..) {
+            if rule.name == "URL" {
+                patterns.push(NormalizationRule::new("URL_HOST", r"https?://[^\s/?#]+", "URL"));
+            } else {
+                patterns.push(rule);
+            }
+        }
+
+        self.patterns = patterns;
+        self.cache_clear();
+        self
+    }
+
+    /// Collapse absolute Unix and Windows file paths to `PATH`, keeping the extension
+    ///
+    /// `/home/alice/app/123.log` and `C:\Users\bob\app.log` both normalize to
+    /// `PATH.log`. Paths must start a token, so URLs are left to the URL rules, but
+    /// HTTP routes with two or more segments collapse too.
+    pub fn with_file_path_normalization(mut self) -> Self {
+        let number = self.patterns.iter().position(|rule| rule.name == "NUMBER").unwrap_or(self.patterns.len());
+        self.patterns.splice(
+            number..number,
+            [
+                NormalizationRule::new("UNIX_PATH", UNIX_FILE_PATH_PATTERN, "${1}PATH${2}${3}"),
+                NormalizationRule::new("WINDOWS_PATH", WINDOWS_FILE_PATH_PATTERN, "${1}PATH${2}${3}"),
+            ],
+        );
+        self.cache_clear();
+        self
+    }
+
+    /// Also collapse digits embedded in identifiers, so `worker5` and `worker42`
+    /// both normalize to `workerN`
+    pub fn with_aggressive_numbers(mut self) -> Self {
+        for rule in &mut self.patterns {
+            if rule.name == "NUMBER" {
+                *rule = NormalizationRule::new("NUMBER", r"\d+", "N");
+            }
+        }
+        self.cache_clear();
+        self
+    }
+
+    /// Replace the values of JSON objects and arrays embedded in messages with type
+    /// placeholders, so payloads with the same keys share a signature
+    pub fn with_json_normalization(mut self) -> Self {
+        self.json_shapes = true;
+        self.cache_clear();
+        self
+    }
+
+    /// Only sanitize and lowercase trimmed messages shorter than `len` characters,
+    /// skipping the normalization rules that cannot find anything worth collapsing in them
+    pub fn with_min_normalization_length(mut self, len: usize) -> Self {
+        self.min_normalization_len = len;
+        self.cache_clear();
+        self
+    }
+
+    /// Cache the signatures of up to about `capacity` recent messages; 0 disables the cache
+    ///
+    /// The cache is keyed by the raw message, so it only pays off for messages repeated
+    /// verbatim. Messages over 1 KiB are never cached.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = SignatureCache::new(capacity);
+        self
+    }
+
+    /// Sanitized, lowercased message if it is too short to be normalized
+    fn below_normalization_length(&self, message: &str) -> Option<String> {
+        let trimmed = message.trim();
+        (trimmed.chars().count() < self.min_normalization_len).then(|| prepare(trimmed))
     }
 
     /// Compute signature for a log message
     pub fn compute_signature(&self, message: &str) -> String {
-        let mut normalized = message.to_lowercase().trim().to_string();
+        if let Some(short) = self.below_normalization_length(message) {
+            return self.hasher.hash(&short);
+        }
+
+        let Some(shard) = self.cache.shard(message) else {
+            return self.hasher.hash(&self.normalize(message));
+        };
+        if let Some(signature) = shard.lock().get(message) {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return signature.clone();
+        }
+
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        let signature = self.hasher.hash(&self.normalize(message));
+        shard.lock().put(message.to_string(), signature.clone());
+        signature
+    }
+
+    /// Size and hit rate of the message signature cache
+    pub fn cache_stats(&self) -> SignatureCacheStats {
+        SignatureCacheStats {
+            entries: self.cache.len(),
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drop every cached signature and reset the hit counters, e.g. after the
+    /// normalization rules changed
+    pub fn cache_clear(&self) {
+        self.cache.clear();
+        self.cache_hits.store(0, Ordering::Relaxed);
+        self.cache_misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Compute signature for a log message refined by the normalized values of some fields
+    ///
+    /// Without values this is the same as [`SignatureComputer::compute_signature`].
+    pub fn compute_with_values(&self, message: &str, values: &[(&str, &str)]) -> String {
+        if values.is_empty() {
+            return self.compute_signature(message);
+        }
+
+        let mut values: Vec<String> = values
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, self.normalize(value)))
+            .collect();
+        values.sort_unstable();
+
+        self.hasher.hash(&format!("{}|{}", self.normalize(message), values.join(",")))
+    }
+
+    /// Sanitize and lowercase a value and replace its dynamic parts with placeholders
+    pub fn normalize(&self, value: &str) -> String {
+        let mut normalized = prepare(value);
+        if self.json_shapes {
+            normalized = json_shapes(&normalized);
+        }
 
         // Apply pattern replacements
-        for (pattern, replacement) in &self.patterns {
-            normalized = pattern.replace_all(&normalized, replacement).to_string();
+        for rule in &self.patterns {
+            normalized = rule.regex.replace_all(&normalized, rule.replacement).to_string();
         }
 
-        // Compute MD5 hash
-        let mut hasher = Md5::new();
-        hasher.update(normalized.as_bytes());
-        let result = hasher.finalize();
-        format!("{:x}", result)
+        normalized
+    }
+
+    /// Show which normalization rules fired for a message and the resulting signature
+    pub fn explain(&self, message: &str) -> SignatureExplanation {
+        if let Some(short) = self.below_normalization_length(message) {
+            return SignatureExplanation {
+                signature: self.hasher.hash(&short),
+                input: short.clone(),
+                steps: Vec::new(),
+                normalized: short,
+            };
+        }
+
+        let input = prepare(message);
+        let mut normalized = input.clone();
+        let mut steps = Vec::new();
+
+        if self.json_shapes {
+            let after = json_shapes(&normalized);
+            if after != normalized {
+                steps.push(NormalizationStep {
+                    rule: "JSON",
+                    before: std::mem::replace(&mut normalized, after.clone()),
+                    after,
+                });
+            }
+        }
+
+        for rule in &self.patterns {
+            let after = rule.regex.replace_all(&normalized, rule.replacement).to_string();
+            if after != normalized {
+                steps.push(NormalizationStep {
+                    rule: rule.name,
+                    before: std::mem::replace(&mut normalized, after.clone()),
+                    after,
+                });
+            }
+        }
+
+        SignatureExplanation {
+            signature: self.hasher.hash(&normalized),
+            input,
+            steps,
+            normalized,
+        }
+    }
+
+    /// Hash a value that is already a stable pattern, skipping normalization
+    pub fn hash_raw(&self, value: &str) -> String {
+        self.hasher.hash(value)
+    }
+
+    /// Compute signature from the shape of a structured event (its field names)
+    pub fn compute_from_fields(&self, keys: &[&str]) -> String {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+
+        self.hasher.hash(&keys.join(","))
+    }
+}
+
+/// Sanitize, lowercase and trim a value, as every normalization starts
+fn prepare(value: &str) -> String {
+    sanitize_message(value).to_lowercase().trim().to_string()
+}
+
+/// Replace every JSON object or array embedded in `text` with its shape
+fn json_shapes(text: &str) -> String {
+    let mut shaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c| c == '{' || c == '[') {
+        shaped.push_str(&rest[..start]);
+        let candidate = &rest[start..];
+        let mut values = serde_json::Deserializer::from_str(candidate).into_iter::<serde_json::Value>();
+        match values.next() {
+            Some(Ok(value)) => {
+                shaped.push_str(&json_shape(value).to_string());
+                rest = &candidate[values.byte_offset()..];
+            }
+            // Not JSON, e.g. "[worker]"; keep the bracket and look further
+            _ => {
+                shaped.push_str(&candidate[..1]);
+                rest = &candidate[1..];
+            }
+        }
+    }
+    shaped.push_str(rest);
+    shaped
+}
+
+/// A JSON value with its keys kept and its leaf values replaced by their type
+///
+/// Arrays collapse to the shape of their first item, so their length doesn't matter.
+fn json_shape(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(fields) => {
+            Value::Object(fields.into_iter().map(|(key, value)| (key, json_shape(value))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().take(1).map(json_shape).collect()),
+        Value::String(_) => Value::from("<string>"),
+        Value::Number(_) => Value::from("<number>"),
+        Value::Bool(_) => Value::from("<bool>"),
+        Value::Null => Value::from("<null>"),
     }
 }
 
@@ -86,6 +490,231 @@ mod tests {
         assert_eq!(sig3, sig4);
     }
 
+    #[test]
+    fn test_signature_cache_stats_and_clear() {
+        let computer = SignatureComputer::new();
+        let signature = computer.compute_signature("cache refreshed");
+        assert_eq!(computer.compute_signature("cache refreshed"), signature);
+        computer.compute_signature("user signed in");
+
+        let stats = computer.cache_stats();
+        assert_eq!(stats, SignatureCacheStats { entries: 2, hits: 1, misses: 2 });
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+        computer.cache_clear();
+        assert_eq!(computer.cache_stats(), SignatureCacheStats::default());
+        assert_eq!(computer.compute_signature("cache refreshed"), signature);
+
+        // Signatures cached under the old rules don't survive a reconfiguration
+        let worker = computer.compute_signature("worker5 started");
+        let computer = computer.with_aggressive_numbers();
+        assert_eq!(computer.cache_stats().entries, 0);
+        assert_ne!(computer.compute_signature("worker5 started"), worker);
+    }
+
+    #[test]
+    fn test_signature_cache_evicts_least_recently_used() {
+        let computer = SignatureComputer::new().with_cache_capacity(1);
+        computer.compute_signature("cache refreshed");
+        computer.compute_signature("user signed in");
+        computer.compute_signature("cache refreshed");
+        assert_eq!(computer.cache_stats(), SignatureCacheStats { entries: 1, hits: 0, misses: 3 });
+        computer.compute_signature("cache refreshed");
+        assert_eq!(computer.cache_stats().hits, 1);
+
+        // Long messages and disabled caches skip the cache entirely
+        computer.compute_signature(&"x".repeat(MAX_CACHED_MESSAGE_LEN + 1));
+        let disabled = SignatureComputer::new().with_cache_capacity(0);
+        disabled.compute_signature("cache refreshed");
+        assert_eq!(disabled.cache_stats(), SignatureCacheStats::default());
+        assert_eq!(computer.cache_stats(), SignatureCacheStats { entries: 1, hits: 1, misses: 3 });
+    }
+
+    #[test]
+    fn test_field_signature() {
+        let computer = SignatureComputer::new();
+
+        let sig1 = computer.compute_from_fields(&["user_id", "order_id", "amount"]);
+        let sig2 = computer.compute_from_fields(&["amount", "user_id", "order_id"]);
+        let sig3 = computer.compute_from_fields(&["user_id", "order_id"]);
+
+        // Same key set in any order shares a signature
+        assert_eq!(sig1, sig2);
+        assert_ne!(sig1, sig3);
+    }
+
+    #[test]
+    fn test_signature_field_values() {
+        let computer = SignatureComputer::new();
+
+        let failed = computer.compute_with_values("Payment processed", &[("status", "error")]);
+        let succeeded = computer.compute_with_values("Payment processed", &[("status", "ok")]);
+        assert_ne!(failed, succeeded);
+
+        // Values are normalized like messages
+        assert_eq!(
+            computer.compute_with_values("Payment processed", &[("code", "502")]),
+            computer.compute_with_values("Payment processed", &[("code", "503")])
+        );
+        assert_eq!(
+            computer.compute_with_values("Payment processed", &[]),
+            computer.compute_signature("Payment processed")
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let computer = SignatureComputer::new();
+
+        let explanation = computer.explain("user 5 from alice@example.com");
+        let rules: Vec<&str> = explanation.steps.iter().map(|step| step.rule).collect();
+        assert_eq!(rules, vec!["NUMBER", "EMAIL"]);
+        assert_eq!(explanation.steps[0].before, "user 5 from alice@example.com");
+        assert_eq!(explanation.steps[0].after, "user N from alice@example.com");
+        assert_eq!(explanation.normalized, "user N from EMAIL");
+        assert_eq!(explanation.signature, computer.compute_signature("user 5 from alice@example.com"));
+
+        // Numbers are replaced first, as they always were, keeping signatures stable
+        assert_eq!(computer.normalize("user 5 from 1.2.3.4"), "user N from N.N.N.N");
+    }
+
+    #[test]
+    fn test_custom_hasher() {
+        struct Uppercase;
+
+        impl SignatureHasher for Uppercase {
+            fn hash(&self, normalized: &str) -> String {
+                normalized.to_uppercase()
+            }
+        }
+
+        let computer = SignatureComputer::with_hasher(Box::new(Uppercase));
+
+        assert_eq!(computer.compute_signature("User 123 logged in"), "USER N LOGGED IN");
+        assert_eq!(computer.compute_from_fields(&["b", "a"]), "A,B");
+
+        // Control bytes never reach the hasher
+        assert_eq!(computer.compute_signature("User\0 123 logged\x07 in"), "USER\u{FFFD} N LOGGED\u{FFFD} IN");
+    }
+
+    #[test]
+    fn test_path_normalization() {
+        let computer = SignatureComputer::new().with_path_normalization();
+
+        let users_a = computer.compute_signature("GET /users/123?token=abc");
+        let users_b = computer.compute_signature("GET /users/456?token=def");
+        let orders = computer.compute_signature("GET /orders/123");
+        assert_eq!(users_a, users_b);
+        assert_ne!(users_a, orders);
+
+        let url_a = computer.compute_signature("Fetched https://api.example.com/users/1?page=2");
+        let url_b = computer.compute_signature("Fetched https://api.example.com/orders/1");
+        assert_ne!(url_a, url_b);
+
+        // Without the option whole URLs collapse to the same token
+        let computer = SignatureComputer::new();
+        assert_eq!(
+            computer.compute_signature("Fetched https://api.example.com/users/1?page=2"),
+            computer.compute_signature("Fetched https://api.example.com/orders/1")
+        );
+    }
+
+    #[test]
+    fn test_json_normalization() {
+        let computer = SignatureComputer::new().with_json_normalization();
+
+        let order_a = computer.compute_signature(r#"Order created {"id": 17, "items": ["book"], "paid": true}"#);
+        let order_b = computer.compute_signature(r#"Order created {"paid": false, "id": 9, "items": ["pen"]}"#);
+        let refund = computer.compute_signature(r#"Order created {"id": 17, "refund": 3.5}"#);
+        assert_eq!(order_a, order_b);
+        assert_ne!(order_a, refund);
+
+        // Arrays group by their first item's shape whatever their length
+        let three_items =
+            computer.compute_signature(r#"Order created {"id": 4, "items": ["a", "b", "c"], "paid": true}"#);
+        assert_eq!(order_a, three_items);
+        assert_eq!(computer.normalize(r#"batch [{"id": 1}, {"id": 2}]"#), r#"batch [{"id":"<number>"}]"#);
+        assert_ne!(order_a, computer.compute_signature(r#"Order created {"id": 4, "items": [], "paid": true}"#));
+
+        // Brackets that aren't JSON are left alone
+        assert_eq!(computer.normalize("[worker] started {"), "[worker] started {");
+
+        // Without the option values still split signatures
+        let computer = SignatureComputer::new();
+        assert_ne!(
+            computer.compute_signature(r#"Order created {"id": 1, "sku": "a"}"#),
+            computer.compute_signature(r#"Order created {"id": 1, "sku": "b"}"#)
+        );
+    }
+
+    #[test]
+    fn test_file_path_normalization() {
+        let computer = SignatureComputer::new().with_file_path_normalization();
+
+        assert_eq!(
+            computer.compute_signature("Failed to open /home/alice/app/123.log: permission denied"),
+            computer.compute_signature("Failed to open /var/lib/bob/456.log: permission denied")
+        );
+        assert_eq!(
+            computer.compute_signature(r"Loaded C:\Users\alice\AppData\settings.json"),
+            computer.compute_signature(r"Loaded D:\profiles\bob\settings.json")
+        );
+        assert_eq!(
+            computer.normalize("Failed to open /home/alice/app/123.log: denied"),
+            "failed to open PATH.log: denied"
+        );
+
+        // The extension still tells files apart, and URLs keep their own rule
+        assert_ne!(
+            computer.compute_signature("Failed to open /home/alice/report.csv"),
+            computer.compute_signature("Failed to open /home/alice/report.pdf")
+        );
+        assert_eq!(computer.normalize("fetched https://example.com/a/b"), "fetched URL");
+    }
+
+    #[test]
+    fn test_short_messages_skip_normalization() {
+        let computer = SignatureComputer::new().with_min_normalization_length(8);
+
+        // Short messages are hashed as they are, so digits still tell them apart
+        assert_ne!(computer.compute_signature("ok 1"), computer.compute_signature("ok 2"));
+        assert_eq!(computer.compute_signature(" ok 1 "), computer.compute_signature("ok 1"));
+        assert_eq!(computer.compute_signature("ok 1"), computer.hash_raw("ok 1"));
+        assert_eq!(computer.compute_signature("OK 1"), computer.compute_signature("ok 1"));
+        assert!(computer.explain("ok 1").steps.is_empty());
+
+        // Control bytes never reach the hasher, however short the message
+        assert_eq!(computer.compute_signature("ok\0 1\x07"), computer.hash_raw("ok\u{FFFD} 1\u{FFFD}"));
+        assert_eq!(computer.explain("ok\0 1").input, "ok\u{FFFD} 1");
+        assert_eq!(computer.cache_stats().entries, 0);
+
+        // Longer messages are normalized as before
+        assert_eq!(
+            computer.compute_signature("User 123 logged in"),
+            computer.compute_signature("User 456 logged in")
+        );
+    }
+
+    #[test]
+    fn test_aggressive_number_normalization() {
+        let conservative = SignatureComputer::new();
+        let aggressive = SignatureComputer::new().with_aggressive_numbers();
+
+        assert_ne!(
+            conservative.compute_signature("worker5 restarted"),
+            conservative.compute_signature("worker42 restarted")
+        );
+        assert_eq!(
+            aggressive.compute_signature("worker5 restarted"),
+            aggressive.compute_signature("worker42 restarted")
+        );
+        assert_eq!(
+            aggressive.compute_signature("pod-7 evicted"),
+            aggressive.compute_signature("pod-42 evicted")
+        );
+        assert_eq!(aggressive.normalize("worker5 on pod-7"), "workerN on pod-N");
+    }
+
     #[test]
     fn test_uuid_normalization() {
         let computer = SignatureComputer::new();
@@ -0,0 +1,43 @@
+//! Callback sink module
+//!
+//! This module hands every log that passes sampling to an application callback, for
+//! routing sampled logs in-process without writing an exporter.
+
+use crate::severity::Severity;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A log that passed sampling
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampledLog {
+    pub body: String,
+    pub severity: Severity,
+    pub timestamp: SystemTime,
+    /// Attributes as they are exported, including inherited span fields
+    pub attributes: Vec<(String, String)>,
+    /// Pattern signature the log was sampled under
+    pub signature: String,
+}
+
+/// Callback invoked with every sampled log, see [`crate::Config::on_sampled_log`]
+#[derive(Clone)]
+pub struct SampledLogCallback(Arc<dyn Fn(&SampledLog) + Send + Sync>);
+
+impl SampledLogCallback {
+    /// Wrap a callback
+    pub fn new(callback: impl Fn(&SampledLog) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Invoke the callback with a sampled log
+    pub fn call(&self, log: &SampledLog) {
+        (self.0)(log)
+    }
+}
+
+impl fmt::Debug for SampledLogCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SampledLogCallback(..)")
+    }
+}
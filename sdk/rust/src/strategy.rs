@@ -0,0 +1,333 @@
+//! Sampling strategy module
+//!
+//! This module holds the algorithms that turn a pattern's sampling rate into a
+//! keep/drop decision, selected through `Config::sampling_strategy`.
+
+use crate::config::SamplingStrategy;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of slots a sliding window is divided into
+const WINDOW_SLOTS: u32 = 10;
+
+/// Patterns tracked before idle per-pattern state is first pruned
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Turns a sampling rate into a keep/drop decision for one log
+pub trait SamplingAlgorithm: Send + Sync {
+    /// Decide whether to keep a log of `pattern` sampled at `rate`
+    ///
+    /// `sample_key` is the value of `Config::consistent_sampling_field`, if the log
+    /// carries it.
+    fn decide(&self, pattern: &str, rate: f64, sample_key: Option<&str>) -> bool;
+
+    /// Whether kept logs were kept with probability `rate`, so their count can be
+    /// scaled by `1 / rate` to estimate the true volume
+    fn applies_rate(&self) -> bool {
+        true
+    }
+}
+
+impl SamplingStrategy {
+    /// Create the algorithm implementing this strategy
+    ///
+    /// `salt` is mixed into per-pattern hashes only, not into sample key hashes.
+    pub fn build(&self, seed: u64, salt: &str) -> Box<dyn SamplingAlgorithm> {
+        match *self {
+            Self::Probabilistic => Box::new(Probabilistic { seed }),
+            Self::DeterministicHash => Box::new(DeterministicHash {
+                seed,
+                salt: salt.to_string(),
+                occurrences: DashMap::new(),
+            }),
+            Self::SlidingWindow { window } => Box::new(SlidingWindow {
+                seed,
+                slot: (window / WINDOW_SLOTS).max(Duration::from_millis(1)),
+                slots: DashMap::new(),
+                prune_at: AtomicUsize::new(PRUNE_THRESHOLD),
+            }),
+            Self::LeakyBucket { capacity, drain_per_sec } => Box::new(LeakyBucket {
+                rate_filter: Probabilistic { seed },
+                capacity: capacity as f64,
+                drain_per_sec,
+                buckets: DashMap::new(),
+                prune_at: AtomicUsize::new(PRUNE_THRESHOLD),
+            }),
+        }
+    }
+}
+
+/// Whether `key` falls in the kept `rate` of keys hashed with `seed`
+///
+/// Identical across processes, so samplers sharing the seed agree on every key.
+pub(crate) fn key_kept(seed: u64, key: &str, rate: f64) -> bool {
+    below_rate(keyed_hash(seed, key), rate)
+}
+
+/// Hash of `key` mixed with the sampling seed, identical across processes
+fn keyed_hash(seed: u64, key: &str) -> u64 {
+    let digest = md5::compute(format!("{}:{}", seed, key));
+    u64::from_be_bytes(digest.0[..8].try_into().expect("md5 digest is 16 bytes"))
+}
+
+/// Whether a uniformly distributed hash falls in the kept fraction
+fn below_rate(hash: u64, rate: f64) -> bool {
+    (hash % 10000) < (rate * 10000.0) as u64
+}
+
+/// Drop idle entries once a per-pattern map outgrows its pruning threshold
+///
+/// Only entries `idle` reports as equivalent to fresh state are removed, so pruning
+/// never changes a decision. The threshold then doubles from what is left, keeping
+/// pruning amortized when most patterns are active.
+fn prune_idle<V>(map: &DashMap<String, V>, prune_at: &AtomicUsize, idle: impl Fn(&V) -> bool) {
+    if map.len() < prune_at.load(Ordering::Relaxed) {
+        return;
+    }
+    map.retain(|_, value| !idle(value));
+    prune_at.store((map.len() * 2).max(PRUNE_THRESHOLD), Ordering::Relaxed);
+}
+
+/// Keeps each log independently with probability `rate`
+///
+/// Logs carrying a sample key are decided on the key instead, so every sampler
+/// sharing the seed agrees on them.
+struct Probabilistic {
+    seed: u64,
+}
+
+impl SamplingAlgorithm for Probabilistic {
+    fn decide(&self, _pattern: &str, rate: f64, sample_key: Option<&str>) -> bool {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let hash = match sample_key {
+            Some(key) => keyed_hash(self.seed, key),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos().hash(&mut hasher);
+                hasher.finish()
+            }
+        };
+
+        below_rate(hash, rate)
+    }
+}
+
+/// Decides on a hash of the seed and the log's sample key, or of the salt, its
+/// pattern and occurrence number, so replaying the same logs always yields the same
+/// decisions
+struct DeterministicHash {
+    seed: u64,
+    salt: String,
+    occurrences: DashMap<String, u64>,
+}
+
+impl SamplingAlgorithm for DeterministicHash {
+    fn decide(&self, pattern: &str, rate: f64, sample_key: Option<&str>) -> bool {
+        let hash = match sample_key {
+            Some(key) => keyed_hash(self.seed, key),
+            None => {
+                let mut occurrence = self.occurrences.entry(pattern.to_string()).or_insert(0);
+                *occurrence += 1;
+                keyed_hash(self.seed, &format!("{}:{}#{}", self.salt, pattern, *occurrence))
+            }
+        };
+
+        below_rate(hash, rate)
+    }
+}
+
+/// Keeps exactly `rate` of each pattern's logs over a trailing time window
+///
+/// A log is kept while the kept fraction of its pattern within the window is below
+/// the rate, so the kept share never drifts the way random sampling can. Logs
+/// carrying a sample key are decided on the key, like the other algorithms, and
+/// still count towards their pattern's window.
+struct SlidingWindow {
+    seed: u64,
+    slot: Duration,
+    slots: DashMap<String, VecDeque<WindowSlot>>,
+    prune_at: AtomicUsize,
+}
+
+/// Seen and kept counts of one slice of a sliding window
+struct WindowSlot {
+    started: Instant,
+    seen: u64,
+    kept: u64,
+}
+
+impl SamplingAlgorithm for SlidingWindow {
+    /// The kept share follows the rate, but whether a given log is kept depends on
+    /// the logs before it
+    fn applies_rate(&self) -> bool {
+        false
+    }
+
+    fn decide(&self, pattern: &str, rate: f64, sample_key: Option<&str>) -> bool {
+        let now = Instant::now();
+        let window = self.slot * WINDOW_SLOTS;
+        prune_idle(&self.slots, &self.prune_at, |slots| {
+            slots.back().map_or(true, |slot| now.duration_since(slot.started) >= window)
+        });
+        let mut slots = self.slots.entry(pattern.to_string()).or_default();
+
+        while slots.front().is_some_and(|slot| now.duration_since(slot.started) >= window) {
+            slots.pop_front();
+        }
+        if slots.back().map_or(true, |slot| now.duration_since(slot.started) >= self.slot) {
+            slots.push_back(WindowSlot {
+                started: now,
+                seen: 0,
+                kept: 0,
+            });
+        }
+
+        let (seen, kept) = slots.iter().fold((0, 0), |(seen, kept), slot| (seen + slot.seen, kept + slot.kept));
+        let keep = match sample_key {
+            Some(key) => key_kept(self.seed, key, rate),
+            None => (kept as f64) < rate * (seen + 1) as f64,
+        };
+
+        let current = slots.back_mut().expect("a slot was just ensured");
+        current.seen += 1;
+        current.kept += keep as u64;
+        keep
+    }
+}
+
+/// Samples each pattern at its rate, then lets the kept logs through in bursts of at
+/// most `capacity`, draining at `drain_per_sec`; logs that would overflow the bucket
+/// are dropped
+struct LeakyBucket {
+    rate_filter: Probabilistic,
+    capacity: f64,
+    drain_per_sec: f64,
+    buckets: DashMap<String, (f64, Instant)>,
+    prune_at: AtomicUsize,
+}
+
+impl SamplingAlgorithm for LeakyBucket {
+    /// The bucket drops kept logs beyond its capacity, whatever the rate
+    fn applies_rate(&self) -> bool {
+        false
+    }
+
+    fn decide(&self, pattern: &str, rate: f64, sample_key: Option<&str>) -> bool {
+        if !self.rate_filter.decide(pattern, rate, sample_key) {
+            return false;
+        }
+
+        let now = Instant::now();
+        prune_idle(&self.buckets, &self.prune_at, |(level, drained_at)| {
+            *level <= now.duration_since(*drained_at).as_secs_f64() * self.drain_per_sec
+        });
+        let mut bucket = self.buckets.entry(pattern.to_string()).or_insert((0.0, now));
+        let (level, drained_at) = &mut *bucket;
+
+        *level = (*level - now.duration_since(*drained_at).as_secs_f64() * self.drain_per_sec).max(0.0);
+        *drained_at = now;
+
+        if *level + 1.0 > self.capacity {
+            return false;
+        }
+        *level += 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probabilistic() {
+        let algorithm = SamplingStrategy::Probabilistic.build(7, "");
+        assert!((0..100).all(|_| algorithm.decide("pattern", 1.0, None)));
+        assert!((0..100).all(|_| !algorithm.decide("pattern", 0.0, None)));
+
+        let kept = (0..2000).filter(|_| algorithm.decide("pattern", 0.5, None)).count();
+        assert!(kept > 800 && kept < 1200);
+    }
+
+    #[test]
+    fn test_deterministic_hash_replays_identically() {
+        let first = SamplingStrategy::DeterministicHash.build(7, "checkout");
+        let second = SamplingStrategy::DeterministicHash.build(7, "checkout");
+
+        let decisions = |algorithm: &dyn SamplingAlgorithm| -> Vec<bool> {
+            (0..500).map(|_| algorithm.decide("pattern", 0.3, None)).collect()
+        };
+        let replayed = decisions(first.as_ref());
+        assert_eq!(replayed, decisions(second.as_ref()));
+
+        let kept = replayed.iter().filter(|kept| **kept).count();
+        assert!(kept > 100 && kept < 200);
+    }
+
+    #[test]
+    fn test_sliding_window_keeps_exact_fraction() {
+        let algorithm = SamplingStrategy::SlidingWindow {
+            window: Duration::from_secs(60),
+        }
+        .build(0, "");
+
+        let kept = (0..100).filter(|_| algorithm.decide("pattern", 0.25, None)).count();
+        assert_eq!(kept, 25);
+
+        // Each pattern gets its own window
+        assert!(algorithm.decide("other pattern", 0.25, None));
+    }
+
+    #[test]
+    fn test_leaky_bucket_limits_bursts() {
+        let algorithm = SamplingStrategy::LeakyBucket {
+            capacity: 5,
+            drain_per_sec: 100.0,
+        }
+        .build(0, "");
+
+        let kept = (0..50).filter(|_| algorithm.decide("pattern", 1.0, None)).count();
+        assert_eq!(kept, 5);
+
+        // The bucket drains over time and admits logs again
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(algorithm.decide("pattern", 1.0, None));
+
+        // The rate still applies before the bucket
+        assert!((0..50).all(|_| !algorithm.decide("dropped pattern", 0.0, None)));
+    }
+
+    #[test]
+    fn test_sliding_window_decides_on_sample_key() {
+        let algorithm = SamplingStrategy::SlidingWindow {
+            window: Duration::from_secs(60),
+        }
+        .build(7, "");
+
+        for i in 0..200 {
+            let key = format!("trace-{}", i);
+            assert_eq!(algorithm.decide("pattern", 0.3, Some(&key)), key_kept(7, &key, 0.3));
+        }
+    }
+
+    #[test]
+    fn test_idle_pattern_state_is_pruned() {
+        let algorithm = SlidingWindow {
+            seed: 0,
+            slot: Duration::from_millis(1),
+            slots: DashMap::new(),
+            prune_at: AtomicUsize::new(PRUNE_THRESHOLD),
+        };
+        for i in 0..PRUNE_THRESHOLD {
+            algorithm.decide(&format!("pattern {}", i), 0.5, None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+
+        algorithm.decide("fresh pattern", 0.5, None);
+        assert_eq!(algorithm.slots.len(), 1);
+    }
+}